@@ -0,0 +1,80 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::clock;
+
+declare_id!("BcP5PbQo6Hj5BpTth3dBkdnQqKZyAFtxkibWtPurSTb1");
+
+// Must match the feed layout read by the loans program, which ignores the trailing authority
+pub const FEED_MAGIC: u32 = 0x464c_4f52;
+pub const FEED_VERSION: u32 = 1;
+
+// Stand-in for a Pyth/Switchboard floor price feed, only meant for local tests.
+#[program]
+pub mod mock_oracle {
+    use super::*;
+
+    pub fn set_price(
+        ctx: Context<SetPrice>,
+        collection: Pubkey,
+        price: i64,
+        conf: u64,
+        expo: i32,
+        publish_time: i64,
+    ) -> Result<()> {
+        let authority = ctx.accounts.authority.key();
+        let mut data = ctx.accounts.feed.try_borrow_mut_data()?;
+        // the first write claims the feed for its signer
+        if let Ok(current) = Feed::deserialize(&mut &data[..]) {
+            if current.magic == FEED_MAGIC && current.authority != authority {
+                return Err(ErrorCode::Unauthorized.into());
+            }
+        }
+
+        let feed = Feed {
+            magic: FEED_MAGIC,
+            version: FEED_VERSION,
+            collection,
+            price,
+            conf,
+            expo,
+            // 0 publishes at the current time
+            publish_time: if publish_time == 0 {
+                clock::Clock::get()?.unix_timestamp
+            } else {
+                publish_time
+            },
+            authority,
+        };
+
+        let mut writer: &mut [u8] = &mut data;
+        feed.serialize(&mut writer)?;
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+pub struct SetPrice<'info> {
+    /// CHECK: raw feed account, created by the client and assigned to this program
+    #[account(mut)]
+    pub feed: UncheckedAccount<'info>,
+
+    // only this signer can update the feed once it is written
+    pub authority: Signer<'info>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct Feed {
+    pub magic: u32,
+    pub version: u32,
+    pub collection: Pubkey,
+    pub price: i64,
+    pub conf: u64,
+    pub expo: i32,
+    pub publish_time: i64,
+    pub authority: Pubkey,
+}
+
+#[error_code]
+pub enum ErrorCode {
+    #[msg("Feed belongs to another authority")]
+    Unauthorized,
+}
@@ -1,35 +1,221 @@
 use anchor_lang::prelude::*;
 use anchor_spl::token::{self, TokenAccount, Token, Mint};
-use anchor_lang::solana_program::{clock};
+use anchor_lang::solana_program::{clock, program::{invoke, invoke_signed}, system_instruction};
+
+pub mod math;
+pub mod metadata;
+pub mod oracle;
 
 pub mod constants {
     pub const CONFIG_PDA_SEED: &[u8] = b"config";
     pub const STABLE_COIN_PDA_SEED: &[u8] = b"stable";
     pub const NFT_PDA_SEED: &[u8] = b"nft";
     pub const ORDER_PDA_SEED: &[u8] = b"order";
+    pub const MARKET_PDA_SEED: &[u8] = b"market";
+    pub const OFFER_PDA_SEED: &[u8] = b"offer";
+    pub const COLLECTION_OFFER_PDA_SEED: &[u8] = b"collection_offer";
+    pub const WHITELIST_PDA_SEED: &[u8] = b"whitelist";
+    pub const TREASURY_PDA_SEED: &[u8] = b"treasury";
+    pub const RECEIPT_PDA_SEED: &[u8] = b"receipt";
+    pub const BID_ESCROW_PDA_SEED: &[u8] = b"bid_escrow";
+    // denominator for rates expressed in basis points
+    pub const BPS_DENOMINATOR: u64 = 10_000;
+    // number of repayments kept in the history of an order
+    pub const REPAYMENT_HISTORY_LEN: usize = 8;
 }
 
 declare_id!("qXdGuL6mPUatQNGHRsLZQRyZADm2QKxddhpYz24PaRn");
 
 pub mod token_constants {
-    // Devnet StableCoin
-    pub const USDC_MINT_PUBKEY: &str = "Gh9ZwEmdLJ8DscKNTkTqPbNwLNNBjuSzaG9Vp2KGtKJr";
-    // Localnet StableCoin
-    //pub const USDC_MINT_PUBKEY: &str = "G7EY516o2hAWDxQ3g8Z9tSCh5gdkhp5Sz7WhHNFQ9kqA";
+    // Wrapped SOL mint, markets on this mint can lend native SOL
+    pub const NATIVE_MINT_PUBKEY: &str = "So11111111111111111111111111111111111111112";
+    // Decimals of native SOL
+    pub const NATIVE_DECIMALS: u8 = 9;
 }
 
 #[program]
 pub mod solana_nft_collaterized_loans {
-    use std::time::Duration;
     use super::*;
 
-    pub fn initialize(ctx: Context<Initialize>, _config_nonce: u8, _stable_nonce: u8) -> Result<()> {
+    pub fn initialize(
+        ctx: Context<Initialize>,
+        _config_nonce: u8,
+        min_period: u64,
+        max_period: u64,
+        max_interest_rate: u64,
+        min_collateral_ratio: u64,
+        require_verified_collection: bool,
+        require_whitelist: bool,
+        protocol_fee_rate: u64,
+        origination_fee_rate: u64,
+        min_interest_period: u64,
+        grace_period: u64,
+        late_fee_rate: u64,
+        keep_receipts: bool,
+        order_lifetime: u64,
+        auction_start_multiplier: u64,
+        auction_duration: u64,
+        bid_increment_rate: u64,
+        anti_snipe_period: u64,
+        oracle_program: Pubkey,
+        max_ltv: u64,
+        max_price_age: u64,
+        maintenance_ltv: u64,
+    ) -> Result<()> {
         let config = &mut ctx.accounts.configuration;
-        config.stable_coin_mint = ctx.accounts.stable_coin_mint.key();
-        config.stable_coin_vault = ctx.accounts.stable_coin_vault.key();
+        config.admin = ctx.accounts.signer.key();
         config.order_id = 0;
-        config.total_additional_collateral = 0;
+        config.offer_id = 0;
+        config.min_period = min_period;
+        config.max_period = max_period;
+        config.max_interest_rate = max_interest_rate;
+        config.min_collateral_ratio = min_collateral_ratio;
+        config.require_verified_collection = require_verified_collection;
+        config.require_whitelist = require_whitelist;
+        config.protocol_fee_rate = protocol_fee_rate;
+        config.origination_fee_rate = origination_fee_rate;
+        config.min_interest_period = min_interest_period;
+        config.grace_period = grace_period;
+        config.late_fee_rate = late_fee_rate;
+        config.keep_receipts = keep_receipts;
+        config.order_lifetime = order_lifetime;
+        config.auction_start_multiplier = auction_start_multiplier;
+        config.auction_duration = auction_duration;
+        config.bid_increment_rate = bid_increment_rate;
+        config.anti_snipe_period = anti_snipe_period;
+        config.oracle_program = oracle_program;
+        config.max_ltv = max_ltv;
+        config.max_price_age = max_price_age;
+        config.maintenance_ltv = maintenance_ltv;
         config.nonce = _config_nonce;
+        config.validate()?;
+
+        Ok(())
+    }
+
+    pub fn update_config(
+        ctx: Context<UpdateConfig>,
+        new_admin: Pubkey,
+        min_period: u64,
+        max_period: u64,
+        max_interest_rate: u64,
+        min_collateral_ratio: u64,
+        require_verified_collection: bool,
+        require_whitelist: bool,
+        protocol_fee_rate: u64,
+        origination_fee_rate: u64,
+        min_interest_period: u64,
+        grace_period: u64,
+        late_fee_rate: u64,
+        keep_receipts: bool,
+        order_lifetime: u64,
+        auction_start_multiplier: u64,
+        auction_duration: u64,
+        bid_increment_rate: u64,
+        anti_snipe_period: u64,
+        oracle_program: Pubkey,
+        max_ltv: u64,
+        max_price_age: u64,
+        maintenance_ltv: u64,
+    ) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        config.admin = new_admin;
+        config.min_period = min_period;
+        config.max_period = max_period;
+        config.max_interest_rate = max_interest_rate;
+        config.min_collateral_ratio = min_collateral_ratio;
+        config.require_verified_collection = require_verified_collection;
+        config.require_whitelist = require_whitelist;
+        config.protocol_fee_rate = protocol_fee_rate;
+        config.origination_fee_rate = origination_fee_rate;
+        config.min_interest_period = min_interest_period;
+        config.grace_period = grace_period;
+        config.late_fee_rate = late_fee_rate;
+        config.keep_receipts = keep_receipts;
+        config.order_lifetime = order_lifetime;
+        config.auction_start_multiplier = auction_start_multiplier;
+        config.auction_duration = auction_duration;
+        config.bid_increment_rate = bid_increment_rate;
+        config.anti_snipe_period = anti_snipe_period;
+        config.oracle_program = oracle_program;
+        config.max_ltv = max_ltv;
+        config.max_price_age = max_price_age;
+        config.maintenance_ltv = maintenance_ltv;
+        config.validate()?;
+
+        emit!(UpdatedConfigEvent {
+            config_key: *config.to_account_info().key,
+            admin: config.admin,
+            min_period,
+            max_period,
+            max_interest_rate,
+            min_collateral_ratio,
+            require_verified_collection,
+            require_whitelist,
+            protocol_fee_rate,
+            origination_fee_rate,
+            min_interest_period,
+            grace_period,
+            late_fee_rate,
+            keep_receipts,
+            order_lifetime,
+            auction_start_multiplier,
+            auction_duration,
+            bid_increment_rate,
+            anti_snipe_period,
+            oracle_program,
+            max_ltv,
+            max_price_age,
+            maintenance_ltv,
+        });
+
+        Ok(())
+    }
+
+    pub fn create_market(
+        ctx: Context<CreateMarket>,
+        _market_nonce: u8,
+        _stable_nonce: u8,
+        min_request_amount: u64,
+        max_request_amount: u64,
+    ) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        market.stable_coin_mint = ctx.accounts.stable_coin_mint.key();
+        market.stable_coin_vault = ctx.accounts.stable_coin_vault.key();
+        market.treasury_vault = ctx.accounts.treasury_vault.key();
+        market.min_request_amount = min_request_amount;
+        market.max_request_amount = max_request_amount;
+        market.total_additional_collateral = 0;
+        market.sol_treasury = 0;
+        market.nonce = _market_nonce;
+        market.validate()?;
+
+        emit!(CreatedMarketEvent {
+            market_key: *market.to_account_info().key,
+            stable_coin_mint: market.stable_coin_mint,
+            min_request_amount,
+            max_request_amount,
+        });
+
+        Ok(())
+    }
+
+    pub fn update_market(
+        ctx: Context<UpdateMarket>,
+        min_request_amount: u64,
+        max_request_amount: u64,
+    ) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        market.min_request_amount = min_request_amount;
+        market.max_request_amount = max_request_amount;
+        market.validate()?;
+
+        emit!(UpdatedMarketEvent {
+            market_key: *market.to_account_info().key,
+            stable_coin_mint: market.stable_coin_mint,
+            min_request_amount,
+            max_request_amount,
+        });
 
         Ok(())
     }
@@ -39,19 +225,619 @@ pub mod solana_nft_collaterized_loans {
         _stable_nonce: u8,
         _nft_nonce: u8,
         _order_nonce: u8,
+        request_amount: u64,
+        interest_rate: u64,
+        period: u64,
+        additional_collateral: u64,
+    ) -> Result<()> {
+        let collection = ctx.accounts.config.check_order(
+            &ctx.accounts.market,
+            &ctx.accounts.nft_metadata.to_account_info(),
+            &ctx.accounts.nft_mint.key(),
+            &ctx.accounts.collection_whitelist.to_account_info(),
+            request_amount,
+            interest_rate,
+            period,
+            additional_collateral,
+        )?;
+        // Transfer collateral to vault.
+        lock_nft(
+            &ctx.accounts.token_program,
+            ctx.accounts.user_nft_vault.to_account_info(),
+            &ctx.accounts.nft_vault,
+            ctx.accounts.borrower.to_account_info(),
+        )?;
+
+        let funds = Funds::Token {
+            token_program: &ctx.accounts.token_program,
+            stable_coin_mint: &ctx.accounts.stable_coin_mint,
+            stable_coin_vault: &ctx.accounts.stable_coin_vault,
+            stable_nonce: _stable_nonce,
+        };
+        open_order(
+            &funds,
+            &mut ctx.accounts.config,
+            &mut ctx.accounts.market,
+            &mut ctx.accounts.order,
+            ctx.accounts.borrower.to_account_info(),
+            ctx.accounts.user_stable_coin_vault.to_account_info(),
+            &ctx.accounts.nft_vault,
+            collection,
+            request_amount,
+            interest_rate,
+            period,
+            additional_collateral,
+            _order_nonce,
+        )
+    }
+
+    pub fn cancel_order(ctx: Context<CancelOrder>, _order_id: u64, _stable_nonce: u8, _nft_nonce: u8) -> Result<()> {
+        ctx.accounts.order.check_state(OrderState::Open)?;
+
+        // Transfer back the nft and the additional collateral.
+        let funds = Funds::Token {
+            token_program: &ctx.accounts.token_program,
+            stable_coin_mint: &ctx.accounts.stable_coin_mint,
+            stable_coin_vault: &ctx.accounts.stable_coin_vault,
+            stable_nonce: _stable_nonce,
+        };
+        release_collateral(
+            &funds,
+            &mut ctx.accounts.market,
+            &ctx.accounts.order,
+            &ctx.accounts.token_program,
+            &ctx.accounts.nft_mint,
+            &ctx.accounts.nft_vault,
+            ctx.accounts.user_nft_vault.to_account_info(),
+            ctx.accounts.user_stable_coin_vault.to_account_info(),
+            ctx.accounts.borrower.to_account_info(),
+            _nft_nonce,
+        )?;
+        let order = &mut ctx.accounts.order;
+        order.state = OrderState::Cancelled;
+        write_receipt(
+            &ctx.accounts.config,
+            &ctx.accounts.receipt,
+            ctx.accounts.borrower.to_account_info(),
+            &ctx.accounts.system_program,
+            order,
+            0,
+        )?;
+
+        emit!(CanceledOrderEvent {
+            order_key: *order.to_account_info().key,
+            borrower: *ctx.accounts.borrower.to_account_info().key,
+        });
+
+        Ok(())
+    }
+
+    pub fn give_loan(
+        ctx: Context<GiveLoan>,
+        _order_id: u64,
+        _stable_nonce: u8,
+        terms_version: u64,
+        expected_request_amount: u64,
+        expected_interest_rate: u64,
+        expected_period: u64,
+        expected_nft_mint: Pubkey,
+    ) -> Result<()> {
+        let now = clock::Clock::get().unwrap().unix_timestamp as u64;
+        ctx.accounts.order.check_fundable(
+            terms_version,
+            expected_request_amount,
+            expected_interest_rate,
+            expected_period,
+            &expected_nft_mint,
+            now,
+        )?;
+
+        // Send the loan to the borrower, withholding the origination fee
+        let funds = Funds::Token {
+            token_program: &ctx.accounts.token_program,
+            stable_coin_mint: &ctx.accounts.stable_coin_mint,
+            stable_coin_vault: &ctx.accounts.stable_coin_vault,
+            stable_nonce: _stable_nonce,
+        };
+        let origination_fee = fund_loan(
+            &funds,
+            &ctx.accounts.config,
+            &mut ctx.accounts.market,
+            &mut ctx.accounts.order,
+            ctx.accounts.lender.to_account_info(),
+            ctx.accounts.lender_stable_coin_vault.to_account_info(),
+            ctx.accounts.borrower_stable_coin_vault.to_account_info(),
+            ctx.accounts.treasury_vault.to_account_info(),
+            now,
+        )?;
+        let order = &mut ctx.accounts.order;
+        value_collateral(
+            &ctx.accounts.config,
+            &ctx.accounts.collection_whitelist,
+            &ctx.accounts.price_feed,
+            order,
+            ctx.accounts.stable_coin_mint.decimals,
+        )?;
+
+        emit!(LoanOrderEvent {
+            order_key: *order.to_account_info().key,
+            borrower: order.borrower,
+            lender: *ctx.accounts.lender.to_account_info().key,
+            origination_fee,
+        });
+        Ok(())
+    }
+
+    pub fn payback(ctx: Context<Payback>, _order_id: u64, _stable_nonce: u8, _nft_nonce: u8) -> Result<()> {
+        ctx.accounts.order.check_state(OrderState::Active)?;
+
+        let clock = clock::Clock::get().unwrap();
+        let now = clock.unix_timestamp as u64;
+        if ctx.accounts.order.liquidation_time()? < now {
+            return Err(ErrorCode::RepaymentPeriodExceeded.into());
+        }
+
+        // Pay Loan
+        let funds = Funds::Token {
+            token_program: &ctx.accounts.token_program,
+            stable_coin_mint: &ctx.accounts.stable_coin_mint,
+            stable_coin_vault: &ctx.accounts.stable_coin_vault,
+            stable_nonce: _stable_nonce,
+        };
+        let (interest, protocol_fee, late_fee) = repay_loan(
+            &funds,
+            &mut ctx.accounts.market,
+            &mut ctx.accounts.order,
+            ctx.accounts.borrower.to_account_info(),
+            ctx.accounts.borrower_stable_coin_vault.to_account_info(),
+            ctx.accounts.lender_stable_coin_vault.to_account_info(),
+            ctx.accounts.treasury_vault.to_account_info(),
+            now,
+        )?;
+
+        // Transfer back the nft and the additional collateral.
+        release_collateral(
+            &funds,
+            &mut ctx.accounts.market,
+            &ctx.accounts.order,
+            &ctx.accounts.token_program,
+            &ctx.accounts.nft_mint,
+            &ctx.accounts.nft_vault,
+            ctx.accounts.borrower_nft_vault.to_account_info(),
+            ctx.accounts.borrower_stable_coin_vault.to_account_info(),
+            ctx.accounts.borrower.to_account_info(),
+            _nft_nonce,
+        )?;
+        let order = &mut ctx.accounts.order;
+        order.state = OrderState::Repaid;
+        order.paid_back_at = now;
+        write_receipt(
+            &ctx.accounts.config,
+            &ctx.accounts.receipt,
+            ctx.accounts.borrower.to_account_info(),
+            &ctx.accounts.system_program,
+            order,
+            late_fee,
+        )?;
+
+        emit!(PayBackOrderEvent {
+            order_key: *order.to_account_info().key,
+            borrower: *ctx.accounts.borrower.to_account_info().key,
+            interest,
+            protocol_fee,
+            late_fee,
+        });
+
+        Ok(())
+    }
+
+    pub fn liquidate(ctx: Context<Liquidate>, _order_id: u64, _stable_nonce: u8, _nft_nonce: u8) -> Result<()> {
+        let clock = clock::Clock::get().unwrap();
+        let now = clock.unix_timestamp as u64;
+        let (trigger, loan_to_value) = if ctx.accounts.order.state == OrderState::Auction {
+            // English auctions are closed with settle_auction
+            if ctx.accounts.order.auction_kind != AuctionKind::Dutch {
+                return Err(ErrorCode::WrongAuctionKind.into());
+            }
+            // Nobody bought the nft, the lender takes the collateral
+            if ctx.accounts.order.auction_end_time > now {
+                return Err(ErrorCode::AuctionInProgress.into());
+            }
+            (LiquidationTrigger::MissedRepayment, 0)
+        } else {
+            liquidation_trigger(&ctx.accounts.config, &ctx.accounts.price_feed, &ctx.accounts.order, ctx.accounts.stable_coin_mint.decimals, now)?
+        };
+
+        // Transfer the nft and the additional collateral to the lender.
+        let funds = Funds::Token {
+            token_program: &ctx.accounts.token_program,
+            stable_coin_mint: &ctx.accounts.stable_coin_mint,
+            stable_coin_vault: &ctx.accounts.stable_coin_vault,
+            stable_nonce: _stable_nonce,
+        };
+        release_collateral(
+            &funds,
+            &mut ctx.accounts.market,
+            &ctx.accounts.order,
+            &ctx.accounts.token_program,
+            &ctx.accounts.nft_mint,
+            &ctx.accounts.nft_vault,
+            ctx.accounts.lender_nft_vault.to_account_info(),
+            ctx.accounts.lender_stable_coin_vault.to_account_info(),
+            ctx.accounts.borrower.to_account_info(),
+            _nft_nonce,
+        )?;
+        let order = &mut ctx.accounts.order;
+        order.state = OrderState::Liquidated;
+        order.withdrew_at = now;
+        write_receipt(
+            &ctx.accounts.config,
+            &ctx.accounts.receipt,
+            ctx.accounts.lender.to_account_info(),
+            &ctx.accounts.system_program,
+            order,
+            0,
+        )?;
+
+        emit!(LiquidityOrderEvent {
+            order_key: *order.to_account_info().key,
+            borrower: *ctx.accounts.borrower.to_account_info().key,
+            lender: *ctx.accounts.lender.to_account_info().key,
+            trigger,
+            loan_to_value,
+        });
+        Ok(())
+    }
+
+    pub fn create_sol_order(
+        ctx: Context<CreateSolOrder>,
+        _nft_nonce: u8,
+        _order_nonce: u8,
+        request_amount: u64,
+        interest_rate: u64,
+        period: u64,
+        additional_collateral: u64,
+    ) -> Result<()> {
+        let collection = ctx.accounts.config.check_order(
+            &ctx.accounts.market,
+            &ctx.accounts.nft_metadata.to_account_info(),
+            &ctx.accounts.nft_mint.key(),
+            &ctx.accounts.collection_whitelist.to_account_info(),
+            request_amount,
+            interest_rate,
+            period,
+            additional_collateral,
+        )?;
+        // Transfer collateral to vault.
+        lock_nft(
+            &ctx.accounts.token_program,
+            ctx.accounts.user_nft_vault.to_account_info(),
+            &ctx.accounts.nft_vault,
+            ctx.accounts.borrower.to_account_info(),
+        )?;
+
+        let funds = Funds::Native {
+            system_program: &ctx.accounts.system_program,
+            order: ctx.accounts.order.to_account_info(),
+        };
+        open_order(
+            &funds,
+            &mut ctx.accounts.config,
+            &mut ctx.accounts.market,
+            &mut ctx.accounts.order,
+            ctx.accounts.borrower.to_account_info(),
+            ctx.accounts.borrower.to_account_info(),
+            &ctx.accounts.nft_vault,
+            collection,
+            request_amount,
+            interest_rate,
+            period,
+            additional_collateral,
+            _order_nonce,
+        )
+    }
+
+    pub fn cancel_sol_order(ctx: Context<CancelSolOrder>, _order_id: u64, _nft_nonce: u8) -> Result<()> {
+        ctx.accounts.order.check_state(OrderState::Open)?;
+
+        // Transfer back the nft and the additional collateral.
+        let funds = Funds::Native {
+            system_program: &ctx.accounts.system_program,
+            order: ctx.accounts.order.to_account_info(),
+        };
+        release_collateral(
+            &funds,
+            &mut ctx.accounts.market,
+            &ctx.accounts.order,
+            &ctx.accounts.token_program,
+            &ctx.accounts.nft_mint,
+            &ctx.accounts.nft_vault,
+            ctx.accounts.user_nft_vault.to_account_info(),
+            ctx.accounts.borrower.to_account_info(),
+            ctx.accounts.borrower.to_account_info(),
+            _nft_nonce,
+        )?;
+        let order = &mut ctx.accounts.order;
+        order.state = OrderState::Cancelled;
+        write_receipt(
+            &ctx.accounts.config,
+            &ctx.accounts.receipt,
+            ctx.accounts.borrower.to_account_info(),
+            &ctx.accounts.system_program,
+            order,
+            0,
+        )?;
+
+        emit!(CanceledOrderEvent {
+            order_key: *order.to_account_info().key,
+            borrower: *ctx.accounts.borrower.to_account_info().key,
+        });
+
+        Ok(())
+    }
+
+    pub fn give_sol_loan(
+        ctx: Context<GiveSolLoan>,
+        _order_id: u64,
+        terms_version: u64,
+        expected_request_amount: u64,
+        expected_interest_rate: u64,
+        expected_period: u64,
+        expected_nft_mint: Pubkey,
+    ) -> Result<()> {
+        let now = clock::Clock::get().unwrap().unix_timestamp as u64;
+        ctx.accounts.order.check_fundable(
+            terms_version,
+            expected_request_amount,
+            expected_interest_rate,
+            expected_period,
+            &expected_nft_mint,
+            now,
+        )?;
+
+        // Send the loan to the borrower, withholding the origination fee
+        let funds = Funds::Native {
+            system_program: &ctx.accounts.system_program,
+            order: ctx.accounts.order.to_account_info(),
+        };
+        let treasury = ctx.accounts.market.to_account_info();
+        let origination_fee = fund_loan(
+            &funds,
+            &ctx.accounts.config,
+            &mut ctx.accounts.market,
+            &mut ctx.accounts.order,
+            ctx.accounts.lender.to_account_info(),
+            ctx.accounts.lender.to_account_info(),
+            ctx.accounts.borrower.to_account_info(),
+            treasury,
+            now,
+        )?;
+        let order = &mut ctx.accounts.order;
+        value_collateral(
+            &ctx.accounts.config,
+            &ctx.accounts.collection_whitelist,
+            &ctx.accounts.price_feed,
+            order,
+            token_constants::NATIVE_DECIMALS,
+        )?;
+
+        emit!(LoanOrderEvent {
+            order_key: *order.to_account_info().key,
+            borrower: order.borrower,
+            lender: *ctx.accounts.lender.to_account_info().key,
+            origination_fee,
+        });
+        Ok(())
+    }
+
+    pub fn payback_sol(ctx: Context<PaybackSol>, _order_id: u64, _nft_nonce: u8) -> Result<()> {
+        ctx.accounts.order.check_state(OrderState::Active)?;
+
+        let clock = clock::Clock::get().unwrap();
+        let now = clock.unix_timestamp as u64;
+        if ctx.accounts.order.liquidation_time()? < now {
+            return Err(ErrorCode::RepaymentPeriodExceeded.into());
+        }
+
+        // Pay Loan
+        let funds = Funds::Native {
+            system_program: &ctx.accounts.system_program,
+            order: ctx.accounts.order.to_account_info(),
+        };
+        let treasury = ctx.accounts.market.to_account_info();
+        let (interest, protocol_fee, late_fee) = repay_loan(
+            &funds,
+            &mut ctx.accounts.market,
+            &mut ctx.accounts.order,
+            ctx.accounts.borrower.to_account_info(),
+            ctx.accounts.borrower.to_account_info(),
+            ctx.accounts.lender.to_account_info(),
+            treasury,
+            now,
+        )?;
+
+        // Transfer back the nft and the additional collateral.
+        release_collateral(
+            &funds,
+            &mut ctx.accounts.market,
+            &ctx.accounts.order,
+            &ctx.accounts.token_program,
+            &ctx.accounts.nft_mint,
+            &ctx.accounts.nft_vault,
+            ctx.accounts.borrower_nft_vault.to_account_info(),
+            ctx.accounts.borrower.to_account_info(),
+            ctx.accounts.borrower.to_account_info(),
+            _nft_nonce,
+        )?;
+        let order = &mut ctx.accounts.order;
+        order.state = OrderState::Repaid;
+        order.paid_back_at = now;
+        write_receipt(
+            &ctx.accounts.config,
+            &ctx.accounts.receipt,
+            ctx.accounts.borrower.to_account_info(),
+            &ctx.accounts.system_program,
+            order,
+            late_fee,
+        )?;
+
+        emit!(PayBackOrderEvent {
+            order_key: *order.to_account_info().key,
+            borrower: *ctx.accounts.borrower.to_account_info().key,
+            interest,
+            protocol_fee,
+            late_fee,
+        });
+
+        Ok(())
+    }
+
+    pub fn liquidate_sol(ctx: Context<LiquidateSol>, _order_id: u64, _nft_nonce: u8) -> Result<()> {
+        let clock = clock::Clock::get().unwrap();
+        let now = clock.unix_timestamp as u64;
+        let (trigger, loan_to_value) =
+            liquidation_trigger(&ctx.accounts.config, &ctx.accounts.price_feed, &ctx.accounts.order, token_constants::NATIVE_DECIMALS, now)?;
+
+        // Transfer the nft and the additional collateral to the lender.
+        let funds = Funds::Native {
+            system_program: &ctx.accounts.system_program,
+            order: ctx.accounts.order.to_account_info(),
+        };
+        release_collateral(
+            &funds,
+            &mut ctx.accounts.market,
+            &ctx.accounts.order,
+            &ctx.accounts.token_program,
+            &ctx.accounts.nft_mint,
+            &ctx.accounts.nft_vault,
+            ctx.accounts.lender_nft_vault.to_account_info(),
+            ctx.accounts.lender.to_account_info(),
+            ctx.accounts.borrower.to_account_info(),
+            _nft_nonce,
+        )?;
+        let order = &mut ctx.accounts.order;
+        order.state = OrderState::Liquidated;
+        order.withdrew_at = now;
+        write_receipt(
+            &ctx.accounts.config,
+            &ctx.accounts.receipt,
+            ctx.accounts.lender.to_account_info(),
+            &ctx.accounts.system_program,
+            order,
+            0,
+        )?;
+
+        emit!(LiquidityOrderEvent {
+            order_key: *order.to_account_info().key,
+            borrower: *ctx.accounts.borrower.to_account_info().key,
+            lender: *ctx.accounts.lender.to_account_info().key,
+            trigger,
+            loan_to_value,
+        });
+        Ok(())
+    }
+
+    pub fn create_offer(
+        ctx: Context<CreateOffer>,
+        _stable_nonce: u8,
+        _offer_nonce: u8,
+        request_amount: u64,
+        interest_rate: u64,
+        period: u64,
+        additional_collateral: u64,
+    ) -> Result<()> {
+        ctx.accounts.config.validate_terms(
+            &ctx.accounts.market,
+            request_amount,
+            interest_rate,
+            period,
+            additional_collateral,
+        )?;
+
+        // Escrow the principal in the vault
+        {
+            let cpi_ctx = CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                token::Transfer {
+                    from: ctx.accounts.lender_stable_coin_vault.to_account_info(),
+                    to: ctx.accounts.stable_coin_vault.to_account_info(),
+                    authority: ctx.accounts.lender.to_account_info(),
+                },
+            );
+            token::transfer(cpi_ctx, request_amount)?;
+        }
+        let clock = clock::Clock::get().unwrap();
+
+        // Save Info
+        let offer = &mut ctx.accounts.offer;
+        let config = &mut ctx.accounts.config;
+        offer.lender = ctx.accounts.lender.key();
+        offer.market = ctx.accounts.market.key();
+        offer.stable_coin_vault = ctx.accounts.stable_coin_vault.key();
+        offer.nft_mint = ctx.accounts.nft_mint.key();
+        offer.request_amount = request_amount;
+        offer.interest_rate = interest_rate;
+        offer.period = period;
+        offer.additional_collateral = additional_collateral;
+        offer.created_at = clock.unix_timestamp as u64;
+        offer.offer_id = config.offer_id;
+        offer.nonce = _offer_nonce;
+
+        config.offer_id += 1;
+
+        emit!(CreatedOfferEvent {
+            offer_key: *offer.to_account_info().key,
+            lender: offer.lender,
+            nft_mint: offer.nft_mint,
+        });
+
+        Ok(())
+    }
+
+    pub fn cancel_offer(ctx: Context<CancelOffer>, _offer_id: u64, _stable_nonce: u8) -> Result<()> {
+        // Transfer back the escrowed principal
+        transfer_from_vault(
+            &ctx.accounts.token_program,
+            &ctx.accounts.stable_coin_mint,
+            &ctx.accounts.stable_coin_vault,
+            ctx.accounts.lender_stable_coin_vault.to_account_info(),
+            _stable_nonce,
+            ctx.accounts.offer.request_amount,
+        )?;
+
+        emit!(CanceledOfferEvent {
+            offer_key: *ctx.accounts.offer.to_account_info().key,
+            lender: *ctx.accounts.lender.to_account_info().key,
+        });
+
+        Ok(())
+    }
+
+    pub fn accept_offer(
+        ctx: Context<AcceptOffer>,
+        _offer_id: u64,
+        _stable_nonce: u8,
+        _nft_nonce: u8,
+        _order_nonce: u8,
     ) -> Result<()> {
-        let request_amount: u64 = 80_000_000;
-        let additional_collateral: u64 = 8_000_000;
-        let payback_amount: u64 = 3_200_000;
-        let interest: u64 = 4_800_000;
-        //let period = Duration::from_secs(60 * 60 * 24 * 7).as_secs();
-        let period = Duration::from_secs(60 * 10).as_secs();
+        let collection = ctx.accounts.config.check_collection(
+            &ctx.accounts.nft_metadata.to_account_info(),
+            &ctx.accounts.nft_mint.key(),
+        )?;
+        ctx.accounts.config.check_whitelist(
+            &ctx.accounts.collection_whitelist.to_account_info(),
+            &collection,
+            &ctx.accounts.market.key(),
+            ctx.accounts.offer.request_amount,
+            ctx.accounts.offer.period,
+        )?;
+
         // Transfer collateral to vault.
         {
             let cpi_ctx = CpiContext::new(
                 ctx.accounts.token_program.to_account_info(),
                 token::Transfer {
-                    from: ctx.accounts.user_nft_vault.to_account_info(),
+                    from: ctx.accounts.borrower_nft_vault.to_account_info(),
                     to: ctx.accounts.nft_vault.to_account_info(),
                     authority: ctx.accounts.borrower.to_account_info(), //Lock nft
                 },
@@ -64,167 +850,570 @@ pub mod solana_nft_collaterized_loans {
             let cpi_ctx = CpiContext::new(
                 ctx.accounts.token_program.to_account_info(),
                 token::Transfer {
-                    from: ctx.accounts.user_stable_coin_vault.to_account_info(),
+                    from: ctx.accounts.borrower_stable_coin_vault.to_account_info(),
                     to: ctx.accounts.stable_coin_vault.to_account_info(),
-                    authority: ctx.accounts.borrower.to_account_info(), //
+                    authority: ctx.accounts.borrower.to_account_info(),
                 },
             );
-            token::transfer(cpi_ctx, additional_collateral)?;
+            token::transfer(cpi_ctx, ctx.accounts.offer.additional_collateral)?;
+        }
+
+        // Release the escrowed principal to the borrower, minus the origination fee
+        let origination_fee = math::bps_of(ctx.accounts.offer.request_amount, ctx.accounts.config.origination_fee_rate)?;
+        transfer_from_vault(
+            &ctx.accounts.token_program,
+            &ctx.accounts.stable_coin_mint,
+            &ctx.accounts.stable_coin_vault,
+            ctx.accounts.borrower_stable_coin_vault.to_account_info(),
+            _stable_nonce,
+            ctx.accounts.offer.request_amount - origination_fee,
+        )?;
+        if origination_fee > 0 {
+            transfer_from_vault(
+                &ctx.accounts.token_program,
+                &ctx.accounts.stable_coin_mint,
+                &ctx.accounts.stable_coin_vault,
+                ctx.accounts.treasury_vault.to_account_info(),
+                _stable_nonce,
+                origination_fee,
+            )?;
         }
         let clock = clock::Clock::get().unwrap();
 
         // Save Info
+        let offer = &ctx.accounts.offer;
         let order = &mut ctx.accounts.order;
         let config = &mut ctx.accounts.config;
+        let market = &mut ctx.accounts.market;
         order.borrower = ctx.accounts.borrower.key();
+        order.market = market.key();
         order.stable_coin_vault = ctx.accounts.stable_coin_vault.key();
         order.nft_mint = ctx.accounts.nft_mint.key();
         order.nft_vault = ctx.accounts.nft_vault.key();
-        order.request_amount = request_amount;
-        order.payback_amount = payback_amount;
-        order.interest = interest;
-        order.period = period;
-        order.additional_collateral = additional_collateral;
-        order.lender = order.key(); // just a placeholder
+        order.collection = collection;
+        order.request_amount = offer.request_amount;
+        order.payback_amount = math::add(
+            offer.request_amount,
+            math::accrued_interest(offer.request_amount, offer.interest_rate, offer.period)?,
+        )?;
+        order.interest_rate = offer.interest_rate;
+        order.min_interest = config.min_interest(offer.request_amount, offer.interest_rate, offer.period)?;
+        order.period = offer.period;
+        order.additional_collateral = offer.additional_collateral;
+        order.lender = offer.lender;
         order.created_at = clock.unix_timestamp as u64;
-        order.loan_start_time = 0; // placeholder
+        order.loan_start_time = clock.unix_timestamp as u64;
+        order.protocol_fee_rate = config.protocol_fee_rate;
+        order.grace_period = config.grace_period;
+        order.late_fee_rate = config.late_fee_rate;
         order.paid_back_at = 0;
         order.withdrew_at = 0;
         order.order_id = config.order_id;
+        order.is_native = false;
         order.nonce = _order_nonce;
 
-        config.total_additional_collateral += additional_collateral;
+        market.total_additional_collateral = math::add(market.total_additional_collateral, offer.additional_collateral)?;
         config.order_id += 1;
 
-        order.order_status = true;
+        order.state = OrderState::Active;
+        value_collateral(
+            config,
+            &ctx.accounts.collection_whitelist,
+            &ctx.accounts.price_feed,
+            order,
+            ctx.accounts.stable_coin_mint.decimals,
+        )?;
 
-        emit!(CreatedOrderEvent {
+        emit!(AcceptedOfferEvent {
+            offer_key: *offer.to_account_info().key,
             order_key: *order.to_account_info().key,
-            borrower: *ctx.accounts.borrower.to_account_info().key,
+            borrower: order.borrower,
+            lender: order.lender,
+            origination_fee,
         });
 
         Ok(())
     }
 
-    pub fn cancel_order(ctx: Context<CancelOrder>, _order_id: u64, _stable_nonce: u8, _nft_nonce: u8) -> Result<()> {
-        let order = &mut ctx.accounts.order;
-        let config = &mut ctx.accounts.config;
-
-        if order.loan_start_time != 0 && order.order_status == false {
-            return Err(ErrorCode::LoanAlreadyStarted.into());
+    pub fn create_collection_offer(
+        ctx: Context<CreateCollectionOffer>,
+        _stable_nonce: u8,
+        _offer_nonce: u8,
+        collection: Pubkey,
+        request_amount: u64,
+        interest_rate: u64,
+        period: u64,
+        additional_collateral: u64,
+        capacity: u64,
+    ) -> Result<()> {
+        ctx.accounts.config.validate_terms(
+            &ctx.accounts.market,
+            request_amount,
+            interest_rate,
+            period,
+            additional_collateral,
+        )?;
+        if capacity == 0 {
+            return Err(ErrorCode::InvalidCapacity.into());
         }
 
-        // Transfer back nft collateral.
+        // Escrow the principal of every loan in the vault
         {
-            let seeds = &[
-                ctx.accounts.nft_mint.to_account_info().key.as_ref(),
-                constants::NFT_PDA_SEED.as_ref(),
-                &[_nft_nonce]
-            ];
-            let signer = &[&seeds[..]];
-
-            let cpi_ctx = CpiContext::new_with_signer(
+            let cpi_ctx = CpiContext::new(
                 ctx.accounts.token_program.to_account_info(),
                 token::Transfer {
-                    from: ctx.accounts.nft_vault.to_account_info(),
-                    to: ctx.accounts.user_nft_vault.to_account_info(),
-                    authority: ctx.accounts.nft_vault.to_account_info(),
+                    from: ctx.accounts.lender_stable_coin_vault.to_account_info(),
+                    to: ctx.accounts.stable_coin_vault.to_account_info(),
+                    authority: ctx.accounts.lender.to_account_info(),
                 },
-                signer,
             );
-            token::transfer(cpi_ctx, 1)?;
-
-            // Close nft_vault
-            {
-                let cpi_ctx = CpiContext::new_with_signer(
-                    ctx.accounts.token_program.to_account_info(),
-                    token::CloseAccount {
-                        account: ctx.accounts.nft_vault.to_account_info(),
-                        destination: ctx.accounts.borrower.to_account_info(),
-                        authority: ctx.accounts.nft_vault.to_account_info(),
-                    },
-                    signer,
-                );
-                token::close_account(cpi_ctx)?;
-            }
+            token::transfer(cpi_ctx, request_amount.checked_mul(capacity).ok_or(ErrorCode::MathOverflow)?)?;
         }
+        let clock = clock::Clock::get().unwrap();
 
-        // Transfer back additional collateral
-        {
-            let seeds = &[
-                ctx.accounts.stable_coin_mint.to_account_info().key.as_ref(),
-                constants::STABLE_COIN_PDA_SEED.as_ref(),
-                &[_stable_nonce]
-            ];
-            let signer = &[&seeds[..]];
+        // Save Info
+        let offer = &mut ctx.accounts.offer;
+        let config = &mut ctx.accounts.config;
+        offer.lender = ctx.accounts.lender.key();
+        offer.market = ctx.accounts.market.key();
+        offer.stable_coin_vault = ctx.accounts.stable_coin_vault.key();
+        offer.collection = collection;
+        offer.request_amount = request_amount;
+        offer.interest_rate = interest_rate;
+        offer.period = period;
+        offer.additional_collateral = additional_collateral;
+        offer.capacity = capacity;
+        offer.remaining = capacity;
+        offer.created_at = clock.unix_timestamp as u64;
+        offer.offer_id = config.offer_id;
+        offer.nonce = _offer_nonce;
 
-            let cpi_ctx = CpiContext::new_with_signer(
-                ctx.accounts.token_program.to_account_info(),
-                token::Transfer {
-                    from: ctx.accounts.stable_coin_vault.to_account_info(),
-                    to: ctx.accounts.user_stable_coin_vault.to_account_info(),
-                    authority: ctx.accounts.stable_coin_vault.to_account_info(),
-                },
-                signer,
-            );
-            token::transfer(cpi_ctx, order.additional_collateral)?;
-        }
-        config.total_additional_collateral -= order.additional_collateral;
+        config.offer_id += 1;
 
-        emit!(CanceledOrderEvent {
-            order_key: *order.to_account_info().key,
-            borrower: *ctx.accounts.borrower.to_account_info().key,
+        emit!(CreatedCollectionOfferEvent {
+            offer_key: *offer.to_account_info().key,
+            lender: offer.lender,
+            collection,
+            capacity,
         });
 
         Ok(())
     }
 
-    pub fn give_loan(ctx: Context<GiveLoan>, _order_id: u64, _stable_nonce: u8) -> Result<()> {
-        let order = &mut ctx.accounts.order;
-        if order.loan_start_time != 0 && order.order_status == false {
-            return Err(ErrorCode::LoanAlreadyStarted.into());
+    pub fn cancel_collection_offer(ctx: Context<CancelCollectionOffer>, _offer_id: u64, _stable_nonce: u8) -> Result<()> {
+        let offer = &ctx.accounts.offer;
+
+        // Transfer back the principal of the loans not taken yet
+        transfer_from_vault(
+            &ctx.accounts.token_program,
+            &ctx.accounts.stable_coin_mint,
+            &ctx.accounts.stable_coin_vault,
+            ctx.accounts.lender_stable_coin_vault.to_account_info(),
+            _stable_nonce,
+            offer.request_amount.checked_mul(offer.remaining).ok_or(ErrorCode::MathOverflow)?,
+        )?;
+
+        emit!(CanceledCollectionOfferEvent {
+            offer_key: *offer.to_account_info().key,
+            lender: *ctx.accounts.lender.to_account_info().key,
+            remaining: offer.remaining,
+        });
+
+        Ok(())
+    }
+
+    pub fn accept_collection_offer(
+        ctx: Context<AcceptCollectionOffer>,
+        _offer_id: u64,
+        _stable_nonce: u8,
+        _nft_nonce: u8,
+        _order_nonce: u8,
+    ) -> Result<()> {
+        if ctx.accounts.offer.remaining == 0 {
+            return Err(ErrorCode::OfferExhausted.into());
         }
+        let collection = metadata::verified_collection(
+            &ctx.accounts.nft_metadata.to_account_info(),
+            &ctx.accounts.nft_mint.key(),
+        )?;
+        if collection != Some(ctx.accounts.offer.collection) {
+            return Err(ErrorCode::CollectionMismatch.into());
+        }
+        ctx.accounts.config.check_whitelist(
+            &ctx.accounts.collection_whitelist.to_account_info(),
+            &ctx.accounts.offer.collection,
+            &ctx.accounts.market.key(),
+            ctx.accounts.offer.request_amount,
+            ctx.accounts.offer.period,
+        )?;
 
-        // Transfer back additional collateral
+        // Transfer collateral to vault.
         {
             let cpi_ctx = CpiContext::new(
                 ctx.accounts.token_program.to_account_info(),
                 token::Transfer {
-                    from: ctx.accounts.lender_stable_coin_vault.to_account_info(),
-                    to: ctx.accounts.borrower_stable_coin_vault.to_account_info(),
-                    authority: ctx.accounts.lender.to_account_info(),
+                    from: ctx.accounts.borrower_nft_vault.to_account_info(),
+                    to: ctx.accounts.nft_vault.to_account_info(),
+                    authority: ctx.accounts.borrower.to_account_info(), //Lock nft
                 },
             );
-            token::transfer(cpi_ctx, order.request_amount)?;
+            token::transfer(cpi_ctx, 1)?;
         }
 
-        // Save Info
-        order.lender = ctx.accounts.lender.key();
-        order.loan_start_time = clock::Clock::get().unwrap().unix_timestamp as u64;
-        order.order_status = false;
-
-        emit!(LoanOrderEvent {
-            order_key: *order.to_account_info().key,
-            borrower: order.borrower,
-            lender: *ctx.accounts.lender.to_account_info().key,
+        // Transfer additional collateral to vault
+        {
+            let cpi_ctx = CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                token::Transfer {
+                    from: ctx.accounts.borrower_stable_coin_vault.to_account_info(),
+                    to: ctx.accounts.stable_coin_vault.to_account_info(),
+                    authority: ctx.accounts.borrower.to_account_info(),
+                },
+            );
+            token::transfer(cpi_ctx, ctx.accounts.offer.additional_collateral)?;
+        }
+
+        // Release the escrowed principal to the borrower, minus the origination fee
+        let origination_fee = math::bps_of(ctx.accounts.offer.request_amount, ctx.accounts.config.origination_fee_rate)?;
+        transfer_from_vault(
+            &ctx.accounts.token_program,
+            &ctx.accounts.stable_coin_mint,
+            &ctx.accounts.stable_coin_vault,
+            ctx.accounts.borrower_stable_coin_vault.to_account_info(),
+            _stable_nonce,
+            ctx.accounts.offer.request_amount - origination_fee,
+        )?;
+        if origination_fee > 0 {
+            transfer_from_vault(
+                &ctx.accounts.token_program,
+                &ctx.accounts.stable_coin_mint,
+                &ctx.accounts.stable_coin_vault,
+                ctx.accounts.treasury_vault.to_account_info(),
+                _stable_nonce,
+                origination_fee,
+            )?;
+        }
+        let clock = clock::Clock::get().unwrap();
+
+        // Save Info
+        let offer = &mut ctx.accounts.offer;
+        let order = &mut ctx.accounts.order;
+        let config = &mut ctx.accounts.config;
+        let market = &mut ctx.accounts.market;
+        order.borrower = ctx.accounts.borrower.key();
+        order.market = market.key();
+        order.stable_coin_vault = ctx.accounts.stable_coin_vault.key();
+        order.nft_mint = ctx.accounts.nft_mint.key();
+        order.nft_vault = ctx.accounts.nft_vault.key();
+        order.collection = offer.collection;
+        order.request_amount = offer.request_amount;
+        order.payback_amount = math::add(
+            offer.request_amount,
+            math::accrued_interest(offer.request_amount, offer.interest_rate, offer.period)?,
+        )?;
+        order.interest_rate = offer.interest_rate;
+        order.min_interest = config.min_interest(offer.request_amount, offer.interest_rate, offer.period)?;
+        order.period = offer.period;
+        order.additional_collateral = offer.additional_collateral;
+        order.lender = offer.lender;
+        order.created_at = clock.unix_timestamp as u64;
+        order.loan_start_time = clock.unix_timestamp as u64;
+        order.protocol_fee_rate = config.protocol_fee_rate;
+        order.grace_period = config.grace_period;
+        order.late_fee_rate = config.late_fee_rate;
+        order.paid_back_at = 0;
+        order.withdrew_at = 0;
+        order.order_id = config.order_id;
+        order.is_native = false;
+        order.nonce = _order_nonce;
+
+        market.total_additional_collateral = math::add(market.total_additional_collateral, offer.additional_collateral)?;
+        config.order_id += 1;
+        offer.remaining -= 1;
+
+        order.state = OrderState::Active;
+        value_collateral(
+            config,
+            &ctx.accounts.collection_whitelist,
+            &ctx.accounts.price_feed,
+            order,
+            ctx.accounts.stable_coin_mint.decimals,
+        )?;
+
+        emit!(AcceptedCollectionOfferEvent {
+            offer_key: *offer.to_account_info().key,
+            order_key: *order.to_account_info().key,
+            borrower: order.borrower,
+            lender: order.lender,
+            remaining: offer.remaining,
+            origination_fee,
         });
+
         Ok(())
     }
 
-    pub fn payback(ctx: Context<Payback>, _order_id: u64, _stable_nonce: u8, _nft_nonce: u8) -> Result<()> {
+    pub fn add_collection(
+        ctx: Context<AddCollection>,
+        _whitelist_nonce: u8,
+        collection: Pubkey,
+        max_request_amount: u64,
+        max_period: u64,
+        price_feed: Pubkey,
+    ) -> Result<()> {
+        let entry = &mut ctx.accounts.collection_whitelist;
+        entry.collection = collection;
+        entry.market = ctx.accounts.market.key();
+        entry.max_request_amount = max_request_amount;
+        entry.max_period = max_period;
+        entry.price_feed = price_feed;
+        entry.nonce = _whitelist_nonce;
+        entry.validate()?;
+
+        emit!(UpdatedCollectionWhitelistEvent {
+            whitelist_key: *entry.to_account_info().key,
+            collection,
+            market: entry.market,
+            max_request_amount,
+            max_period,
+            price_feed,
+        });
+
+        Ok(())
+    }
+
+    pub fn update_collection(
+        ctx: Context<UpdateCollection>,
+        max_request_amount: u64,
+        max_period: u64,
+        price_feed: Pubkey,
+    ) -> Result<()> {
+        let entry = &mut ctx.accounts.collection_whitelist;
+        entry.max_request_amount = max_request_amount;
+        entry.max_period = max_period;
+        entry.price_feed = price_feed;
+        entry.validate()?;
+
+        emit!(UpdatedCollectionWhitelistEvent {
+            whitelist_key: *entry.to_account_info().key,
+            collection: entry.collection,
+            market: entry.market,
+            max_request_amount,
+            max_period,
+            price_feed,
+        });
+
+        Ok(())
+    }
+
+    pub fn remove_collection(ctx: Context<RemoveCollection>) -> Result<()> {
+        let entry = &ctx.accounts.collection_whitelist;
+
+        emit!(RemovedCollectionWhitelistEvent {
+            whitelist_key: *entry.to_account_info().key,
+            collection: entry.collection,
+            market: entry.market,
+        });
+
+        Ok(())
+    }
+
+    pub fn withdraw_treasury(ctx: Context<WithdrawTreasury>, _treasury_nonce: u8, amount: u64) -> Result<()> {
+        {
+            let seeds = &[
+                ctx.accounts.stable_coin_mint.to_account_info().key.as_ref(),
+                constants::TREASURY_PDA_SEED.as_ref(),
+                &[_treasury_nonce]
+            ];
+            let signer = &[&seeds[..]];
+
+            let cpi_ctx = CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                token::Transfer {
+                    from: ctx.accounts.treasury_vault.to_account_info(),
+                    to: ctx.accounts.admin_stable_coin_vault.to_account_info(),
+                    authority: ctx.accounts.treasury_vault.to_account_info(),
+                },
+                signer,
+            );
+            token::transfer(cpi_ctx, amount)?;
+        }
+
+        emit!(WithdrewTreasuryEvent {
+            market_key: *ctx.accounts.market.to_account_info().key,
+            admin: *ctx.accounts.admin.to_account_info().key,
+            amount,
+        });
+
+        Ok(())
+    }
+
+    pub fn withdraw_sol_treasury(ctx: Context<WithdrawSolTreasury>, amount: u64) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        if amount > market.sol_treasury {
+            return Err(ErrorCode::InsufficientTreasury.into());
+        }
+
+        withdraw_lamports(
+            market.to_account_info(),
+            ctx.accounts.admin.to_account_info(),
+            amount,
+        )?;
+        market.sol_treasury = math::sub(market.sol_treasury, amount)?;
+
+        emit!(WithdrewTreasuryEvent {
+            market_key: *market.to_account_info().key,
+            admin: *ctx.accounts.admin.to_account_info().key,
+            amount,
+        });
+
+        Ok(())
+    }
+
+    pub fn set_installment_schedule(
+        ctx: Context<SetInstallmentSchedule>,
+        _order_id: u64,
+        installment_amount: u64,
+        installment_interval: u64,
+    ) -> Result<()> {
         let order = &mut ctx.accounts.order;
-        let config = &mut ctx.accounts.config;
+        order.check_state(OrderState::Open)?;
+        // both zero removes the schedule
+        if (installment_amount == 0) != (installment_interval == 0) || installment_interval > order.period {
+            return Err(ErrorCode::InvalidInstallmentSchedule.into());
+        }
+
+        order.installment_amount = installment_amount;
+        order.installment_interval = installment_interval;
+        order.terms_version = math::add(order.terms_version, 1)?;
+
+        emit!(UpdatedInstallmentScheduleEvent {
+            order_key: *order.to_account_info().key,
+            borrower: order.borrower,
+            installment_amount,
+            installment_interval,
+            terms_version: order.terms_version,
+        });
+
+        Ok(())
+    }
+
+    pub fn repay_partial(ctx: Context<RepayPartial>, _order_id: u64, _stable_nonce: u8, amount: u64) -> Result<()> {
+        let funds = Funds::Token {
+            token_program: &ctx.accounts.token_program,
+            stable_coin_mint: &ctx.accounts.stable_coin_mint,
+            stable_coin_vault: &ctx.accounts.stable_coin_vault,
+            stable_nonce: _stable_nonce,
+        };
+        repay_part(
+            &funds,
+            &mut ctx.accounts.market,
+            &mut ctx.accounts.order,
+            ctx.accounts.borrower.to_account_info(),
+            ctx.accounts.borrower_stable_coin_vault.to_account_info(),
+            ctx.accounts.lender_stable_coin_vault.to_account_info(),
+            ctx.accounts.treasury_vault.to_account_info(),
+            amount,
+        )
+    }
 
-        if order.loan_start_time == 0 && order.order_status == true {
-            return Err(ErrorCode::LoanNotProvided.into());
+    pub fn repay_partial_sol(ctx: Context<RepayPartialSol>, _order_id: u64, amount: u64) -> Result<()> {
+        let funds = Funds::Native {
+            system_program: &ctx.accounts.system_program,
+            order: ctx.accounts.order.to_account_info(),
+        };
+        let treasury = ctx.accounts.market.to_account_info();
+        repay_part(
+            &funds,
+            &mut ctx.accounts.market,
+            &mut ctx.accounts.order,
+            ctx.accounts.borrower.to_account_info(),
+            ctx.accounts.borrower.to_account_info(),
+            ctx.accounts.lender.to_account_info(),
+            treasury,
+            amount,
+        )
+    }
+
+    pub fn propose_extension(
+        ctx: Context<ProposeExtension>,
+        _order_id: u64,
+        new_period: u64,
+        extra_interest: u64,
+    ) -> Result<()> {
+        let order = &mut ctx.accounts.order;
+        order.check_state(OrderState::Active)?;
+        if new_period <= order.period || new_period > ctx.accounts.config.max_period {
+            return Err(ErrorCode::InvalidPeriod.into());
+        }
+
+        order.proposed_period = new_period;
+        order.proposed_extra_interest = extra_interest;
+
+        emit!(ProposedExtensionEvent {
+            order_key: *order.to_account_info().key,
+            borrower: order.borrower,
+            new_period,
+            extra_interest,
+        });
+
+        Ok(())
+    }
+
+    // The lender accepts any extension up to `max_period` that pays at least `min_extra_interest`,
+    // approving a single proposal or pre-authorizing future ones.
+    pub fn approve_extension(
+        ctx: Context<ApproveExtension>,
+        _order_id: u64,
+        max_period: u64,
+        min_extra_interest: u64,
+    ) -> Result<()> {
+        let order = &mut ctx.accounts.order;
+        order.check_state(OrderState::Active)?;
+        if max_period > ctx.accounts.config.max_period {
+            return Err(ErrorCode::InvalidPeriod.into());
         }
 
+        order.approved_max_period = max_period;
+        order.approved_min_extra_interest = min_extra_interest;
+
+        emit!(ApprovedExtensionEvent {
+            order_key: *order.to_account_info().key,
+            lender: order.lender,
+            max_period,
+            min_extra_interest,
+        });
+
+        Ok(())
+    }
+
+    pub fn extend_loan(ctx: Context<ExtendLoan>, _order_id: u64) -> Result<()> {
+        let order = &mut ctx.accounts.order;
+        order.check_state(OrderState::Active)?;
+
         let clock = clock::Clock::get().unwrap();
-        if order.loan_start_time.checked_add(order.period).unwrap() < clock.unix_timestamp as u64 {
+        let now = clock.unix_timestamp as u64;
+        if order.deadline()? < now || order.is_defaulted(now)? {
             return Err(ErrorCode::RepaymentPeriodExceeded.into());
         }
 
-        // Pay Loan
+        if order.proposed_period <= order.period {
+            return Err(ErrorCode::NoExtensionProposed.into());
+        }
+        // the protocol bound may have been lowered since the proposal
+        if order.proposed_period > ctx.accounts.config.max_period {
+            return Err(ErrorCode::InvalidPeriod.into());
+        }
+        if order.proposed_period > order.approved_max_period
+            || order.proposed_extra_interest < order.approved_min_extra_interest
+        {
+            return Err(ErrorCode::ExtensionNotApproved.into());
+        }
+
+        // Settle the interest accrued so far together with the extra interest of the extension
+        let interest = math::add(order.accrued_interest(now)?, order.proposed_extra_interest)?;
+        let protocol_fee = math::bps_of(interest, order.protocol_fee_rate)?;
+
+        // Pay the lender
         {
             let cpi_ctx = CpiContext::new(
                 ctx.accounts.token_program.to_account_info(),
@@ -234,202 +1423,3558 @@ pub mod solana_nft_collaterized_loans {
                     authority: ctx.accounts.borrower.to_account_info(),
                 },
             );
-            token::transfer(cpi_ctx, order.request_amount.checked_add(order.interest).unwrap())?;
+            token::transfer(cpi_ctx, interest - protocol_fee)?;
         }
 
-        // Transfer back nft collateral.
-        {
-            let seeds = &[
-                ctx.accounts.nft_mint.to_account_info().key.as_ref(),
-                constants::NFT_PDA_SEED.as_ref(),
-                &[_nft_nonce]
-            ];
-            let signer = &[&seeds[..]];
-
-            let cpi_ctx = CpiContext::new_with_signer(
+        // Protocol fee on the interest
+        if protocol_fee > 0 {
+            let cpi_ctx = CpiContext::new(
                 ctx.accounts.token_program.to_account_info(),
                 token::Transfer {
-                    from: ctx.accounts.nft_vault.to_account_info(),
-                    to: ctx.accounts.borrower_nft_vault.to_account_info(),
-                    authority: ctx.accounts.nft_vault.to_account_info(),
+                    from: ctx.accounts.borrower_stable_coin_vault.to_account_info(),
+                    to: ctx.accounts.treasury_vault.to_account_info(),
+                    authority: ctx.accounts.borrower.to_account_info(),
                 },
-                signer,
             );
-            token::transfer(cpi_ctx, 1)?;
+            token::transfer(cpi_ctx, protocol_fee)?;
+        }
 
-            // Close Nft Vault
+        order.record_repayment(0, interest, now)?;
+        let old_period = order.period;
+        order.period = order.proposed_period;
+        order.proposed_period = 0;
+        order.proposed_extra_interest = 0;
+        order.extension_count = math::add(order.extension_count, 1)?;
 
-            let cpi_ctx = CpiContext::new_with_signer(
+        emit!(ExtendedLoanEvent {
+            order_key: *order.to_account_info().key,
+            borrower: order.borrower,
+            lender: order.lender,
+            old_period,
+            new_period: order.period,
+            interest,
+            protocol_fee,
+        });
+
+        Ok(())
+    }
+
+    // A new lender pays off the current lender and takes over the loan on new terms,
+    // the nft stays in its vault.
+    pub fn refinance(
+        ctx: Context<Refinance>,
+        _order_id: u64,
+        interest_rate: u64,
+        period: u64,
+    ) -> Result<()> {
+        let order = &mut ctx.accounts.order;
+        let config = &ctx.accounts.config;
+        order.check_state(OrderState::Active)?;
+
+        let clock = clock::Clock::get().unwrap();
+        let now = clock.unix_timestamp as u64;
+        if order.deadline()? < now {
+            return Err(ErrorCode::RepaymentPeriodExceeded.into());
+        }
+
+        // The new loan covers the outstanding principal and the interest owed to the current lender
+        let outstanding = order.outstanding_principal()?;
+        let interest = order.interest_due(now)?;
+        let protocol_fee = math::bps_of(interest, order.protocol_fee_rate)?;
+        let request_amount = math::add(outstanding, interest)?;
+        config.validate_terms(
+            &ctx.accounts.market,
+            request_amount,
+            interest_rate,
+            period,
+            order.additional_collateral,
+        )?;
+        config.check_whitelist(
+            &ctx.accounts.collection_whitelist.to_account_info(),
+            &order.collection,
+            &ctx.accounts.market.key(),
+            request_amount,
+            period,
+        )?;
+
+        // Pay off the current lender
+        {
+            let cpi_ctx = CpiContext::new(
                 ctx.accounts.token_program.to_account_info(),
-                token::CloseAccount {
-                    account: ctx.accounts.nft_vault.to_account_info(),
-                    destination: ctx.accounts.borrower.to_account_info(),
-                    authority: ctx.accounts.nft_vault.to_account_info(),
+                token::Transfer {
+                    from: ctx.accounts.new_lender_stable_coin_vault.to_account_info(),
+                    to: ctx.accounts.lender_stable_coin_vault.to_account_info(),
+                    authority: ctx.accounts.new_lender.to_account_info(),
                 },
-                signer,
             );
-            token::close_account(cpi_ctx)?;
+            token::transfer(cpi_ctx, request_amount - protocol_fee)?;
         }
 
-        // Transfer back additional collateral
-        {
-            let seeds = &[
-                ctx.accounts.stable_coin_mint.to_account_info().key.as_ref(),
-                constants::STABLE_COIN_PDA_SEED.as_ref(),
-                &[_stable_nonce]
-            ];
-            let signer = &[&seeds[..]];
-
-            let cpi_ctx = CpiContext::new_with_signer(
+        // Protocol fee on the interest
+        if protocol_fee > 0 {
+            let cpi_ctx = CpiContext::new(
                 ctx.accounts.token_program.to_account_info(),
                 token::Transfer {
-                    from: ctx.accounts.stable_coin_vault.to_account_info(),
-                    to: ctx.accounts.borrower_stable_coin_vault.to_account_info(),
-                    authority: ctx.accounts.stable_coin_vault.to_account_info(),
+                    from: ctx.accounts.new_lender_stable_coin_vault.to_account_info(),
+                    to: ctx.accounts.treasury_vault.to_account_info(),
+                    authority: ctx.accounts.new_lender.to_account_info(),
                 },
-                signer,
             );
-            token::transfer(cpi_ctx, order.additional_collateral)?;
+            token::transfer(cpi_ctx, protocol_fee)?;
         }
-        config.total_additional_collateral -= order.additional_collateral;
 
-        emit!(PayBackOrderEvent {
+        let old_lender = order.lender;
+        let old_interest_rate = order.interest_rate;
+        let old_period = order.period;
+
+        // Save Info
+        order.lender = ctx.accounts.new_lender.key();
+        order.request_amount = request_amount;
+        order.interest_rate = interest_rate;
+        order.period = period;
+        order.min_interest = config.min_interest(request_amount, interest_rate, period)?;
+        order.payback_amount = math::add(
+            request_amount,
+            math::accrued_interest(request_amount, interest_rate, period)?,
+        )?;
+        order.loan_start_time = now;
+        order.protocol_fee_rate = config.protocol_fee_rate;
+        order.grace_period = config.grace_period;
+        order.late_fee_rate = config.late_fee_rate;
+        order.amount_repaid = 0;
+        order.principal_repaid = 0;
+        order.last_repaid_at = 0;
+        order.repayment_count = 0;
+        order.repayments = Default::default();
+        order.installment_amount = 0;
+        order.installment_interval = 0;
+        order.proposed_period = 0;
+        order.proposed_extra_interest = 0;
+        order.approved_max_period = 0;
+        order.approved_min_extra_interest = 0;
+
+        // Value the collateral against the new principal
+        order.price_feed = Pubkey::default();
+        order.floor_price = 0;
+        order.floor_price_time = 0;
+        order.maintenance_ltv = 0;
+        value_collateral(
+            config,
+            &ctx.accounts.collection_whitelist,
+            &ctx.accounts.price_feed,
+            order,
+            ctx.accounts.stable_coin_mint.decimals,
+        )?;
+
+        emit!(RefinancedOrderEvent {
             order_key: *order.to_account_info().key,
-            borrower: *ctx.accounts.borrower.to_account_info().key,
+            borrower: order.borrower,
+            old_lender,
+            new_lender: order.lender,
+            old_principal: outstanding,
+            old_interest_rate,
+            old_period,
+            new_principal: request_amount,
+            new_interest_rate: interest_rate,
+            new_period: period,
+            interest,
+            protocol_fee,
         });
 
         Ok(())
     }
 
-    pub fn liquidate(ctx: Context<Liquidate>, _order_id: u64, _stable_nonce: u8, _nft_nonce: u8) -> Result<()> {
+    pub fn close_receipt(_ctx: Context<CloseReceipt>) -> Result<()> {
+        Ok(())
+    }
+
+    // Anyone can expire an unfunded order past its expiry, returning the collateral to the borrower.
+    pub fn expire_order(ctx: Context<ExpireOrder>, _order_id: u64, _stable_nonce: u8, _nft_nonce: u8) -> Result<()> {
+        ctx.accounts.order.check_state(OrderState::Open)?;
+        if !ctx.accounts.order.is_expired(clock::Clock::get().unwrap().unix_timestamp as u64) {
+            return Err(ErrorCode::OrderNotExpired.into());
+        }
+
+        // Transfer back the nft and the additional collateral.
+        let funds = Funds::Token {
+            token_program: &ctx.accounts.token_program,
+            stable_coin_mint: &ctx.accounts.stable_coin_mint,
+            stable_coin_vault: &ctx.accounts.stable_coin_vault,
+            stable_nonce: _stable_nonce,
+        };
+        release_collateral(
+            &funds,
+            &mut ctx.accounts.market,
+            &ctx.accounts.order,
+            &ctx.accounts.token_program,
+            &ctx.accounts.nft_mint,
+            &ctx.accounts.nft_vault,
+            ctx.accounts.user_nft_vault.to_account_info(),
+            ctx.accounts.user_stable_coin_vault.to_account_info(),
+            ctx.accounts.borrower.to_account_info(),
+            _nft_nonce,
+        )?;
         let order = &mut ctx.accounts.order;
-        let config = &mut ctx.accounts.config;
+        order.state = OrderState::Expired;
+        write_receipt(
+            &ctx.accounts.config,
+            &ctx.accounts.receipt,
+            ctx.accounts.payer.to_account_info(),
+            &ctx.accounts.system_program,
+            order,
+            0,
+        )?;
+
+        emit!(ExpiredOrderEvent {
+            order_key: *order.to_account_info().key,
+            borrower: order.borrower,
+        });
+
+        Ok(())
+    }
 
-        if order.loan_start_time == 0 && order.order_status == true {
-            return Err(ErrorCode::LoanNotProvided.into());
+    pub fn expire_sol_order(ctx: Context<ExpireSolOrder>, _order_id: u64, _nft_nonce: u8) -> Result<()> {
+        ctx.accounts.order.check_state(OrderState::Open)?;
+        if !ctx.accounts.order.is_expired(clock::Clock::get().unwrap().unix_timestamp as u64) {
+            return Err(ErrorCode::OrderNotExpired.into());
         }
 
-        let clock = clock::Clock::get().unwrap();
-        if order.loan_start_time.checked_add(order.period).unwrap() > clock.unix_timestamp as u64 {
-            return Err(ErrorCode::RepaymentPeriodNotExceeded.into());
+        // Transfer back the nft and the additional collateral.
+        let funds = Funds::Native {
+            system_program: &ctx.accounts.system_program,
+            order: ctx.accounts.order.to_account_info(),
+        };
+        release_collateral(
+            &funds,
+            &mut ctx.accounts.market,
+            &ctx.accounts.order,
+            &ctx.accounts.token_program,
+            &ctx.accounts.nft_mint,
+            &ctx.accounts.nft_vault,
+            ctx.accounts.user_nft_vault.to_account_info(),
+            ctx.accounts.borrower.to_account_info(),
+            ctx.accounts.borrower.to_account_info(),
+            _nft_nonce,
+        )?;
+        let order = &mut ctx.accounts.order;
+        order.state = OrderState::Expired;
+        write_receipt(
+            &ctx.accounts.config,
+            &ctx.accounts.receipt,
+            ctx.accounts.payer.to_account_info(),
+            &ctx.accounts.system_program,
+            order,
+            0,
+        )?;
+
+        emit!(ExpiredOrderEvent {
+            order_key: *order.to_account_info().key,
+            borrower: order.borrower,
+        });
+
+        Ok(())
+    }
+
+    pub fn update_order(
+        ctx: Context<UpdateOrder>,
+        _order_id: u64,
+        _stable_nonce: u8,
+        request_amount: u64,
+        interest_rate: u64,
+        period: u64,
+        additional_collateral: u64,
+    ) -> Result<()> {
+        ctx.accounts.order.check_state(OrderState::Open)?;
+        if ctx.accounts.order.is_expired(clock::Clock::get().unwrap().unix_timestamp as u64) {
+            return Err(ErrorCode::OrderExpired.into());
+        }
+        ctx.accounts.config.validate_terms(
+            &ctx.accounts.market,
+            request_amount,
+            interest_rate,
+            period,
+            additional_collateral,
+        )?;
+        ctx.accounts.config.check_whitelist(
+            &ctx.accounts.collection_whitelist.to_account_info(),
+            &ctx.accounts.order.collection,
+            &ctx.accounts.market.key(),
+            request_amount,
+            period,
+        )?;
+
+        // Top up or withdraw additional collateral
+        let funds = Funds::Token {
+            token_program: &ctx.accounts.token_program,
+            stable_coin_mint: &ctx.accounts.stable_coin_mint,
+            stable_coin_vault: &ctx.accounts.stable_coin_vault,
+            stable_nonce: _stable_nonce,
+        };
+        adjust_collateral(
+            &funds,
+            &mut ctx.accounts.market,
+            &ctx.accounts.order,
+            ctx.accounts.borrower.to_account_info(),
+            ctx.accounts.user_stable_coin_vault.to_account_info(),
+            additional_collateral,
+        )?;
+
+        // Save Info
+        let order = &mut ctx.accounts.order;
+        order.set_terms(&ctx.accounts.config, request_amount, interest_rate, period, additional_collateral)?;
+
+        emit!(UpdatedOrderEvent {
+            order_key: *order.to_account_info().key,
+            borrower: order.borrower,
+            request_amount,
+            interest_rate,
+            period,
+            additional_collateral,
+            terms_version: order.terms_version,
+        });
+
+        Ok(())
+    }
+
+    pub fn update_sol_order(
+        ctx: Context<UpdateSolOrder>,
+        _order_id: u64,
+        request_amount: u64,
+        interest_rate: u64,
+        period: u64,
+        additional_collateral: u64,
+    ) -> Result<()> {
+        ctx.accounts.order.check_state(OrderState::Open)?;
+        if ctx.accounts.order.is_expired(clock::Clock::get().unwrap().unix_timestamp as u64) {
+            return Err(ErrorCode::OrderExpired.into());
         }
+        ctx.accounts.config.validate_terms(
+            &ctx.accounts.market,
+            request_amount,
+            interest_rate,
+            period,
+            additional_collateral,
+        )?;
+        ctx.accounts.config.check_whitelist(
+            &ctx.accounts.collection_whitelist.to_account_info(),
+            &ctx.accounts.order.collection,
+            &ctx.accounts.market.key(),
+            request_amount,
+            period,
+        )?;
 
-        if order.withdrew_at != 0 {
-            return Err(ErrorCode::AlreadyLiquidated.into());
+        // Top up or withdraw additional collateral
+        let funds = Funds::Native {
+            system_program: &ctx.accounts.system_program,
+            order: ctx.accounts.order.to_account_info(),
+        };
+        adjust_collateral(
+            &funds,
+            &mut ctx.accounts.market,
+            &ctx.accounts.order,
+            ctx.accounts.borrower.to_account_info(),
+            ctx.accounts.borrower.to_account_info(),
+            additional_collateral,
+        )?;
+
+        // Save Info
+        let order = &mut ctx.accounts.order;
+        order.set_terms(&ctx.accounts.config, request_amount, interest_rate, period, additional_collateral)?;
+
+        emit!(UpdatedOrderEvent {
+            order_key: *order.to_account_info().key,
+            borrower: order.borrower,
+            request_amount,
+            interest_rate,
+            period,
+            additional_collateral,
+            terms_version: order.terms_version,
+        });
+
+        Ok(())
+    }
+
+    pub fn start_auction(ctx: Context<StartAuction>, _order_id: u64) -> Result<()> {
+        let config = &ctx.accounts.config;
+        let order = &mut ctx.accounts.order;
+        if config.auction_start_multiplier == 0 {
+            return Err(ErrorCode::AuctionsDisabled.into());
+        }
+
+        let now = clock::Clock::get().unwrap().unix_timestamp as u64;
+        order.check_liquidatable(now)?;
+
+        order.state = OrderState::Auction;
+        order.auction_kind = AuctionKind::Dutch;
+        order.auction_start_time = now;
+        order.auction_end_time = math::add(now, config.auction_duration)?;
+        let debt = order.auction_debt()?;
+        order.auction_start_price = math::bps_of(debt, config.auction_start_multiplier)?;
+
+        emit!(StartedAuctionEvent {
+            order_key: *order.to_account_info().key,
+            lender: order.lender,
+            debt,
+            start_price: order.auction_start_price,
+            end_time: order.auction_end_time,
+        });
+
+        Ok(())
+    }
+
+    pub fn buy_auction(
+        ctx: Context<BuyAuction>,
+        _order_id: u64,
+        _stable_nonce: u8,
+        _nft_nonce: u8,
+        max_price: u64,
+    ) -> Result<()> {
+        let order = &mut ctx.accounts.order;
+        let market = &mut ctx.accounts.market;
+        if order.state != OrderState::Auction {
+            return Err(ErrorCode::NoAuction.into());
+        }
+        if order.auction_kind != AuctionKind::Dutch {
+            return Err(ErrorCode::WrongAuctionKind.into());
+        }
+
+        let now = clock::Clock::get().unwrap().unix_timestamp as u64;
+        if now >= order.auction_end_time {
+            return Err(ErrorCode::AuctionEnded.into());
+        }
+        let price = order.auction_price(now)?;
+        if price > max_price {
+            return Err(ErrorCode::AuctionPriceTooHigh.into());
         }
+        let interest = order.interest_due(order.auction_start_time)?;
+        let late_fee = order.late_fee(order.auction_start_time)?;
+        let protocol_fee = math::bps_of(interest, order.protocol_fee_rate)?;
+        let debt = order.auction_debt()?;
+        let surplus = math::sub(price, debt)?;
 
-        // Transfer nft collateral.
+        // Pay the lender
         {
-            let seeds = &[
-                ctx.accounts.nft_mint.to_account_info().key.as_ref(),
-                constants::NFT_PDA_SEED.as_ref(),
-                &[_nft_nonce]
-            ];
+            let cpi_ctx = CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                token::Transfer {
+                    from: ctx.accounts.buyer_stable_coin_vault.to_account_info(),
+                    to: ctx.accounts.lender_stable_coin_vault.to_account_info(),
+                    authority: ctx.accounts.buyer.to_account_info(),
+                },
+            );
+            token::transfer(cpi_ctx, math::sub(debt, protocol_fee)?)?;
+        }
+
+        // Protocol fee on the interest
+        if protocol_fee > 0 {
+            let cpi_ctx = CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                token::Transfer {
+                    from: ctx.accounts.buyer_stable_coin_vault.to_account_info(),
+                    to: ctx.accounts.treasury_vault.to_account_info(),
+                    authority: ctx.accounts.buyer.to_account_info(),
+                },
+            );
+            token::transfer(cpi_ctx, protocol_fee)?;
+        }
+
+        // Surplus goes back to the borrower
+        if surplus > 0 {
+            let cpi_ctx = CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                token::Transfer {
+                    from: ctx.accounts.buyer_stable_coin_vault.to_account_info(),
+                    to: ctx.accounts.borrower_stable_coin_vault.to_account_info(),
+                    authority: ctx.accounts.buyer.to_account_info(),
+                },
+            );
+            token::transfer(cpi_ctx, surplus)?;
+        }
+
+        // Transfer nft collateral to the buyer and close nft_vault.
+        release_nft(
+            &ctx.accounts.token_program,
+            &ctx.accounts.nft_mint,
+            &ctx.accounts.nft_vault,
+            ctx.accounts.buyer_nft_vault.to_account_info(),
+            ctx.accounts.borrower.to_account_info(),
+            _nft_nonce,
+        )?;
+
+        // Transfer back additional collateral
+        transfer_from_vault(
+            &ctx.accounts.token_program,
+            &ctx.accounts.stable_coin_mint,
+            &ctx.accounts.stable_coin_vault,
+            ctx.accounts.borrower_stable_coin_vault.to_account_info(),
+            _stable_nonce,
+            order.additional_collateral,
+        )?;
+        market.total_additional_collateral = math::sub(market.total_additional_collateral, order.additional_collateral)?;
+        order.amount_repaid = math::add(order.amount_repaid, math::add(order.outstanding_principal()?, interest)?)?;
+        order.principal_repaid = order.request_amount;
+        order.state = OrderState::Liquidated;
+        order.withdrew_at = now;
+        write_receipt(
+            &ctx.accounts.config,
+            &ctx.accounts.receipt,
+            ctx.accounts.buyer.to_account_info(),
+            &ctx.accounts.system_program,
+            order,
+            late_fee,
+        )?;
+
+        emit!(SoldAuctionEvent {
+            order_key: *order.to_account_info().key,
+            buyer: *ctx.accounts.buyer.to_account_info().key,
+            price,
+            debt,
+            protocol_fee,
+            surplus,
+        });
+
+        Ok(())
+    }
+
+    pub fn start_english_auction(ctx: Context<StartEnglishAuction>, _order_id: u64) -> Result<()> {
+        let config = &ctx.accounts.config;
+        let order = &mut ctx.accounts.order;
+        if config.auction_duration == 0 {
+            return Err(ErrorCode::AuctionsDisabled.into());
+        }
+
+        let now = clock::Clock::get().unwrap().unix_timestamp as u64;
+        order.check_liquidatable(now)?;
+
+        order.state = OrderState::Auction;
+        order.auction_kind = AuctionKind::English;
+        order.auction_start_time = now;
+        order.auction_end_time = math::add(now, config.auction_duration)?;
+        order.bid_increment_rate = config.bid_increment_rate;
+        order.anti_snipe_period = config.anti_snipe_period;
+        let debt = order.auction_debt()?;
+
+        emit!(StartedAuctionEvent {
+            order_key: *order.to_account_info().key,
+            lender: order.lender,
+            debt,
+            start_price: debt,
+            end_time: order.auction_end_time,
+        });
+
+        Ok(())
+    }
+
+    pub fn place_bid(ctx: Context<PlaceBid>, _order_id: u64, _escrow_nonce: u8, amount: u64) -> Result<()> {
+        let order = &mut ctx.accounts.order;
+        if order.state != OrderState::Auction {
+            return Err(ErrorCode::NoAuction.into());
+        }
+        if order.auction_kind != AuctionKind::English {
+            return Err(ErrorCode::WrongAuctionKind.into());
+        }
+        let now = clock::Clock::get().unwrap().unix_timestamp as u64;
+        if now >= order.auction_end_time {
+            return Err(ErrorCode::AuctionEnded.into());
+        }
+        if amount < order.min_bid()? {
+            return Err(ErrorCode::BidTooLow.into());
+        }
+
+        // Escrow the new bid
+        {
+            let cpi_ctx = CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                token::Transfer {
+                    from: ctx.accounts.bidder_stable_coin_vault.to_account_info(),
+                    to: ctx.accounts.bid_escrow.to_account_info(),
+                    authority: ctx.accounts.bidder.to_account_info(),
+                },
+            );
+            token::transfer(cpi_ctx, amount)?;
+        }
+
+        // Refund the outbid bidder
+        if order.highest_bid > 0 {
+            if ctx.accounts.previous_bidder_stable_coin_vault.owner != order.highest_bidder {
+                return Err(ErrorCode::InvalidBidRefund.into());
+            }
+            transfer_from_bid_escrow(
+                &ctx.accounts.token_program,
+                &order.key(),
+                &ctx.accounts.bid_escrow,
+                ctx.accounts.previous_bidder_stable_coin_vault.to_account_info(),
+                _escrow_nonce,
+                order.highest_bid,
+            )?;
+        }
+
+        order.highest_bid = amount;
+        order.highest_bidder = ctx.accounts.bidder.key();
+        // Late bids push the end back so others get a chance to answer
+        let snipe_end = math::add(now, order.anti_snipe_period)?;
+        if order.auction_end_time < snipe_end {
+            order.auction_end_time = snipe_end;
+        }
+
+        emit!(PlacedBidEvent {
+            order_key: *order.to_account_info().key,
+            bidder: order.highest_bidder,
+            amount,
+            end_time: order.auction_end_time,
+        });
+
+        Ok(())
+    }
+
+    pub fn settle_auction(
+        ctx: Context<SettleAuction>,
+        _order_id: u64,
+        _stable_nonce: u8,
+        _nft_nonce: u8,
+        _escrow_nonce: u8,
+    ) -> Result<()> {
+        let order = &mut ctx.accounts.order;
+        let market = &mut ctx.accounts.market;
+        if order.state != OrderState::Auction {
+            return Err(ErrorCode::NoAuction.into());
+        }
+        if order.auction_kind != AuctionKind::English {
+            return Err(ErrorCode::WrongAuctionKind.into());
+        }
+        let now = clock::Clock::get().unwrap().unix_timestamp as u64;
+        if order.auction_end_time > now {
+            return Err(ErrorCode::AuctionInProgress.into());
+        }
+
+        let order_key = order.key();
+        let price = order.highest_bid;
+        let sold = price > 0;
+        // Without bids the lender takes the collateral
+        let winner = if sold { order.highest_bidder } else { order.lender };
+        if ctx.accounts.winner_nft_vault.owner != winner {
+            return Err(ErrorCode::InvalidAuctionWinner.into());
+        }
+
+        let interest = order.interest_due(order.auction_start_time)?;
+        let late_fee = order.late_fee(order.auction_start_time)?;
+        let mut protocol_fee = 0;
+        let mut surplus = 0;
+        let debt = order.auction_debt()?;
+        if sold {
+            protocol_fee = math::bps_of(interest, order.protocol_fee_rate)?;
+            surplus = math::sub(price, debt)?;
+
+            // Pay the lender
+            transfer_from_bid_escrow(
+                &ctx.accounts.token_program,
+                &order_key,
+                &ctx.accounts.bid_escrow,
+                ctx.accounts.lender_stable_coin_vault.to_account_info(),
+                _escrow_nonce,
+                math::sub(debt, protocol_fee)?,
+            )?;
+
+            // Protocol fee on the interest
+            if protocol_fee > 0 {
+                transfer_from_bid_escrow(
+                    &ctx.accounts.token_program,
+                    &order_key,
+                    &ctx.accounts.bid_escrow,
+                    ctx.accounts.treasury_vault.to_account_info(),
+                    _escrow_nonce,
+                    protocol_fee,
+                )?;
+            }
+
+            // Surplus goes back to the borrower
+            if surplus > 0 {
+                transfer_from_bid_escrow(
+                    &ctx.accounts.token_program,
+                    &order_key,
+                    &ctx.accounts.bid_escrow,
+                    ctx.accounts.borrower_stable_coin_vault.to_account_info(),
+                    _escrow_nonce,
+                    surplus,
+                )?;
+            }
+        }
+
+        // Tokens sent to the escrow outside of bids would block closing it, they go to the borrower
+        ctx.accounts.bid_escrow.reload()?;
+        let leftover = ctx.accounts.bid_escrow.amount;
+        if leftover > 0 {
+            transfer_from_bid_escrow(
+                &ctx.accounts.token_program,
+                &order_key,
+                &ctx.accounts.bid_escrow,
+                ctx.accounts.borrower_stable_coin_vault.to_account_info(),
+                _escrow_nonce,
+                leftover,
+            )?;
+            surplus = math::add(surplus, leftover)?;
+        }
+
+        // Close the emptied escrow, refunding rent to the lender who opened it
+        {
+            let seeds = &[
+                order_key.as_ref(),
+                constants::BID_ESCROW_PDA_SEED.as_ref(),
+                &[_escrow_nonce]
+            ];
             let signer = &[&seeds[..]];
+            let cpi_ctx = CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                token::CloseAccount {
+                    account: ctx.accounts.bid_escrow.to_account_info(),
+                    destination: ctx.accounts.lender.to_account_info(),
+                    authority: ctx.accounts.bid_escrow.to_account_info(),
+                },
+                signer,
+            );
+            token::close_account(cpi_ctx)?;
+        }
+
+        // Transfer nft collateral to the winner and close nft_vault.
+        release_nft(
+            &ctx.accounts.token_program,
+            &ctx.accounts.nft_mint,
+            &ctx.accounts.nft_vault,
+            ctx.accounts.winner_nft_vault.to_account_info(),
+            ctx.accounts.borrower.to_account_info(),
+            _nft_nonce,
+        )?;
+
+        // Additional collateral goes back to the borrower once the debt is paid, to the lender otherwise
+        let collateral_destination = if sold {
+            ctx.accounts.borrower_stable_coin_vault.to_account_info()
+        } else {
+            ctx.accounts.lender_stable_coin_vault.to_account_info()
+        };
+        transfer_from_vault(
+            &ctx.accounts.token_program,
+            &ctx.accounts.stable_coin_mint,
+            &ctx.accounts.stable_coin_vault,
+            collateral_destination,
+            _stable_nonce,
+            order.additional_collateral,
+        )?;
+        market.total_additional_collateral = math::sub(market.total_additional_collateral, order.additional_collateral)?;
+        if sold {
+            order.amount_repaid = math::add(order.amount_repaid, math::add(order.outstanding_principal()?, interest)?)?;
+            order.principal_repaid = order.request_amount;
+        }
+        order.state = OrderState::Liquidated;
+        order.withdrew_at = now;
+        write_receipt(
+            &ctx.accounts.config,
+            &ctx.accounts.receipt,
+            ctx.accounts.payer.to_account_info(),
+            &ctx.accounts.system_program,
+            order,
+            if sold { late_fee } else { 0 },
+        )?;
+
+        emit!(SettledAuctionEvent {
+            order_key,
+            winner,
+            price,
+            debt,
+            protocol_fee,
+            surplus,
+        });
+
+        Ok(())
+    }
+
+    pub fn set_redemption_window(
+        ctx: Context<SetRedemptionWindow>,
+        _order_id: u64,
+        redemption_period: u64,
+        redemption_penalty_rate: u64,
+    ) -> Result<()> {
+        let order = &mut ctx.accounts.order;
+        order.check_state(OrderState::Open)?;
+        // zero period removes the window
+        if redemption_period == 0 && redemption_penalty_rate != 0 {
+            return Err(ErrorCode::InvalidRedemptionWindow.into());
+        }
+
+        order.redemption_period = redemption_period;
+        order.redemption_penalty_rate = redemption_penalty_rate;
+        order.terms_version = math::add(order.terms_version, 1)?;
+
+        emit!(UpdatedRedemptionWindowEvent {
+            order_key: *order.to_account_info().key,
+            borrower: order.borrower,
+            redemption_period,
+            redemption_penalty_rate,
+            terms_version: order.terms_version,
+        });
+
+        Ok(())
+    }
+
+    pub fn redeem(ctx: Context<Redeem>, _order_id: u64, _stable_nonce: u8, _nft_nonce: u8) -> Result<()> {
+        let order = &mut ctx.accounts.order;
+        let market = &mut ctx.accounts.market;
+
+        order.check_state(OrderState::Active)?;
+        if order.redemption_period == 0 {
+            return Err(ErrorCode::NoRedemptionWindow.into());
+        }
+        // Loans that are not in default are closed with payback
+        let now = clock::Clock::get().unwrap().unix_timestamp as u64;
+        if !order.is_defaulted(now)? {
+            return Err(ErrorCode::RepaymentPeriodNotExceeded.into());
+        }
+        if order.redemption_deadline()? < now {
+            return Err(ErrorCode::RedemptionWindowClosed.into());
+        }
+
+        let interest = order.interest_due(now)?;
+        let protocol_fee = math::bps_of(interest, order.protocol_fee_rate)?;
+        let late_fee = order.late_fee(now)?;
+        let debt = math::add(order.outstanding_principal()?, interest)?;
+        let penalty = math::bps_of(debt, order.redemption_penalty_rate)?;
+
+        // Pay debt, late fee and penalty to the lender
+        {
+            let cpi_ctx = CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                token::Transfer {
+                    from: ctx.accounts.borrower_stable_coin_vault.to_account_info(),
+                    to: ctx.accounts.lender_stable_coin_vault.to_account_info(),
+                    authority: ctx.accounts.borrower.to_account_info(),
+                },
+            );
+            let amount = math::add(math::sub(debt, protocol_fee)?, math::add(late_fee, penalty)?)?;
+            token::transfer(cpi_ctx, amount)?;
+        }
+
+        // Protocol fee on the interest
+        if protocol_fee > 0 {
+            let cpi_ctx = CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                token::Transfer {
+                    from: ctx.accounts.borrower_stable_coin_vault.to_account_info(),
+                    to: ctx.accounts.treasury_vault.to_account_info(),
+                    authority: ctx.accounts.borrower.to_account_info(),
+                },
+            );
+            token::transfer(cpi_ctx, protocol_fee)?;
+        }
+
+        // Transfer back nft collateral and close nft_vault.
+        release_nft(
+            &ctx.accounts.token_program,
+            &ctx.accounts.nft_mint,
+            &ctx.accounts.nft_vault,
+            ctx.accounts.borrower_nft_vault.to_account_info(),
+            ctx.accounts.borrower.to_account_info(),
+            _nft_nonce,
+        )?;
+
+        // Transfer back additional collateral
+        transfer_from_vault(
+            &ctx.accounts.token_program,
+            &ctx.accounts.stable_coin_mint,
+            &ctx.accounts.stable_coin_vault,
+            ctx.accounts.borrower_stable_coin_vault.to_account_info(),
+            _stable_nonce,
+            order.additional_collateral,
+        )?;
+        market.total_additional_collateral = math::sub(market.total_additional_collateral, order.additional_collateral)?;
+        order.amount_repaid = math::add(order.amount_repaid, debt)?;
+        order.principal_repaid = order.request_amount;
+        order.state = OrderState::Repaid;
+        order.paid_back_at = now;
+        write_receipt(
+            &ctx.accounts.config,
+            &ctx.accounts.receipt,
+            ctx.accounts.borrower.to_account_info(),
+            &ctx.accounts.system_program,
+            order,
+            late_fee,
+        )?;
+
+        emit!(RedeemedOrderEvent {
+            order_key: *order.to_account_info().key,
+            borrower: *ctx.accounts.borrower.to_account_info().key,
+            interest,
+            protocol_fee,
+            late_fee,
+            penalty,
+        });
+
+        Ok(())
+    }
+}
+
+// Keep a receipt of the settled order when the configuration asks for it. The account paying
+// the rent can reclaim it with `close_receipt`.
+fn write_receipt<'info>(
+    config: &Configuration,
+    receipt: &UncheckedAccount<'info>,
+    rent_payer: AccountInfo<'info>,
+    system_program: &Program<'info, System>,
+    order: &Account<'info, Order>,
+    late_fee: u64,
+) -> Result<()> {
+    if !config.keep_receipts {
+        return Ok(());
+    }
+    let order_key = order.key();
+    let (address, bump) = Pubkey::find_program_address(
+        &[order_key.as_ref(), constants::RECEIPT_PDA_SEED.as_ref()],
+        &crate::ID,
+    );
+    if receipt.key() != address {
+        return Err(ErrorCode::InvalidReceipt.into());
+    }
+
+    let data = LoanReceipt {
+        order: order_key,
+        order_id: order.order_id,
+        borrower: order.borrower,
+        lender: order.lender,
+        market: order.market,
+        nft_mint: order.nft_mint,
+        collection: order.collection,
+        state: order.state,
+        request_amount: order.request_amount,
+        interest_rate: order.interest_rate,
+        period: order.period,
+        additional_collateral: order.additional_collateral,
+        amount_repaid: order.amount_repaid,
+        principal_repaid: order.principal_repaid,
+        late_fee,
+        created_at: order.created_at,
+        loan_start_time: order.loan_start_time,
+        paid_back_at: order.paid_back_at,
+        withdrew_at: order.withdrew_at,
+        closed_at: clock::Clock::get()?.unix_timestamp as u64,
+        rent_payer: rent_payer.key(),
+        nonce: bump,
+    };
+    let space = 8 + data.try_to_vec()?.len();
+    let seeds = &[
+        order_key.as_ref(),
+        constants::RECEIPT_PDA_SEED.as_ref(),
+        &[bump]
+    ];
+    let signer = &[&seeds[..]];
+
+    // Fund, allocate and assign separately so a pre-funded address can't block the creation
+    let rent = Rent::get()?.minimum_balance(space);
+    if receipt.lamports() < rent {
+        transfer_lamports(
+            rent_payer,
+            receipt.to_account_info(),
+            system_program.to_account_info(),
+            rent - receipt.lamports(),
+        )?;
+    }
+    invoke_signed(
+        &system_instruction::allocate(&address, space as u64),
+        &[receipt.to_account_info(), system_program.to_account_info()],
+        signer,
+    )?;
+    invoke_signed(
+        &system_instruction::assign(&address, &crate::ID),
+        &[receipt.to_account_info(), system_program.to_account_info()],
+        signer,
+    )?;
+
+    let mut buf = receipt.try_borrow_mut_data()?;
+    let mut writer: &mut [u8] = &mut buf;
+    data.try_serialize(&mut writer)
+}
+
+// Snapshot the floor price of the collateral when the loan starts and enforce the maximum loan-to-value.
+// The price must come from the feed registered for the collection. Does nothing while the oracle is disabled.
+fn value_collateral(
+    config: &Configuration,
+    collection_whitelist: &AccountInfo,
+    price_feed: &AccountInfo,
+    order: &mut Order,
+    decimals: u8,
+) -> Result<()> {
+    if config.oracle_program == Pubkey::default() {
+        return Ok(());
+    }
+    let floor = oracle::floor_price(price_feed, &config.oracle_program, &order.collection, decimals)?;
+    let registered = CollectionWhitelist::registered_price_feed(collection_whitelist, &order.collection, &order.market)?;
+    if price_feed.key() != registered {
+        return Err(ErrorCode::InvalidPriceFeed.into());
+    }
+    if math::add(floor.publish_time, config.max_price_age)? < order.loan_start_time {
+        return Err(ErrorCode::StalePrice.into());
+    }
+    order.price_feed = registered;
+    order.floor_price = floor.price;
+    order.floor_price_time = floor.publish_time;
+    order.maintenance_ltv = config.maintenance_ltv;
+    if config.max_ltv > 0 && order.loan_to_value(order.outstanding_principal()?, floor.price)? > config.max_ltv {
+        return Err(ErrorCode::LoanToValueTooHigh.into());
+    }
+    Ok(())
+}
+
+// Current loan-to-value of a loan that is not in default, failing unless it reached the maintenance threshold.
+// Loans valued without a threshold or with a redemption window can only be liquidated after default, the
+// price is read from the feed the loan was valued with.
+fn check_maintenance_ltv(
+    config: &Configuration,
+    price_feed: &AccountInfo,
+    order: &Order,
+    decimals: u8,
+    now: u64,
+) -> Result<u64> {
+    if order.maintenance_ltv == 0 || order.redemption_period > 0 {
+        return Err(ErrorCode::RepaymentPeriodNotExceeded.into());
+    }
+    if price_feed.key() != order.price_feed {
+        return Err(ErrorCode::InvalidPriceFeed.into());
+    }
+    let floor = oracle::floor_price(price_feed, &config.oracle_program, &order.collection, decimals)?;
+    if math::add(floor.publish_time, config.max_price_age)? < now {
+        return Err(ErrorCode::StalePrice.into());
+    }
+    // Interest keeps adding to the debt until it is repaid
+    let debt = math::add(order.outstanding_principal()?, order.interest_due(now)?)?;
+    let loan_to_value = order.loan_to_value(debt, floor.price)?;
+    if loan_to_value < order.maintenance_ltv {
+        return Err(ErrorCode::LoanToValueHealthy.into());
+    }
+    Ok(loan_to_value)
+}
+
+// Stablecoins or lamports moved by an order. Spl orders keep their additional collateral in the market's
+// stablecoin vault, native SOL orders keep it in the order account itself.
+enum Funds<'a, 'info> {
+    Token {
+        token_program: &'a Program<'info, Token>,
+        stable_coin_mint: &'a Account<'info, Mint>,
+        stable_coin_vault: &'a Account<'info, TokenAccount>,
+        stable_nonce: u8,
+    },
+    Native {
+        system_program: &'a Program<'info, System>,
+        order: AccountInfo<'info>,
+    },
+}
+
+impl<'a, 'info> Funds<'a, 'info> {
+    fn is_native(&self) -> bool {
+        matches!(self, Funds::Native { .. })
+    }
+
+    // Account holding the additional collateral, recorded on spl orders only.
+    fn vault(&self) -> Pubkey {
+        match self {
+            Funds::Token { stable_coin_vault, .. } => stable_coin_vault.key(),
+            Funds::Native { .. } => Pubkey::default(),
+        }
+    }
+
+    // Transfer out of a user's wallet or token account, `authority` must sign the transaction.
+    // Lamports are paid by `from` itself, which must sign instead.
+    fn pay(&self, from: AccountInfo<'info>, to: AccountInfo<'info>, authority: AccountInfo<'info>, amount: u64) -> Result<()> {
+        match self {
+            Funds::Token { token_program, .. } => {
+                let cpi_ctx = CpiContext::new(
+                    token_program.to_account_info(),
+                    token::Transfer { from, to, authority },
+                );
+                token::transfer(cpi_ctx, amount)
+            }
+            Funds::Native { system_program, .. } => {
+                transfer_lamports(from, to, system_program.to_account_info(), amount)
+            }
+        }
+    }
+
+    // Lock additional collateral.
+    fn deposit(&self, from: AccountInfo<'info>, authority: AccountInfo<'info>, amount: u64) -> Result<()> {
+        let to = match self {
+            Funds::Token { stable_coin_vault, .. } => stable_coin_vault.to_account_info(),
+            Funds::Native { order, .. } => order.clone(),
+        };
+        self.pay(from, to, authority, amount)
+    }
+
+    // Release additional collateral.
+    fn release(&self, to: AccountInfo<'info>, amount: u64) -> Result<()> {
+        match self {
+            Funds::Token { token_program, stable_coin_mint, stable_coin_vault, stable_nonce } => {
+                transfer_from_vault(token_program, stable_coin_mint, stable_coin_vault, to, *stable_nonce, amount)
+            }
+            Funds::Native { order, .. } => withdraw_lamports(order.clone(), to, amount),
+        }
+    }
+
+    // Pay a protocol fee to the treasury: the treasury vault of an spl market, or the market account
+    // itself for native SOL, which keeps count of the fees in `sol_treasury`.
+    fn pay_fee(
+        &self,
+        from: AccountInfo<'info>,
+        treasury: AccountInfo<'info>,
+        authority: AccountInfo<'info>,
+        market: &mut Market,
+        amount: u64,
+    ) -> Result<()> {
+        if amount == 0 {
+            return Ok(());
+        }
+        self.pay(from, treasury, authority, amount)?;
+        if self.is_native() {
+            market.sol_treasury = math::add(market.sol_treasury, amount)?;
+        }
+        Ok(())
+    }
+}
+
+// Lock the additional collateral of a new order and save its terms.
+fn open_order<'info>(
+    funds: &Funds<'_, 'info>,
+    config: &mut Configuration,
+    market: &mut Account<'info, Market>,
+    order: &mut Account<'info, Order>,
+    borrower: AccountInfo<'info>,
+    borrower_funds: AccountInfo<'info>,
+    nft_vault: &Account<'info, TokenAccount>,
+    collection: Pubkey,
+    request_amount: u64,
+    interest_rate: u64,
+    period: u64,
+    additional_collateral: u64,
+    order_nonce: u8,
+) -> Result<()> {
+    // Transfer additional collateral to vault
+    funds.deposit(borrower_funds, borrower.clone(), additional_collateral)?;
+    let clock = clock::Clock::get().unwrap();
+
+    // Save Info
+    order.borrower = borrower.key();
+    order.market = market.key();
+    order.stable_coin_vault = funds.vault();
+    order.nft_mint = nft_vault.mint;
+    order.nft_vault = nft_vault.key();
+    order.collection = collection;
+    order.request_amount = request_amount;
+    order.payback_amount = math::add(request_amount, math::accrued_interest(request_amount, interest_rate, period)?)?;
+    order.interest_rate = interest_rate;
+    order.min_interest = config.min_interest(request_amount, interest_rate, period)?;
+    order.period = period;
+    order.additional_collateral = additional_collateral;
+    order.lender = order.key(); // just a placeholder
+    order.created_at = clock.unix_timestamp as u64;
+    order.expires_at = config.expires_at(order.created_at)?;
+    order.loan_start_time = 0; // placeholder
+    order.paid_back_at = 0;
+    order.withdrew_at = 0;
+    order.order_id = config.order_id;
+    order.is_native = funds.is_native();
+    order.nonce = order_nonce;
+
+    market.total_additional_collateral = math::add(market.total_additional_collateral, additional_collateral)?;
+    config.order_id += 1;
+
+    order.state = OrderState::Open;
+
+    emit!(CreatedOrderEvent {
+        order_key: *order.to_account_info().key,
+        borrower: order.borrower,
+        collection: order.collection,
+    });
+
+    Ok(())
+}
+
+// Send the loan to the borrower, withhold the origination fee and start the loan. Returns the origination fee.
+fn fund_loan<'info>(
+    funds: &Funds<'_, 'info>,
+    config: &Configuration,
+    market: &mut Market,
+    order: &mut Order,
+    lender: AccountInfo<'info>,
+    lender_funds: AccountInfo<'info>,
+    borrower_funds: AccountInfo<'info>,
+    treasury: AccountInfo<'info>,
+    now: u64,
+) -> Result<u64> {
+    let origination_fee = math::bps_of(order.request_amount, config.origination_fee_rate)?;
+    funds.pay(
+        lender_funds.clone(),
+        borrower_funds,
+        lender.clone(),
+        math::sub(order.request_amount, origination_fee)?,
+    )?;
+    funds.pay_fee(lender_funds, treasury, lender.clone(), market, origination_fee)?;
+
+    order.lender = lender.key();
+    order.loan_start_time = now;
+    order.protocol_fee_rate = config.protocol_fee_rate;
+    order.grace_period = config.grace_period;
+    order.late_fee_rate = config.late_fee_rate;
+    order.state = OrderState::Active;
+    Ok(origination_fee)
+}
+
+// Pay the outstanding principal, the interest and the late fee to the lender and the protocol fee on the
+// interest to the treasury. Returns the interest, protocol fee and late fee.
+fn repay_loan<'info>(
+    funds: &Funds<'_, 'info>,
+    market: &mut Market,
+    order: &mut Order,
+    borrower: AccountInfo<'info>,
+    borrower_funds: AccountInfo<'info>,
+    lender_funds: AccountInfo<'info>,
+    treasury: AccountInfo<'info>,
+    now: u64,
+) -> Result<(u64, u64, u64)> {
+    let interest = order.interest_due(now)?;
+    let protocol_fee = math::bps_of(interest, order.protocol_fee_rate)?;
+    let late_fee = order.late_fee(now)?;
+
+    let amount = math::add(order.outstanding_principal()?, math::sub(interest, protocol_fee)?)?;
+    funds.pay(borrower_funds.clone(), lender_funds, borrower.clone(), math::add(amount, late_fee)?)?;
+    funds.pay_fee(borrower_funds, treasury, borrower, market, protocol_fee)?;
+
+    order.amount_repaid = math::add(order.amount_repaid, math::add(order.outstanding_principal()?, interest)?)?;
+    order.principal_repaid = order.request_amount;
+    Ok((interest, protocol_fee, late_fee))
+}
+
+// Repay part of an active loan. Repayments settle the accrued interest first, the rest reduces the principal.
+// Clearing the whole balance goes through payback, which releases the nft.
+fn repay_part<'info>(
+    funds: &Funds<'_, 'info>,
+    market: &mut Market,
+    order: &mut Account<'info, Order>,
+    borrower: AccountInfo<'info>,
+    borrower_funds: AccountInfo<'info>,
+    lender_funds: AccountInfo<'info>,
+    treasury: AccountInfo<'info>,
+    amount: u64,
+) -> Result<()> {
+    order.check_state(OrderState::Active)?;
+
+    let clock = clock::Clock::get().unwrap();
+    let now = clock.unix_timestamp as u64;
+    if order.deadline()? < now {
+        return Err(ErrorCode::RepaymentPeriodExceeded.into());
+    }
+
+    let interest = order.accrued_interest(now)?;
+    if amount < interest {
+        return Err(ErrorCode::RepaymentTooSmall.into());
+    }
+    let principal = amount - interest;
+    let outstanding = order.outstanding_principal()?;
+    if principal >= outstanding {
+        return Err(ErrorCode::RepaymentExceedsBalance.into());
+    }
+    let protocol_fee = math::bps_of(interest, order.protocol_fee_rate)?;
+
+    // Pay the lender, and the protocol fee on the interest
+    funds.pay(borrower_funds.clone(), lender_funds, borrower.clone(), amount - protocol_fee)?;
+    funds.pay_fee(borrower_funds, treasury, borrower, market, protocol_fee)?;
+
+    order.record_repayment(principal, interest, now)?;
+
+    emit!(RepaidOrderEvent {
+        order_key: *order.to_account_info().key,
+        borrower: order.borrower,
+        principal,
+        interest,
+        protocol_fee,
+        outstanding_principal: order.outstanding_principal()?,
+    });
+
+    Ok(())
+}
+
+// Transfer the nft and the additional collateral out of the order, refunding the nft vault's rent to the borrower.
+fn release_collateral<'info>(
+    funds: &Funds<'_, 'info>,
+    market: &mut Market,
+    order: &Order,
+    token_program: &Program<'info, Token>,
+    nft_mint: &Account<'info, Mint>,
+    nft_vault: &Account<'info, TokenAccount>,
+    nft_destination: AccountInfo<'info>,
+    collateral_destination: AccountInfo<'info>,
+    borrower: AccountInfo<'info>,
+    nft_nonce: u8,
+) -> Result<()> {
+    release_nft(token_program, nft_mint, nft_vault, nft_destination, borrower, nft_nonce)?;
+    funds.release(collateral_destination, order.additional_collateral)?;
+    market.total_additional_collateral = math::sub(market.total_additional_collateral, order.additional_collateral)?;
+    Ok(())
+}
+
+// Top up or withdraw the additional collateral of an open order.
+fn adjust_collateral<'info>(
+    funds: &Funds<'_, 'info>,
+    market: &mut Market,
+    order: &Order,
+    borrower: AccountInfo<'info>,
+    borrower_funds: AccountInfo<'info>,
+    additional_collateral: u64,
+) -> Result<()> {
+    let current_collateral = order.additional_collateral;
+    if additional_collateral > current_collateral {
+        funds.deposit(borrower_funds, borrower, additional_collateral - current_collateral)?;
+    } else if additional_collateral < current_collateral {
+        funds.release(borrower_funds, current_collateral - additional_collateral)?;
+    }
+    market.total_additional_collateral = math::add(
+        math::sub(market.total_additional_collateral, current_collateral)?,
+        additional_collateral,
+    )?;
+    Ok(())
+}
+
+// Why the lender can liquidate the loan at `now`, with the loan-to-value it is liquidated at. Before default
+// only a collateral value below the maintenance threshold allows liquidation.
+fn liquidation_trigger(
+    config: &Configuration,
+    price_feed: &AccountInfo,
+    order: &Order,
+    decimals: u8,
+    now: u64,
+) -> Result<(LiquidationTrigger, u64)> {
+    if order.state == OrderState::Active && !order.is_defaulted(now)? {
+        let loan_to_value = check_maintenance_ltv(config, price_feed, order, decimals, now)?;
+        return Ok((LiquidationTrigger::LoanToValue, loan_to_value));
+    }
+    order.check_liquidatable(now)?;
+    Ok((LiquidationTrigger::MissedRepayment, 0))
+}
+
+// Transfer the nft into its vault.
+fn lock_nft<'info>(
+    token_program: &Program<'info, Token>,
+    from: AccountInfo<'info>,
+    nft_vault: &Account<'info, TokenAccount>,
+    authority: AccountInfo<'info>,
+) -> Result<()> {
+    let cpi_ctx = CpiContext::new(
+        token_program.to_account_info(),
+        token::Transfer {
+            from,
+            to: nft_vault.to_account_info(),
+            authority,
+        },
+    );
+    token::transfer(cpi_ctx, 1)
+}
+
+// Transfer the nft out of its vault and close the vault, refunding rent to `rent_receiver`.
+fn release_nft<'info>(
+    token_program: &Program<'info, Token>,
+    nft_mint: &Account<'info, Mint>,
+    nft_vault: &Account<'info, TokenAccount>,
+    destination: AccountInfo<'info>,
+    rent_receiver: AccountInfo<'info>,
+    nft_nonce: u8,
+) -> Result<()> {
+    let nft_mint_key = nft_mint.key();
+    let seeds = &[
+        nft_mint_key.as_ref(),
+        constants::NFT_PDA_SEED.as_ref(),
+        &[nft_nonce]
+    ];
+    let signer = &[&seeds[..]];
+
+    let cpi_ctx = CpiContext::new_with_signer(
+        token_program.to_account_info(),
+        token::Transfer {
+            from: nft_vault.to_account_info(),
+            to: destination,
+            authority: nft_vault.to_account_info(),
+        },
+        signer,
+    );
+    token::transfer(cpi_ctx, 1)?;
+
+    let cpi_ctx = CpiContext::new_with_signer(
+        token_program.to_account_info(),
+        token::CloseAccount {
+            account: nft_vault.to_account_info(),
+            destination: rent_receiver,
+            authority: nft_vault.to_account_info(),
+        },
+        signer,
+    );
+    token::close_account(cpi_ctx)
+}
+
+// Transfer stablecoins out of the market vault.
+fn transfer_from_vault<'info>(
+    token_program: &Program<'info, Token>,
+    stable_coin_mint: &Account<'info, Mint>,
+    stable_coin_vault: &Account<'info, TokenAccount>,
+    destination: AccountInfo<'info>,
+    stable_nonce: u8,
+    amount: u64,
+) -> Result<()> {
+    let stable_coin_mint_key = stable_coin_mint.key();
+    let seeds = &[
+        stable_coin_mint_key.as_ref(),
+        constants::STABLE_COIN_PDA_SEED.as_ref(),
+        &[stable_nonce]
+    ];
+    let signer = &[&seeds[..]];
+
+    let cpi_ctx = CpiContext::new_with_signer(
+        token_program.to_account_info(),
+        token::Transfer {
+            from: stable_coin_vault.to_account_info(),
+            to: destination,
+            authority: stable_coin_vault.to_account_info(),
+        },
+        signer,
+    );
+    token::transfer(cpi_ctx, amount)
+}
+
+// Transfer stablecoins out of the bid escrow of an english auction.
+fn transfer_from_bid_escrow<'info>(
+    token_program: &Program<'info, Token>,
+    order_key: &Pubkey,
+    bid_escrow: &Account<'info, TokenAccount>,
+    destination: AccountInfo<'info>,
+    escrow_nonce: u8,
+    amount: u64,
+) -> Result<()> {
+    let seeds = &[
+        order_key.as_ref(),
+        constants::BID_ESCROW_PDA_SEED.as_ref(),
+        &[escrow_nonce]
+    ];
+    let signer = &[&seeds[..]];
+
+    let cpi_ctx = CpiContext::new_with_signer(
+        token_program.to_account_info(),
+        token::Transfer {
+            from: bid_escrow.to_account_info(),
+            to: destination,
+            authority: bid_escrow.to_account_info(),
+        },
+        signer,
+    );
+    token::transfer(cpi_ctx, amount)
+}
+
+// Transfer lamports out of a system account, `from` must sign the transaction.
+fn transfer_lamports<'info>(
+    from: AccountInfo<'info>,
+    to: AccountInfo<'info>,
+    system_program: AccountInfo<'info>,
+    amount: u64,
+) -> Result<()> {
+    invoke(
+        &system_instruction::transfer(from.key, to.key, amount),
+        &[from, to, system_program],
+    )?;
+    Ok(())
+}
+
+// Transfer lamports out of an account owned by this program.
+fn withdraw_lamports<'info>(from: AccountInfo<'info>, to: AccountInfo<'info>, amount: u64) -> Result<()> {
+    **from.try_borrow_mut_lamports()? = from
+        .lamports()
+        .checked_sub(amount)
+        .ok_or(ErrorCode::MathOverflow)?;
+    **to.try_borrow_mut_lamports()? = to
+        .lamports()
+        .checked_add(amount)
+        .ok_or(ErrorCode::MathOverflow)?;
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct Initialize<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    #[account(
+    init,
+    payer = signer,
+    seeds = [constants::CONFIG_PDA_SEED.as_ref()],
+    bump
+    )]
+    pub configuration: Box<Account<'info, Configuration>>,
+
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+
+}
+
+#[derive(Accounts)]
+pub struct UpdateConfig<'info> {
+    #[account(
+    mut,
+    has_one = admin,
+    seeds = [constants::CONFIG_PDA_SEED.as_ref()],
+    bump = config.nonce
+    )]
+    pub config: Box<Account<'info, Configuration>>,
+
+    pub admin: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CreateMarket<'info> {
+    #[account(
+    has_one = admin,
+    seeds = [constants::CONFIG_PDA_SEED.as_ref()],
+    bump = config.nonce
+    )]
+    pub config: Box<Account<'info, Configuration>>,
+
+    #[account(
+    init,
+    payer = admin,
+    seeds = [stable_coin_mint.key().as_ref(), constants::MARKET_PDA_SEED.as_ref()],
+    bump
+    )]
+    pub market: Box<Account<'info, Market>>,
+
+    pub stable_coin_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+    init,
+    payer = admin,
+    token::mint = stable_coin_mint,
+    token::authority = stable_coin_vault,
+    seeds = [stable_coin_mint.key().as_ref(), constants::STABLE_COIN_PDA_SEED.as_ref()],
+    bump
+    )]
+    pub stable_coin_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+    init,
+    payer = admin,
+    token::mint = stable_coin_mint,
+    token::authority = treasury_vault,
+    seeds = [stable_coin_mint.key().as_ref(), constants::TREASURY_PDA_SEED.as_ref()],
+    bump
+    )]
+    pub treasury_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateMarket<'info> {
+    #[account(
+    has_one = admin,
+    seeds = [constants::CONFIG_PDA_SEED.as_ref()],
+    bump = config.nonce
+    )]
+    pub config: Box<Account<'info, Configuration>>,
+
+    #[account(
+    mut,
+    seeds = [market.stable_coin_mint.as_ref(), constants::MARKET_PDA_SEED.as_ref()],
+    bump = market.nonce
+    )]
+    pub market: Box<Account<'info, Market>>,
+
+    pub admin: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(_stable_nonce: u8)]
+pub struct CreateOrder<'info> {
+    #[account(
+    mut,
+    seeds = [constants::CONFIG_PDA_SEED.as_ref()],
+    bump = config.nonce
+    )]
+    pub config: Box<Account<'info, Configuration>>,
+
+    #[account(
+    mut,
+    has_one = stable_coin_vault,
+    has_one = stable_coin_mint,
+    seeds = [stable_coin_mint.key().as_ref(), constants::MARKET_PDA_SEED.as_ref()],
+    bump = market.nonce
+    )]
+    pub market: Box<Account<'info, Market>>,
+
+    pub stable_coin_mint: Box<Account<'info, Mint>>,
+    #[account(
+    mut,
+    seeds = [stable_coin_mint.key().as_ref(), constants::STABLE_COIN_PDA_SEED.as_ref()],
+    bump = _stable_nonce,
+    )]
+    pub stable_coin_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+    mut,
+    constraint = user_stable_coin_vault.mint == stable_coin_mint.key(),
+    constraint = user_stable_coin_vault.owner == borrower.key(),
+    )]
+    pub user_stable_coin_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+    constraint = nft_mint.supply == 1,
+    constraint = nft_mint.decimals == 0,
+    )]
+    pub nft_mint: Box<Account<'info, Mint>>,
+
+    /// CHECK: metaplex metadata of nft_mint, validated in the instruction
+    pub nft_metadata: UncheckedAccount<'info>,
+
+    /// CHECK: whitelist entry of the nft's collection, validated in the instruction
+    pub collection_whitelist: UncheckedAccount<'info>,
+
+    #[account(
+    init,
+    payer = borrower,
+    token::mint = nft_mint,
+    token::authority = nft_vault,
+    seeds = [nft_mint.key().as_ref(), constants::NFT_PDA_SEED.as_ref()],
+    bump,
+    )]
+    pub nft_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+    mut,
+    constraint = user_nft_vault.mint == nft_mint.key(),
+    constraint = user_nft_vault.owner == borrower.key(),
+    )]
+    pub user_nft_vault: Box<Account<'info, TokenAccount>>,
+    // Order.
+    #[account(
+    init_if_needed,
+    payer = borrower,
+    seeds = [
+    config.order_id.to_string().as_ref(),
+    constants::ORDER_PDA_SEED.as_ref(),
+    ],
+    bump
+    )]
+    pub order: Box<Account<'info, Order>>,
+
+    #[account(mut)]
+    pub borrower: Signer<'info>,
+
+    // misc
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+#[instruction(_order_id: u64, _stable_nonce: u8, _nft_nonce: u8)]
+pub struct CancelOrder<'info> {
+    #[account(
+    seeds = [constants::CONFIG_PDA_SEED.as_ref()],
+    bump = config.nonce
+    )]
+    pub config: Box<Account<'info, Configuration>>,
+
+    #[account(
+    mut,
+    has_one = stable_coin_vault,
+    has_one = stable_coin_mint,
+    seeds = [stable_coin_mint.key().as_ref(), constants::MARKET_PDA_SEED.as_ref()],
+    bump = market.nonce
+    )]
+    pub market: Box<Account<'info, Market>>,
+
+    // Order.
+    #[account(
+    mut,
+    constraint = order.market == market.key(),
+    constraint = order.stable_coin_vault == stable_coin_vault.key(),
+    constraint = order.borrower == borrower.key(),
+    constraint = order.nft_vault == nft_vault.key(),
+    constraint = order.nft_mint == nft_mint.key(),
+    seeds = [
+    _order_id.to_string().as_ref(),
+    constants::ORDER_PDA_SEED.as_ref(),
+    ],
+    close = borrower,
+    bump = order.nonce
+    )]
+    pub order: Box<Account<'info, Order>>,
+
+    pub stable_coin_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+    mut,
+    seeds = [stable_coin_mint.key().as_ref(), constants::STABLE_COIN_PDA_SEED.as_ref()],
+    bump = _stable_nonce,
+    )]
+    pub stable_coin_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+    mut,
+    constraint = user_stable_coin_vault.mint == stable_coin_mint.key(),
+    constraint = user_stable_coin_vault.owner == borrower.key(),
+    )]
+    pub user_stable_coin_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+    constraint = nft_mint.supply == 1,
+    constraint = nft_mint.decimals == 0,
+    )]
+    pub nft_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+    mut,
+    seeds = [nft_mint.key().as_ref(), constants::NFT_PDA_SEED.as_ref()],
+    bump = _nft_nonce,
+    )]
+    pub nft_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+    mut,
+    constraint = user_nft_vault.mint == nft_mint.key(),
+    constraint = user_nft_vault.owner == borrower.key(),
+    )]
+    pub user_nft_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(mut)]
+    pub borrower: Signer<'info>,
+
+    /// CHECK: loan receipt of the order, created in the instruction when receipts are kept
+    #[account(mut)]
+    pub receipt: UncheckedAccount<'info>,
+
+    // misc
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+#[instruction(_order_id: u64, _stable_nonce: u8)]
+pub struct GiveLoan<'info> {
+    #[account(
+    seeds = [constants::CONFIG_PDA_SEED.as_ref()],
+    bump = config.nonce
+    )]
+    pub config: Box<Account<'info, Configuration>>,
+
+    #[account(
+    mut,
+    has_one = stable_coin_vault,
+    has_one = stable_coin_mint,
+    seeds = [stable_coin_mint.key().as_ref(), constants::MARKET_PDA_SEED.as_ref()],
+    bump = market.nonce
+    )]
+    pub market: Box<Account<'info, Market>>,
+
+    // Order.
+    #[account(
+    mut,
+    constraint = order.market == market.key(),
+    constraint = order.stable_coin_vault == stable_coin_vault.key(),
+    constraint = order.borrower != lender.key(),
+    seeds = [
+    _order_id.to_string().as_ref(),
+    constants::ORDER_PDA_SEED.as_ref(),
+    ],
+    bump = order.nonce
+    )]
+    pub order: Box<Account<'info, Order>>,
+
+    pub stable_coin_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+    mut,
+    seeds = [stable_coin_mint.key().as_ref(), constants::STABLE_COIN_PDA_SEED.as_ref()],
+    bump = _stable_nonce,
+    )]
+    pub stable_coin_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+    mut,
+    address = market.treasury_vault,
+    )]
+    pub treasury_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+    mut,
+    constraint = lender_stable_coin_vault.mint == stable_coin_mint.key(),
+    constraint = lender_stable_coin_vault.owner == lender.key(),
+    )]
+    pub lender_stable_coin_vault: Box<Account<'info, TokenAccount>>,
+    #[account(
+    mut,
+    constraint = borrower_stable_coin_vault.mint == stable_coin_mint.key(),
+    constraint = borrower_stable_coin_vault.owner == order.borrower,
+    )]
+    pub borrower_stable_coin_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(mut)]
+    pub lender: Signer<'info>,
+
+    /// CHECK: whitelist entry of the order's collection, holds its registered price feed, validated in the instruction
+    pub collection_whitelist: UncheckedAccount<'info>,
+
+    /// CHECK: floor price feed of the nft's collection, validated in the instruction when the oracle is enabled
+    pub price_feed: UncheckedAccount<'info>,
+
+    // misc
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+#[instruction(_order_id: u64, _stable_nonce: u8, _nft_nonce: u8)]
+pub struct Payback<'info> {
+    #[account(
+    seeds = [constants::CONFIG_PDA_SEED.as_ref()],
+    bump = config.nonce
+    )]
+    pub config: Box<Account<'info, Configuration>>,
+
+    #[account(
+    mut,
+    has_one = stable_coin_vault,
+    has_one = stable_coin_mint,
+    seeds = [stable_coin_mint.key().as_ref(), constants::MARKET_PDA_SEED.as_ref()],
+    bump = market.nonce
+    )]
+    pub market: Box<Account<'info, Market>>,
+
+    // Order.
+    #[account(
+    mut,
+    constraint = order.market == market.key(),
+    constraint = order.stable_coin_vault == stable_coin_vault.key(),
+    constraint = order.borrower == borrower.key(),
+    constraint = order.nft_vault == nft_vault.key(),
+    constraint = order.nft_mint == nft_mint.key(),
+    seeds = [
+    _order_id.to_string().as_ref(),
+    constants::ORDER_PDA_SEED.as_ref(),
+    ],
+    close = borrower,
+    bump = order.nonce
+    )]
+    pub order: Box<Account<'info, Order>>,
+
+    pub stable_coin_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+    mut,
+    seeds = [stable_coin_mint.key().as_ref(), constants::STABLE_COIN_PDA_SEED.as_ref()],
+    bump = _stable_nonce,
+    )]
+    pub stable_coin_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+    mut,
+    address = market.treasury_vault,
+    )]
+    pub treasury_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+    mut,
+    constraint = lender_stable_coin_vault.mint == stable_coin_mint.key(),
+    constraint = lender_stable_coin_vault.owner == order.lender,
+    )]
+    pub lender_stable_coin_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+    mut,
+    constraint = borrower_stable_coin_vault.mint == stable_coin_mint.key(),
+    constraint = borrower_stable_coin_vault.owner == borrower.key(),
+    )]
+    pub borrower_stable_coin_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+    constraint = nft_mint.supply == 1,
+    constraint = nft_mint.decimals == 0,
+    )]
+    pub nft_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+    mut,
+    seeds = [nft_mint.key().as_ref(), constants::NFT_PDA_SEED.as_ref()],
+    bump = _nft_nonce,
+    )]
+    pub nft_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+    mut,
+    constraint = borrower_nft_vault.mint == nft_mint.key(),
+    constraint = borrower_nft_vault.owner == borrower.key(),
+    )]
+    pub borrower_nft_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(mut)]
+    pub borrower: Signer<'info>,
+
+    /// CHECK: loan receipt of the order, created in the instruction when receipts are kept
+    #[account(mut)]
+    pub receipt: UncheckedAccount<'info>,
+
+    // misc
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+#[instruction(_order_id: u64, _stable_nonce: u8, _nft_nonce: u8)]
+pub struct Liquidate<'info> {
+    #[account(
+    seeds = [constants::CONFIG_PDA_SEED.as_ref()],
+    bump = config.nonce
+    )]
+    pub config: Box<Account<'info, Configuration>>,
+
+    #[account(
+    mut,
+    has_one = stable_coin_vault,
+    has_one = stable_coin_mint,
+    seeds = [stable_coin_mint.key().as_ref(), constants::MARKET_PDA_SEED.as_ref()],
+    bump = market.nonce
+    )]
+    pub market: Box<Account<'info, Market>>,
+
+    // Order.
+    #[account(
+    mut,
+    constraint = order.market == market.key(),
+    constraint = order.stable_coin_vault == stable_coin_vault.key(),
+    has_one = lender,
+    constraint = order.nft_vault == nft_vault.key(),
+    constraint = order.nft_mint == nft_mint.key(),
+    constraint = order.borrower == borrower.key(),
+    seeds = [
+    _order_id.to_string().as_ref(),
+    constants::ORDER_PDA_SEED.as_ref(),
+    ],
+    close = borrower,
+    bump = order.nonce
+    )]
+    pub order: Box<Account<'info, Order>>,
+
+    pub stable_coin_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+    mut,
+    seeds = [stable_coin_mint.key().as_ref(), constants::STABLE_COIN_PDA_SEED.as_ref()],
+    bump = _stable_nonce,
+    )]
+    pub stable_coin_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+    mut,
+    constraint = lender_stable_coin_vault.mint == stable_coin_mint.key(),
+    constraint = lender_stable_coin_vault.owner == lender.key(),
+    )]
+    pub lender_stable_coin_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+    constraint = nft_mint.supply == 1,
+    constraint = nft_mint.decimals == 0,
+    )]
+    pub nft_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+    mut,
+    seeds = [nft_mint.key().as_ref(), constants::NFT_PDA_SEED.as_ref()],
+    bump = _nft_nonce,
+    )]
+    pub nft_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+    mut,
+    constraint = lender_nft_vault.mint == nft_mint.key(),
+    constraint = lender_nft_vault.owner == lender.key(),
+    )]
+    pub lender_nft_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(mut)]
+    /// CHECK: back to borrower
+    pub borrower: AccountInfo<'info>,
+
+    #[account(mut)]
+    pub lender: Signer<'info>,
+
+    /// CHECK: loan receipt of the order, created in the instruction when receipts are kept
+    #[account(mut)]
+    pub receipt: UncheckedAccount<'info>,
+
+    /// CHECK: floor price feed of the nft's collection, only read when liquidating before default
+    pub price_feed: UncheckedAccount<'info>,
+
+    // misc
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct CreateSolOrder<'info> {
+    #[account(
+    mut,
+    seeds = [constants::CONFIG_PDA_SEED.as_ref()],
+    bump = config.nonce
+    )]
+    pub config: Box<Account<'info, Configuration>>,
+
+    #[account(
+    mut,
+    constraint = market.stable_coin_mint == token_constants::NATIVE_MINT_PUBKEY.parse::< Pubkey > ().unwrap() @ ErrorCode::NotNativeMarket,
+    seeds = [market.stable_coin_mint.as_ref(), constants::MARKET_PDA_SEED.as_ref()],
+    bump = market.nonce
+    )]
+    pub market: Box<Account<'info, Market>>,
+
+    #[account(
+    constraint = nft_mint.supply == 1,
+    constraint = nft_mint.decimals == 0,
+    )]
+    pub nft_mint: Box<Account<'info, Mint>>,
+
+    /// CHECK: metaplex metadata of nft_mint, validated in the instruction
+    pub nft_metadata: UncheckedAccount<'info>,
+
+    /// CHECK: whitelist entry of the nft's collection, validated in the instruction
+    pub collection_whitelist: UncheckedAccount<'info>,
+
+    #[account(
+    init,
+    payer = borrower,
+    token::mint = nft_mint,
+    token::authority = nft_vault,
+    seeds = [nft_mint.key().as_ref(), constants::NFT_PDA_SEED.as_ref()],
+    bump,
+    )]
+    pub nft_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+    mut,
+    constraint = user_nft_vault.mint == nft_mint.key(),
+    constraint = user_nft_vault.owner == borrower.key(),
+    )]
+    pub user_nft_vault: Box<Account<'info, TokenAccount>>,
+    // Order.
+    #[account(
+    init_if_needed,
+    payer = borrower,
+    seeds = [
+    config.order_id.to_string().as_ref(),
+    constants::ORDER_PDA_SEED.as_ref(),
+    ],
+    bump
+    )]
+    pub order: Box<Account<'info, Order>>,
+
+    #[account(mut)]
+    pub borrower: Signer<'info>,
+
+    // misc
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+#[instruction(_order_id: u64, _nft_nonce: u8)]
+pub struct CancelSolOrder<'info> {
+    #[account(
+    seeds = [constants::CONFIG_PDA_SEED.as_ref()],
+    bump = config.nonce
+    )]
+    pub config: Box<Account<'info, Configuration>>,
+
+    #[account(
+    mut,
+    seeds = [market.stable_coin_mint.as_ref(), constants::MARKET_PDA_SEED.as_ref()],
+    bump = market.nonce
+    )]
+    pub market: Box<Account<'info, Market>>,
+
+    // Order.
+    #[account(
+    mut,
+    constraint = order.market == market.key(),
+    constraint = order.is_native,
+    constraint = order.borrower == borrower.key(),
+    constraint = order.nft_vault == nft_vault.key(),
+    constraint = order.nft_mint == nft_mint.key(),
+    seeds = [
+    _order_id.to_string().as_ref(),
+    constants::ORDER_PDA_SEED.as_ref(),
+    ],
+    close = borrower,
+    bump = order.nonce
+    )]
+    pub order: Box<Account<'info, Order>>,
+
+    #[account(
+    constraint = nft_mint.supply == 1,
+    constraint = nft_mint.decimals == 0,
+    )]
+    pub nft_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+    mut,
+    seeds = [nft_mint.key().as_ref(), constants::NFT_PDA_SEED.as_ref()],
+    bump = _nft_nonce,
+    )]
+    pub nft_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+    mut,
+    constraint = user_nft_vault.mint == nft_mint.key(),
+    constraint = user_nft_vault.owner == borrower.key(),
+    )]
+    pub user_nft_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(mut)]
+    pub borrower: Signer<'info>,
+
+    /// CHECK: loan receipt of the order, created in the instruction when receipts are kept
+    #[account(mut)]
+    pub receipt: UncheckedAccount<'info>,
+
+    // misc
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+#[instruction(_order_id: u64)]
+pub struct GiveSolLoan<'info> {
+    #[account(
+    seeds = [constants::CONFIG_PDA_SEED.as_ref()],
+    bump = config.nonce
+    )]
+    pub config: Box<Account<'info, Configuration>>,
+
+    #[account(
+    mut,
+    seeds = [market.stable_coin_mint.as_ref(), constants::MARKET_PDA_SEED.as_ref()],
+    bump = market.nonce
+    )]
+    pub market: Box<Account<'info, Market>>,
+
+    // Order.
+    #[account(
+    mut,
+    constraint = order.market == market.key(),
+    constraint = order.is_native,
+    constraint = order.borrower != lender.key(),
+    seeds = [
+    _order_id.to_string().as_ref(),
+    constants::ORDER_PDA_SEED.as_ref(),
+    ],
+    bump = order.nonce
+    )]
+    pub order: Box<Account<'info, Order>>,
+
+    #[account(
+    mut,
+    address = order.borrower,
+    )]
+    /// CHECK: receives the loan
+    pub borrower: AccountInfo<'info>,
+
+    #[account(mut)]
+    pub lender: Signer<'info>,
+
+    /// CHECK: whitelist entry of the order's collection, holds its registered price feed, validated in the instruction
+    pub collection_whitelist: UncheckedAccount<'info>,
+
+    /// CHECK: floor price feed of the nft's collection, validated in the instruction when the oracle is enabled
+    pub price_feed: UncheckedAccount<'info>,
+
+    // misc
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(_order_id: u64, _nft_nonce: u8)]
+pub struct PaybackSol<'info> {
+    #[account(
+    seeds = [constants::CONFIG_PDA_SEED.as_ref()],
+    bump = config.nonce
+    )]
+    pub config: Box<Account<'info, Configuration>>,
+
+    #[account(
+    mut,
+    seeds = [market.stable_coin_mint.as_ref(), constants::MARKET_PDA_SEED.as_ref()],
+    bump = market.nonce
+    )]
+    pub market: Box<Account<'info, Market>>,
+
+    // Order.
+    #[account(
+    mut,
+    constraint = order.market == market.key(),
+    constraint = order.is_native,
+    constraint = order.borrower == borrower.key(),
+    constraint = order.nft_vault == nft_vault.key(),
+    constraint = order.nft_mint == nft_mint.key(),
+    seeds = [
+    _order_id.to_string().as_ref(),
+    constants::ORDER_PDA_SEED.as_ref(),
+    ],
+    close = borrower,
+    bump = order.nonce
+    )]
+    pub order: Box<Account<'info, Order>>,
+
+    #[account(
+    mut,
+    address = order.lender,
+    )]
+    /// CHECK: receives the repayment
+    pub lender: AccountInfo<'info>,
+
+    #[account(
+    constraint = nft_mint.supply == 1,
+    constraint = nft_mint.decimals == 0,
+    )]
+    pub nft_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+    mut,
+    seeds = [nft_mint.key().as_ref(), constants::NFT_PDA_SEED.as_ref()],
+    bump = _nft_nonce,
+    )]
+    pub nft_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+    mut,
+    constraint = borrower_nft_vault.mint == nft_mint.key(),
+    constraint = borrower_nft_vault.owner == borrower.key(),
+    )]
+    pub borrower_nft_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(mut)]
+    pub borrower: Signer<'info>,
+
+    /// CHECK: loan receipt of the order, created in the instruction when receipts are kept
+    #[account(mut)]
+    pub receipt: UncheckedAccount<'info>,
+
+    // misc
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+#[instruction(_order_id: u64, _nft_nonce: u8)]
+pub struct LiquidateSol<'info> {
+    #[account(
+    seeds = [constants::CONFIG_PDA_SEED.as_ref()],
+    bump = config.nonce
+    )]
+    pub config: Box<Account<'info, Configuration>>,
+
+    #[account(
+    mut,
+    seeds = [market.stable_coin_mint.as_ref(), constants::MARKET_PDA_SEED.as_ref()],
+    bump = market.nonce
+    )]
+    pub market: Box<Account<'info, Market>>,
+
+    // Order.
+    #[account(
+    mut,
+    constraint = order.market == market.key(),
+    constraint = order.is_native,
+    has_one = lender,
+    constraint = order.nft_vault == nft_vault.key(),
+    constraint = order.nft_mint == nft_mint.key(),
+    constraint = order.borrower == borrower.key(),
+    seeds = [
+    _order_id.to_string().as_ref(),
+    constants::ORDER_PDA_SEED.as_ref(),
+    ],
+    close = borrower,
+    bump = order.nonce
+    )]
+    pub order: Box<Account<'info, Order>>,
+
+    #[account(
+    constraint = nft_mint.supply == 1,
+    constraint = nft_mint.decimals == 0,
+    )]
+    pub nft_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+    mut,
+    seeds = [nft_mint.key().as_ref(), constants::NFT_PDA_SEED.as_ref()],
+    bump = _nft_nonce,
+    )]
+    pub nft_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+    mut,
+    constraint = lender_nft_vault.mint == nft_mint.key(),
+    constraint = lender_nft_vault.owner == lender.key(),
+    )]
+    pub lender_nft_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(mut)]
+    /// CHECK: back to borrower
+    pub borrower: AccountInfo<'info>,
+
+    #[account(mut)]
+    pub lender: Signer<'info>,
+
+    /// CHECK: loan receipt of the order, created in the instruction when receipts are kept
+    #[account(mut)]
+    pub receipt: UncheckedAccount<'info>,
+
+    /// CHECK: floor price feed of the nft's collection, only read when liquidating before default
+    pub price_feed: UncheckedAccount<'info>,
+
+    // misc
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+#[instruction(_stable_nonce: u8)]
+pub struct CreateOffer<'info> {
+    #[account(
+    mut,
+    seeds = [constants::CONFIG_PDA_SEED.as_ref()],
+    bump = config.nonce
+    )]
+    pub config: Box<Account<'info, Configuration>>,
+
+    #[account(
+    mut,
+    has_one = stable_coin_vault,
+    has_one = stable_coin_mint,
+    seeds = [stable_coin_mint.key().as_ref(), constants::MARKET_PDA_SEED.as_ref()],
+    bump = market.nonce
+    )]
+    pub market: Box<Account<'info, Market>>,
+
+    pub stable_coin_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+    mut,
+    seeds = [stable_coin_mint.key().as_ref(), constants::STABLE_COIN_PDA_SEED.as_ref()],
+    bump = _stable_nonce,
+    )]
+    pub stable_coin_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+    mut,
+    constraint = lender_stable_coin_vault.mint == stable_coin_mint.key(),
+    constraint = lender_stable_coin_vault.owner == lender.key(),
+    )]
+    pub lender_stable_coin_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+    constraint = nft_mint.supply == 1,
+    constraint = nft_mint.decimals == 0,
+    )]
+    pub nft_mint: Box<Account<'info, Mint>>,
+
+    // Offer.
+    #[account(
+    init,
+    payer = lender,
+    seeds = [
+    config.offer_id.to_string().as_ref(),
+    constants::OFFER_PDA_SEED.as_ref(),
+    ],
+    bump
+    )]
+    pub offer: Box<Account<'info, Offer>>,
+
+    #[account(mut)]
+    pub lender: Signer<'info>,
+
+    // misc
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+#[instruction(_offer_id: u64, _stable_nonce: u8)]
+pub struct CancelOffer<'info> {
+    #[account(
+    seeds = [constants::CONFIG_PDA_SEED.as_ref()],
+    bump = config.nonce
+    )]
+    pub config: Box<Account<'info, Configuration>>,
+
+    #[account(
+    mut,
+    has_one = stable_coin_vault,
+    has_one = stable_coin_mint,
+    seeds = [stable_coin_mint.key().as_ref(), constants::MARKET_PDA_SEED.as_ref()],
+    bump = market.nonce
+    )]
+    pub market: Box<Account<'info, Market>>,
+
+    // Offer.
+    #[account(
+    mut,
+    constraint = offer.market == market.key(),
+    constraint = offer.stable_coin_vault == stable_coin_vault.key(),
+    has_one = lender,
+    seeds = [
+    _offer_id.to_string().as_ref(),
+    constants::OFFER_PDA_SEED.as_ref(),
+    ],
+    close = lender,
+    bump = offer.nonce
+    )]
+    pub offer: Box<Account<'info, Offer>>,
+
+    pub stable_coin_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+    mut,
+    seeds = [stable_coin_mint.key().as_ref(), constants::STABLE_COIN_PDA_SEED.as_ref()],
+    bump = _stable_nonce,
+    )]
+    pub stable_coin_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+    mut,
+    constraint = lender_stable_coin_vault.mint == stable_coin_mint.key(),
+    constraint = lender_stable_coin_vault.owner == lender.key(),
+    )]
+    pub lender_stable_coin_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(mut)]
+    pub lender: Signer<'info>,
+
+    // misc
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+#[instruction(_offer_id: u64, _stable_nonce: u8)]
+pub struct AcceptOffer<'info> {
+    #[account(
+    mut,
+    seeds = [constants::CONFIG_PDA_SEED.as_ref()],
+    bump = config.nonce
+    )]
+    pub config: Box<Account<'info, Configuration>>,
+
+    #[account(
+    mut,
+    has_one = stable_coin_vault,
+    has_one = stable_coin_mint,
+    seeds = [stable_coin_mint.key().as_ref(), constants::MARKET_PDA_SEED.as_ref()],
+    bump = market.nonce
+    )]
+    pub market: Box<Account<'info, Market>>,
+
+    // Offer.
+    #[account(
+    mut,
+    constraint = offer.market == market.key(),
+    constraint = offer.stable_coin_vault == stable_coin_vault.key(),
+    constraint = offer.nft_mint == nft_mint.key(),
+    constraint = offer.lender != borrower.key(),
+    has_one = lender,
+    seeds = [
+    _offer_id.to_string().as_ref(),
+    constants::OFFER_PDA_SEED.as_ref(),
+    ],
+    close = lender,
+    bump = offer.nonce
+    )]
+    pub offer: Box<Account<'info, Offer>>,
+
+    pub stable_coin_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+    mut,
+    seeds = [stable_coin_mint.key().as_ref(), constants::STABLE_COIN_PDA_SEED.as_ref()],
+    bump = _stable_nonce,
+    )]
+    pub stable_coin_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+    mut,
+    address = market.treasury_vault,
+    )]
+    pub treasury_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+    mut,
+    constraint = borrower_stable_coin_vault.mint == stable_coin_mint.key(),
+    constraint = borrower_stable_coin_vault.owner == borrower.key(),
+    )]
+    pub borrower_stable_coin_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+    constraint = nft_mint.supply == 1,
+    constraint = nft_mint.decimals == 0,
+    )]
+    pub nft_mint: Box<Account<'info, Mint>>,
+
+    /// CHECK: metaplex metadata of nft_mint, validated in the instruction
+    pub nft_metadata: UncheckedAccount<'info>,
+
+    /// CHECK: whitelist entry of the nft's collection, validated in the instruction
+    pub collection_whitelist: UncheckedAccount<'info>,
+
+    #[account(
+    init,
+    payer = borrower,
+    token::mint = nft_mint,
+    token::authority = nft_vault,
+    seeds = [nft_mint.key().as_ref(), constants::NFT_PDA_SEED.as_ref()],
+    bump,
+    )]
+    pub nft_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+    mut,
+    constraint = borrower_nft_vault.mint == nft_mint.key(),
+    constraint = borrower_nft_vault.owner == borrower.key(),
+    )]
+    pub borrower_nft_vault: Box<Account<'info, TokenAccount>>,
+
+    // Order.
+    #[account(
+    init_if_needed,
+    payer = borrower,
+    seeds = [
+    config.order_id.to_string().as_ref(),
+    constants::ORDER_PDA_SEED.as_ref(),
+    ],
+    bump
+    )]
+    pub order: Box<Account<'info, Order>>,
+
+    #[account(mut)]
+    /// CHECK: receives the offer rent back
+    pub lender: AccountInfo<'info>,
+
+    #[account(mut)]
+    pub borrower: Signer<'info>,
+
+    /// CHECK: floor price feed of the nft's collection, validated in the instruction when the oracle is enabled
+    pub price_feed: UncheckedAccount<'info>,
+
+    // misc
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+#[instruction(_stable_nonce: u8)]
+pub struct CreateCollectionOffer<'info> {
+    #[account(
+    mut,
+    seeds = [constants::CONFIG_PDA_SEED.as_ref()],
+    bump = config.nonce
+    )]
+    pub config: Box<Account<'info, Configuration>>,
+
+    #[account(
+    mut,
+    has_one = stable_coin_vault,
+    has_one = stable_coin_mint,
+    seeds = [stable_coin_mint.key().as_ref(), constants::MARKET_PDA_SEED.as_ref()],
+    bump = market.nonce
+    )]
+    pub market: Box<Account<'info, Market>>,
+
+    pub stable_coin_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+    mut,
+    seeds = [stable_coin_mint.key().as_ref(), constants::STABLE_COIN_PDA_SEED.as_ref()],
+    bump = _stable_nonce,
+    )]
+    pub stable_coin_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+    mut,
+    constraint = lender_stable_coin_vault.mint == stable_coin_mint.key(),
+    constraint = lender_stable_coin_vault.owner == lender.key(),
+    )]
+    pub lender_stable_coin_vault: Box<Account<'info, TokenAccount>>,
+
+    // Offer.
+    #[account(
+    init,
+    payer = lender,
+    seeds = [
+    config.offer_id.to_string().as_ref(),
+    constants::COLLECTION_OFFER_PDA_SEED.as_ref(),
+    ],
+    bump
+    )]
+    pub offer: Box<Account<'info, CollectionOffer>>,
+
+    #[account(mut)]
+    pub lender: Signer<'info>,
+
+    // misc
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+#[instruction(_offer_id: u64, _stable_nonce: u8)]
+pub struct CancelCollectionOffer<'info> {
+    #[account(
+    seeds = [constants::CONFIG_PDA_SEED.as_ref()],
+    bump = config.nonce
+    )]
+    pub config: Box<Account<'info, Configuration>>,
+
+    #[account(
+    mut,
+    has_one = stable_coin_vault,
+    has_one = stable_coin_mint,
+    seeds = [stable_coin_mint.key().as_ref(), constants::MARKET_PDA_SEED.as_ref()],
+    bump = market.nonce
+    )]
+    pub market: Box<Account<'info, Market>>,
+
+    // Offer.
+    #[account(
+    mut,
+    constraint = offer.market == market.key(),
+    constraint = offer.stable_coin_vault == stable_coin_vault.key(),
+    has_one = lender,
+    seeds = [
+    _offer_id.to_string().as_ref(),
+    constants::COLLECTION_OFFER_PDA_SEED.as_ref(),
+    ],
+    close = lender,
+    bump = offer.nonce
+    )]
+    pub offer: Box<Account<'info, CollectionOffer>>,
+
+    pub stable_coin_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+    mut,
+    seeds = [stable_coin_mint.key().as_ref(), constants::STABLE_COIN_PDA_SEED.as_ref()],
+    bump = _stable_nonce,
+    )]
+    pub stable_coin_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+    mut,
+    constraint = lender_stable_coin_vault.mint == stable_coin_mint.key(),
+    constraint = lender_stable_coin_vault.owner == lender.key(),
+    )]
+    pub lender_stable_coin_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(mut)]
+    pub lender: Signer<'info>,
+
+    // misc
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+#[instruction(_offer_id: u64, _stable_nonce: u8)]
+pub struct AcceptCollectionOffer<'info> {
+    #[account(
+    mut,
+    seeds = [constants::CONFIG_PDA_SEED.as_ref()],
+    bump = config.nonce
+    )]
+    pub config: Box<Account<'info, Configuration>>,
+
+    #[account(
+    mut,
+    has_one = stable_coin_vault,
+    has_one = stable_coin_mint,
+    seeds = [stable_coin_mint.key().as_ref(), constants::MARKET_PDA_SEED.as_ref()],
+    bump = market.nonce
+    )]
+    pub market: Box<Account<'info, Market>>,
+
+    // Offer.
+    #[account(
+    mut,
+    constraint = offer.market == market.key(),
+    constraint = offer.stable_coin_vault == stable_coin_vault.key(),
+    constraint = offer.lender != borrower.key(),
+    seeds = [
+    _offer_id.to_string().as_ref(),
+    constants::COLLECTION_OFFER_PDA_SEED.as_ref(),
+    ],
+    bump = offer.nonce
+    )]
+    pub offer: Box<Account<'info, CollectionOffer>>,
+
+    pub stable_coin_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+    mut,
+    seeds = [stable_coin_mint.key().as_ref(), constants::STABLE_COIN_PDA_SEED.as_ref()],
+    bump = _stable_nonce,
+    )]
+    pub stable_coin_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+    mut,
+    address = market.treasury_vault,
+    )]
+    pub treasury_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+    mut,
+    constraint = borrower_stable_coin_vault.mint == stable_coin_mint.key(),
+    constraint = borrower_stable_coin_vault.owner == borrower.key(),
+    )]
+    pub borrower_stable_coin_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+    constraint = nft_mint.supply == 1,
+    constraint = nft_mint.decimals == 0,
+    )]
+    pub nft_mint: Box<Account<'info, Mint>>,
+
+    /// CHECK: metaplex metadata of nft_mint, validated in the instruction
+    pub nft_metadata: UncheckedAccount<'info>,
+
+    /// CHECK: whitelist entry of the nft's collection, validated in the instruction
+    pub collection_whitelist: UncheckedAccount<'info>,
+
+    #[account(
+    init,
+    payer = borrower,
+    token::mint = nft_mint,
+    token::authority = nft_vault,
+    seeds = [nft_mint.key().as_ref(), constants::NFT_PDA_SEED.as_ref()],
+    bump,
+    )]
+    pub nft_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+    mut,
+    constraint = borrower_nft_vault.mint == nft_mint.key(),
+    constraint = borrower_nft_vault.owner == borrower.key(),
+    )]
+    pub borrower_nft_vault: Box<Account<'info, TokenAccount>>,
+
+    // Order.
+    #[account(
+    init_if_needed,
+    payer = borrower,
+    seeds = [
+    config.order_id.to_string().as_ref(),
+    constants::ORDER_PDA_SEED.as_ref(),
+    ],
+    bump
+    )]
+    pub order: Box<Account<'info, Order>>,
+
+    #[account(mut)]
+    pub borrower: Signer<'info>,
+
+    /// CHECK: floor price feed of the nft's collection, validated in the instruction when the oracle is enabled
+    pub price_feed: UncheckedAccount<'info>,
+
+    // misc
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+#[instruction(_whitelist_nonce: u8, collection: Pubkey)]
+pub struct AddCollection<'info> {
+    #[account(
+    has_one = admin,
+    seeds = [constants::CONFIG_PDA_SEED.as_ref()],
+    bump = config.nonce
+    )]
+    pub config: Box<Account<'info, Configuration>>,
+
+    #[account(
+    seeds = [market.stable_coin_mint.as_ref(), constants::MARKET_PDA_SEED.as_ref()],
+    bump = market.nonce
+    )]
+    pub market: Box<Account<'info, Market>>,
+
+    #[account(
+    init,
+    payer = admin,
+    seeds = [collection.as_ref(), market.key().as_ref(), constants::WHITELIST_PDA_SEED.as_ref()],
+    bump
+    )]
+    pub collection_whitelist: Box<Account<'info, CollectionWhitelist>>,
+
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateCollection<'info> {
+    #[account(
+    has_one = admin,
+    seeds = [constants::CONFIG_PDA_SEED.as_ref()],
+    bump = config.nonce
+    )]
+    pub config: Box<Account<'info, Configuration>>,
+
+    #[account(
+    mut,
+    seeds = [collection_whitelist.collection.as_ref(), collection_whitelist.market.as_ref(), constants::WHITELIST_PDA_SEED.as_ref()],
+    bump = collection_whitelist.nonce
+    )]
+    pub collection_whitelist: Box<Account<'info, CollectionWhitelist>>,
+
+    pub admin: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct RemoveCollection<'info> {
+    #[account(
+    has_one = admin,
+    seeds = [constants::CONFIG_PDA_SEED.as_ref()],
+    bump = config.nonce
+    )]
+    pub config: Box<Account<'info, Configuration>>,
+
+    #[account(
+    mut,
+    seeds = [collection_whitelist.collection.as_ref(), collection_whitelist.market.as_ref(), constants::WHITELIST_PDA_SEED.as_ref()],
+    bump = collection_whitelist.nonce,
+    close = admin
+    )]
+    pub collection_whitelist: Box<Account<'info, CollectionWhitelist>>,
+
+    #[account(mut)]
+    pub admin: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(_treasury_nonce: u8)]
+pub struct WithdrawTreasury<'info> {
+    #[account(
+    has_one = admin,
+    seeds = [constants::CONFIG_PDA_SEED.as_ref()],
+    bump = config.nonce
+    )]
+    pub config: Box<Account<'info, Configuration>>,
+
+    #[account(
+    has_one = treasury_vault,
+    has_one = stable_coin_mint,
+    seeds = [stable_coin_mint.key().as_ref(), constants::MARKET_PDA_SEED.as_ref()],
+    bump = market.nonce
+    )]
+    pub market: Box<Account<'info, Market>>,
+
+    pub stable_coin_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+    mut,
+    seeds = [stable_coin_mint.key().as_ref(), constants::TREASURY_PDA_SEED.as_ref()],
+    bump = _treasury_nonce,
+    )]
+    pub treasury_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+    mut,
+    constraint = admin_stable_coin_vault.mint == stable_coin_mint.key(),
+    )]
+    pub admin_stable_coin_vault: Box<Account<'info, TokenAccount>>,
+
+    pub admin: Signer<'info>,
+
+    // misc
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawSolTreasury<'info> {
+    #[account(
+    has_one = admin,
+    seeds = [constants::CONFIG_PDA_SEED.as_ref()],
+    bump = config.nonce
+    )]
+    pub config: Box<Account<'info, Configuration>>,
+
+    #[account(
+    mut,
+    seeds = [market.stable_coin_mint.as_ref(), constants::MARKET_PDA_SEED.as_ref()],
+    bump = market.nonce
+    )]
+    pub market: Box<Account<'info, Market>>,
+
+    #[account(mut)]
+    pub admin: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(_order_id: u64)]
+pub struct SetInstallmentSchedule<'info> {
+    // Order.
+    #[account(
+    mut,
+    constraint = order.borrower == borrower.key(),
+    constraint = !order.is_native @ ErrorCode::NativeNotSupported,
+    seeds = [
+    _order_id.to_string().as_ref(),
+    constants::ORDER_PDA_SEED.as_ref(),
+    ],
+    bump = order.nonce
+    )]
+    pub order: Box<Account<'info, Order>>,
+
+    pub borrower: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(_order_id: u64, _stable_nonce: u8)]
+pub struct RepayPartial<'info> {
+    #[account(
+    seeds = [constants::CONFIG_PDA_SEED.as_ref()],
+    bump = config.nonce
+    )]
+    pub config: Box<Account<'info, Configuration>>,
+
+    #[account(
+    mut,
+    has_one = stable_coin_vault,
+    has_one = stable_coin_mint,
+    seeds = [stable_coin_mint.key().as_ref(), constants::MARKET_PDA_SEED.as_ref()],
+    bump = market.nonce
+    )]
+    pub market: Box<Account<'info, Market>>,
+
+    // Order.
+    #[account(
+    mut,
+    constraint = order.market == market.key(),
+    constraint = !order.is_native,
+    constraint = order.borrower == borrower.key(),
+    seeds = [
+    _order_id.to_string().as_ref(),
+    constants::ORDER_PDA_SEED.as_ref(),
+    ],
+    bump = order.nonce
+    )]
+    pub order: Box<Account<'info, Order>>,
+
+    pub stable_coin_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+    seeds = [stable_coin_mint.key().as_ref(), constants::STABLE_COIN_PDA_SEED.as_ref()],
+    bump = _stable_nonce,
+    )]
+    pub stable_coin_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+    mut,
+    address = market.treasury_vault,
+    )]
+    pub treasury_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+    mut,
+    constraint = lender_stable_coin_vault.mint == stable_coin_mint.key(),
+    constraint = lender_stable_coin_vault.owner == order.lender,
+    )]
+    pub lender_stable_coin_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+    mut,
+    constraint = borrower_stable_coin_vault.mint == stable_coin_mint.key(),
+    constraint = borrower_stable_coin_vault.owner == borrower.key(),
+    )]
+    pub borrower_stable_coin_vault: Box<Account<'info, TokenAccount>>,
+
+    pub borrower: Signer<'info>,
+
+    // misc
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+#[instruction(_order_id: u64)]
+pub struct RepayPartialSol<'info> {
+    #[account(
+    seeds = [constants::CONFIG_PDA_SEED.as_ref()],
+    bump = config.nonce
+    )]
+    pub config: Box<Account<'info, Configuration>>,
+
+    #[account(
+    mut,
+    seeds = [market.stable_coin_mint.as_ref(), constants::MARKET_PDA_SEED.as_ref()],
+    bump = market.nonce
+    )]
+    pub market: Box<Account<'info, Market>>,
+
+    // Order.
+    #[account(
+    mut,
+    constraint = order.market == market.key(),
+    constraint = order.is_native,
+    constraint = order.borrower == borrower.key(),
+    seeds = [
+    _order_id.to_string().as_ref(),
+    constants::ORDER_PDA_SEED.as_ref(),
+    ],
+    bump = order.nonce
+    )]
+    pub order: Box<Account<'info, Order>>,
+
+    #[account(
+    mut,
+    address = order.lender,
+    )]
+    /// CHECK: receives the repayment
+    pub lender: AccountInfo<'info>,
+
+    #[account(mut)]
+    pub borrower: Signer<'info>,
+
+    // misc
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(_order_id: u64)]
+pub struct ProposeExtension<'info> {
+    #[account(
+    seeds = [constants::CONFIG_PDA_SEED.as_ref()],
+    bump = config.nonce
+    )]
+    pub config: Box<Account<'info, Configuration>>,
+
+    // Order.
+    #[account(
+    mut,
+    constraint = order.borrower == borrower.key(),
+    constraint = !order.is_native @ ErrorCode::NativeNotSupported,
+    seeds = [
+    _order_id.to_string().as_ref(),
+    constants::ORDER_PDA_SEED.as_ref(),
+    ],
+    bump = order.nonce
+    )]
+    pub order: Box<Account<'info, Order>>,
+
+    pub borrower: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(_order_id: u64)]
+pub struct ApproveExtension<'info> {
+    #[account(
+    seeds = [constants::CONFIG_PDA_SEED.as_ref()],
+    bump = config.nonce
+    )]
+    pub config: Box<Account<'info, Configuration>>,
+
+    // Order.
+    #[account(
+    mut,
+    constraint = order.lender == lender.key(),
+    constraint = !order.is_native @ ErrorCode::NativeNotSupported,
+    seeds = [
+    _order_id.to_string().as_ref(),
+    constants::ORDER_PDA_SEED.as_ref(),
+    ],
+    bump = order.nonce
+    )]
+    pub order: Box<Account<'info, Order>>,
+
+    pub lender: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(_order_id: u64)]
+pub struct ExtendLoan<'info> {
+    #[account(
+    seeds = [constants::CONFIG_PDA_SEED.as_ref()],
+    bump = config.nonce
+    )]
+    pub config: Box<Account<'info, Configuration>>,
+
+    #[account(
+    mut,
+    has_one = stable_coin_mint,
+    seeds = [stable_coin_mint.key().as_ref(), constants::MARKET_PDA_SEED.as_ref()],
+    bump = market.nonce
+    )]
+    pub market: Box<Account<'info, Market>>,
+
+    // Order.
+    #[account(
+    mut,
+    constraint = order.market == market.key(),
+    constraint = !order.is_native @ ErrorCode::NativeNotSupported,
+    constraint = order.borrower == borrower.key(),
+    seeds = [
+    _order_id.to_string().as_ref(),
+    constants::ORDER_PDA_SEED.as_ref(),
+    ],
+    bump = order.nonce
+    )]
+    pub order: Box<Account<'info, Order>>,
+
+    pub stable_coin_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+    mut,
+    address = market.treasury_vault,
+    )]
+    pub treasury_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+    mut,
+    constraint = lender_stable_coin_vault.mint == stable_coin_mint.key(),
+    constraint = lender_stable_coin_vault.owner == order.lender,
+    )]
+    pub lender_stable_coin_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+    mut,
+    constraint = borrower_stable_coin_vault.mint == stable_coin_mint.key(),
+    constraint = borrower_stable_coin_vault.owner == borrower.key(),
+    )]
+    pub borrower_stable_coin_vault: Box<Account<'info, TokenAccount>>,
+
+    pub borrower: Signer<'info>,
+
+    // misc
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+#[instruction(_order_id: u64)]
+pub struct Refinance<'info> {
+    #[account(
+    seeds = [constants::CONFIG_PDA_SEED.as_ref()],
+    bump = config.nonce
+    )]
+    pub config: Box<Account<'info, Configuration>>,
+
+    #[account(
+    has_one = stable_coin_mint,
+    seeds = [stable_coin_mint.key().as_ref(), constants::MARKET_PDA_SEED.as_ref()],
+    bump = market.nonce
+    )]
+    pub market: Box<Account<'info, Market>>,
+
+    // Order.
+    #[account(
+    mut,
+    constraint = order.market == market.key(),
+    constraint = !order.is_native @ ErrorCode::NativeNotSupported,
+    constraint = order.borrower == borrower.key(),
+    constraint = order.borrower != new_lender.key(),
+    seeds = [
+    _order_id.to_string().as_ref(),
+    constants::ORDER_PDA_SEED.as_ref(),
+    ],
+    bump = order.nonce
+    )]
+    pub order: Box<Account<'info, Order>>,
+
+    /// CHECK: whitelist entry of the nft's collection, holds its registered price feed, validated in the instruction
+    pub collection_whitelist: UncheckedAccount<'info>,
+
+    pub stable_coin_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+    mut,
+    address = market.treasury_vault,
+    )]
+    pub treasury_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+    mut,
+    constraint = lender_stable_coin_vault.mint == stable_coin_mint.key(),
+    constraint = lender_stable_coin_vault.owner == order.lender,
+    )]
+    pub lender_stable_coin_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+    mut,
+    constraint = new_lender_stable_coin_vault.mint == stable_coin_mint.key(),
+    constraint = new_lender_stable_coin_vault.owner == new_lender.key(),
+    )]
+    pub new_lender_stable_coin_vault: Box<Account<'info, TokenAccount>>,
+
+    pub new_lender: Signer<'info>,
+
+    // borrower agrees to the new terms
+    pub borrower: Signer<'info>,
+
+    /// CHECK: floor price feed of the nft's collection, validated in the instruction when the oracle is enabled
+    pub price_feed: UncheckedAccount<'info>,
+
+    // misc
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct CloseReceipt<'info> {
+    #[account(
+    mut,
+    has_one = rent_payer,
+    close = rent_payer,
+    seeds = [receipt.order.as_ref(), constants::RECEIPT_PDA_SEED.as_ref()],
+    bump = receipt.nonce
+    )]
+    pub receipt: Box<Account<'info, LoanReceipt>>,
+
+    #[account(mut)]
+    pub rent_payer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(_order_id: u64, _stable_nonce: u8, _nft_nonce: u8)]
+pub struct ExpireOrder<'info> {
+    #[account(
+    seeds = [constants::CONFIG_PDA_SEED.as_ref()],
+    bump = config.nonce
+    )]
+    pub config: Box<Account<'info, Configuration>>,
+
+    #[account(
+    mut,
+    has_one = stable_coin_vault,
+    has_one = stable_coin_mint,
+    seeds = [stable_coin_mint.key().as_ref(), constants::MARKET_PDA_SEED.as_ref()],
+    bump = market.nonce
+    )]
+    pub market: Box<Account<'info, Market>>,
+
+    // Order.
+    #[account(
+    mut,
+    constraint = order.market == market.key(),
+    constraint = order.stable_coin_vault == stable_coin_vault.key(),
+    constraint = order.borrower == borrower.key(),
+    constraint = order.nft_vault == nft_vault.key(),
+    constraint = order.nft_mint == nft_mint.key(),
+    seeds = [
+    _order_id.to_string().as_ref(),
+    constants::ORDER_PDA_SEED.as_ref(),
+    ],
+    close = borrower,
+    bump = order.nonce
+    )]
+    pub order: Box<Account<'info, Order>>,
+
+    pub stable_coin_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+    mut,
+    seeds = [stable_coin_mint.key().as_ref(), constants::STABLE_COIN_PDA_SEED.as_ref()],
+    bump = _stable_nonce,
+    )]
+    pub stable_coin_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+    mut,
+    constraint = user_stable_coin_vault.mint == stable_coin_mint.key(),
+    constraint = user_stable_coin_vault.owner == borrower.key(),
+    )]
+    pub user_stable_coin_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+    constraint = nft_mint.supply == 1,
+    constraint = nft_mint.decimals == 0,
+    )]
+    pub nft_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+    mut,
+    seeds = [nft_mint.key().as_ref(), constants::NFT_PDA_SEED.as_ref()],
+    bump = _nft_nonce,
+    )]
+    pub nft_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+    mut,
+    constraint = user_nft_vault.mint == nft_mint.key(),
+    constraint = user_nft_vault.owner == borrower.key(),
+    )]
+    pub user_nft_vault: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: borrower of the order, receives the collateral and the rent
+    #[account(mut)]
+    pub borrower: AccountInfo<'info>,
+
+    // anyone can expire the order, pays the receipt rent
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    /// CHECK: loan receipt of the order, created in the instruction when receipts are kept
+    #[account(mut)]
+    pub receipt: UncheckedAccount<'info>,
+
+    // misc
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+#[instruction(_order_id: u64, _nft_nonce: u8)]
+pub struct ExpireSolOrder<'info> {
+    #[account(
+    seeds = [constants::CONFIG_PDA_SEED.as_ref()],
+    bump = config.nonce
+    )]
+    pub config: Box<Account<'info, Configuration>>,
+
+    #[account(
+    mut,
+    seeds = [market.stable_coin_mint.as_ref(), constants::MARKET_PDA_SEED.as_ref()],
+    bump = market.nonce
+    )]
+    pub market: Box<Account<'info, Market>>,
+
+    // Order.
+    #[account(
+    mut,
+    constraint = order.market == market.key(),
+    constraint = order.is_native,
+    constraint = order.borrower == borrower.key(),
+    constraint = order.nft_vault == nft_vault.key(),
+    constraint = order.nft_mint == nft_mint.key(),
+    seeds = [
+    _order_id.to_string().as_ref(),
+    constants::ORDER_PDA_SEED.as_ref(),
+    ],
+    close = borrower,
+    bump = order.nonce
+    )]
+    pub order: Box<Account<'info, Order>>,
+
+    #[account(
+    constraint = nft_mint.supply == 1,
+    constraint = nft_mint.decimals == 0,
+    )]
+    pub nft_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+    mut,
+    seeds = [nft_mint.key().as_ref(), constants::NFT_PDA_SEED.as_ref()],
+    bump = _nft_nonce,
+    )]
+    pub nft_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+    mut,
+    constraint = user_nft_vault.mint == nft_mint.key(),
+    constraint = user_nft_vault.owner == borrower.key(),
+    )]
+    pub user_nft_vault: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: borrower of the order, receives the collateral and the rent
+    #[account(mut)]
+    pub borrower: AccountInfo<'info>,
+
+    // anyone can expire the order, pays the receipt rent
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    /// CHECK: loan receipt of the order, created in the instruction when receipts are kept
+    #[account(mut)]
+    pub receipt: UncheckedAccount<'info>,
+
+    // misc
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+#[instruction(_order_id: u64, _stable_nonce: u8)]
+pub struct UpdateOrder<'info> {
+    #[account(
+    seeds = [constants::CONFIG_PDA_SEED.as_ref()],
+    bump = config.nonce
+    )]
+    pub config: Box<Account<'info, Configuration>>,
+
+    #[account(
+    mut,
+    has_one = stable_coin_vault,
+    has_one = stable_coin_mint,
+    seeds = [stable_coin_mint.key().as_ref(), constants::MARKET_PDA_SEED.as_ref()],
+    bump = market.nonce
+    )]
+    pub market: Box<Account<'info, Market>>,
+
+    // Order.
+    #[account(
+    mut,
+    constraint = order.market == market.key(),
+    constraint = order.stable_coin_vault == stable_coin_vault.key(),
+    constraint = order.borrower == borrower.key(),
+    seeds = [
+    _order_id.to_string().as_ref(),
+    constants::ORDER_PDA_SEED.as_ref(),
+    ],
+    bump = order.nonce
+    )]
+    pub order: Box<Account<'info, Order>>,
 
-            let cpi_ctx = CpiContext::new_with_signer(
-                ctx.accounts.token_program.to_account_info(),
-                token::Transfer {
-                    from: ctx.accounts.nft_vault.to_account_info(),
-                    to: ctx.accounts.lender_nft_vault.to_account_info(),
-                    authority: ctx.accounts.nft_vault.to_account_info(),
-                },
-                signer,
-            );
-            token::transfer(cpi_ctx, 1)?;
+    pub stable_coin_mint: Box<Account<'info, Mint>>,
 
-            // Close Nft Vault
-            let cpi_ctx = CpiContext::new_with_signer(
-                ctx.accounts.token_program.to_account_info(),
-                token::CloseAccount {
-                    account: ctx.accounts.nft_vault.to_account_info(),
-                    destination: ctx.accounts.borrower.to_account_info(),
-                    authority: ctx.accounts.nft_vault.to_account_info(),
-                },
-                signer,
-            );
-            token::close_account(cpi_ctx)?;
-        }
+    #[account(
+    mut,
+    seeds = [stable_coin_mint.key().as_ref(), constants::STABLE_COIN_PDA_SEED.as_ref()],
+    bump = _stable_nonce,
+    )]
+    pub stable_coin_vault: Box<Account<'info, TokenAccount>>,
 
-        // Transfer additional collateral
-        {
-            let seeds = &[
-                ctx.accounts.stable_coin_mint.to_account_info().key.as_ref(),
-                constants::STABLE_COIN_PDA_SEED.as_ref(),
-                &[_stable_nonce]
-            ];
-            let signer = &[&seeds[..]];
+    #[account(
+    mut,
+    constraint = user_stable_coin_vault.mint == stable_coin_mint.key(),
+    constraint = user_stable_coin_vault.owner == borrower.key(),
+    )]
+    pub user_stable_coin_vault: Box<Account<'info, TokenAccount>>,
 
-            let cpi_ctx = CpiContext::new_with_signer(
-                ctx.accounts.token_program.to_account_info(),
-                token::Transfer {
-                    from: ctx.accounts.stable_coin_vault.to_account_info(),
-                    to: ctx.accounts.lender_stable_coin_vault.to_account_info(),
-                    authority: ctx.accounts.stable_coin_vault.to_account_info(),
-                },
-                signer,
-            );
-            token::transfer(cpi_ctx, order.additional_collateral)?;
-        }
-        config.total_additional_collateral -= order.additional_collateral;
+    /// CHECK: whitelist entry of the nft's collection, validated in the instruction
+    pub collection_whitelist: UncheckedAccount<'info>,
 
-        emit!(LiquidityOrderEvent {
-            order_key: *order.to_account_info().key,
-            borrower: *ctx.accounts.borrower.to_account_info().key,
-            lender: *ctx.accounts.lender.to_account_info().key,
-        });
-        Ok(())
-    }
+    pub borrower: Signer<'info>,
+
+    // misc
+    pub token_program: Program<'info, Token>,
 }
 
 #[derive(Accounts)]
-pub struct Initialize<'info> {
-    #[account(mut)]
-    pub signer: Signer<'info>,
-
+#[instruction(_order_id: u64)]
+pub struct UpdateSolOrder<'info> {
     #[account(
-    init,
-    payer = signer,
     seeds = [constants::CONFIG_PDA_SEED.as_ref()],
-    bump
+    bump = config.nonce
     )]
-    pub configuration: Box<Account<'info, Configuration>>,
+    pub config: Box<Account<'info, Configuration>>,
 
     #[account(
-    address = token_constants::USDC_MINT_PUBKEY.parse::< Pubkey > ().unwrap(),
+    mut,
+    seeds = [market.stable_coin_mint.as_ref(), constants::MARKET_PDA_SEED.as_ref()],
+    bump = market.nonce
     )]
-    pub stable_coin_mint: Box<Account<'info, Mint>>,
+    pub market: Box<Account<'info, Market>>,
 
+    // Order.
     #[account(
-    init,
-    payer = signer,
-    token::mint = stable_coin_mint,
-    token::authority = stable_coin_vault,
-    seeds = [token_constants::USDC_MINT_PUBKEY.parse::< Pubkey > ().unwrap().as_ref(), constants::STABLE_COIN_PDA_SEED.as_ref()],
-    bump
+    mut,
+    constraint = order.market == market.key(),
+    constraint = order.is_native,
+    constraint = order.borrower == borrower.key(),
+    seeds = [
+    _order_id.to_string().as_ref(),
+    constants::ORDER_PDA_SEED.as_ref(),
+    ],
+    bump = order.nonce
     )]
-    pub stable_coin_vault: Box<Account<'info, TokenAccount>>,
+    pub order: Box<Account<'info, Order>>,
 
+    /// CHECK: whitelist entry of the nft's collection, validated in the instruction
+    pub collection_whitelist: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub borrower: Signer<'info>,
+
+    // misc
     pub system_program: Program<'info, System>,
-    pub token_program: Program<'info, Token>,
-    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+#[instruction(_order_id: u64)]
+pub struct StartAuction<'info> {
+    #[account(
+    seeds = [constants::CONFIG_PDA_SEED.as_ref()],
+    bump = config.nonce
+    )]
+    pub config: Box<Account<'info, Configuration>>,
+
+    // Order.
+    #[account(
+    mut,
+    has_one = lender,
+    constraint = !order.is_native @ ErrorCode::NativeNotSupported,
+    seeds = [
+    _order_id.to_string().as_ref(),
+    constants::ORDER_PDA_SEED.as_ref(),
+    ],
+    bump = order.nonce
+    )]
+    pub order: Box<Account<'info, Order>>,
 
+    pub lender: Signer<'info>,
 }
 
 #[derive(Accounts)]
-#[instruction(_stable_nonce: u8)]
-pub struct CreateOrder<'info> {
+#[instruction(_order_id: u64, _stable_nonce: u8, _nft_nonce: u8)]
+pub struct BuyAuction<'info> {
+    #[account(
+    seeds = [constants::CONFIG_PDA_SEED.as_ref()],
+    bump = config.nonce
+    )]
+    pub config: Box<Account<'info, Configuration>>,
+
     #[account(
     mut,
     has_one = stable_coin_vault,
-    has_one = stable_coin_mint
+    has_one = stable_coin_mint,
+    seeds = [stable_coin_mint.key().as_ref(), constants::MARKET_PDA_SEED.as_ref()],
+    bump = market.nonce
     )]
-    pub config: Box<Account<'info, Configuration>>,
+    pub market: Box<Account<'info, Market>>,
 
+    // Order.
     #[account(
-    address = token_constants::USDC_MINT_PUBKEY.parse::< Pubkey > ().unwrap(),
+    mut,
+    constraint = order.market == market.key(),
+    constraint = order.stable_coin_vault == stable_coin_vault.key(),
+    constraint = order.borrower == borrower.key(),
+    constraint = order.nft_vault == nft_vault.key(),
+    constraint = order.nft_mint == nft_mint.key(),
+    seeds = [
+    _order_id.to_string().as_ref(),
+    constants::ORDER_PDA_SEED.as_ref(),
+    ],
+    close = borrower,
+    bump = order.nonce
     )]
+    pub order: Box<Account<'info, Order>>,
+
     pub stable_coin_mint: Box<Account<'info, Mint>>,
+
     #[account(
     mut,
     seeds = [stable_coin_mint.key().as_ref(), constants::STABLE_COIN_PDA_SEED.as_ref()],
@@ -439,10 +4984,30 @@ pub struct CreateOrder<'info> {
 
     #[account(
     mut,
-    constraint = user_stable_coin_vault.mint == stable_coin_mint.key(),
-    constraint = user_stable_coin_vault.owner == borrower.key(),
+    address = market.treasury_vault,
     )]
-    pub user_stable_coin_vault: Box<Account<'info, TokenAccount>>,
+    pub treasury_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+    mut,
+    constraint = lender_stable_coin_vault.mint == stable_coin_mint.key(),
+    constraint = lender_stable_coin_vault.owner == order.lender,
+    )]
+    pub lender_stable_coin_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+    mut,
+    constraint = borrower_stable_coin_vault.mint == stable_coin_mint.key(),
+    constraint = borrower_stable_coin_vault.owner == borrower.key(),
+    )]
+    pub borrower_stable_coin_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+    mut,
+    constraint = buyer_stable_coin_vault.mint == stable_coin_mint.key(),
+    constraint = buyer_stable_coin_vault.owner == buyer.key(),
+    )]
+    pub buyer_stable_coin_vault: Box<Account<'info, TokenAccount>>,
 
     #[account(
     constraint = nft_mint.supply == 1,
@@ -451,56 +5016,150 @@ pub struct CreateOrder<'info> {
     pub nft_mint: Box<Account<'info, Mint>>,
 
     #[account(
-    init,
-    payer = borrower,
-    token::mint = nft_mint,
-    token::authority = nft_vault,
+    mut,
     seeds = [nft_mint.key().as_ref(), constants::NFT_PDA_SEED.as_ref()],
-    bump,
+    bump = _nft_nonce,
     )]
     pub nft_vault: Box<Account<'info, TokenAccount>>,
 
     #[account(
     mut,
-    constraint = user_nft_vault.mint == nft_mint.key(),
-    constraint = user_nft_vault.owner == borrower.key(),
+    constraint = buyer_nft_vault.mint == nft_mint.key(),
+    constraint = buyer_nft_vault.owner == buyer.key(),
     )]
-    pub user_nft_vault: Box<Account<'info, TokenAccount>>,
+    pub buyer_nft_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(mut)]
+    /// CHECK: back to borrower
+    pub borrower: AccountInfo<'info>,
+
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+
+    /// CHECK: loan receipt of the order, created in the instruction when receipts are kept
+    #[account(mut)]
+    pub receipt: UncheckedAccount<'info>,
+
+    // misc
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+#[instruction(_order_id: u64)]
+pub struct StartEnglishAuction<'info> {
+    #[account(
+    seeds = [constants::CONFIG_PDA_SEED.as_ref()],
+    bump = config.nonce
+    )]
+    pub config: Box<Account<'info, Configuration>>,
+
+    #[account(
+    has_one = stable_coin_mint,
+    seeds = [stable_coin_mint.key().as_ref(), constants::MARKET_PDA_SEED.as_ref()],
+    bump = market.nonce
+    )]
+    pub market: Box<Account<'info, Market>>,
+
     // Order.
     #[account(
-    init_if_needed,
-    payer = borrower,
+    mut,
+    has_one = lender,
+    constraint = order.market == market.key(),
+    constraint = !order.is_native @ ErrorCode::NativeNotSupported,
     seeds = [
-    config.order_id.to_string().as_ref(),
+    _order_id.to_string().as_ref(),
     constants::ORDER_PDA_SEED.as_ref(),
     ],
-    bump
+    bump = order.nonce
     )]
     pub order: Box<Account<'info, Order>>,
 
+    pub stable_coin_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+    init,
+    payer = lender,
+    token::mint = stable_coin_mint,
+    token::authority = bid_escrow,
+    seeds = [order.key().as_ref(), constants::BID_ESCROW_PDA_SEED.as_ref()],
+    bump
+    )]
+    pub bid_escrow: Box<Account<'info, TokenAccount>>,
+
     #[account(mut)]
-    pub borrower: Signer<'info>,
+    pub lender: Signer<'info>,
 
     // misc
     pub system_program: Program<'info, System>,
     pub token_program: Program<'info, Token>,
-
     pub rent: Sysvar<'info, Rent>,
 }
 
 #[derive(Accounts)]
-#[instruction(_order_id: u64, _stable_nonce: u8, _nft_nonce: u8)]
-pub struct CancelOrder<'info> {
+#[instruction(_order_id: u64, _escrow_nonce: u8)]
+pub struct PlaceBid<'info> {
+    // Order.
     #[account(
     mut,
-    has_one = stable_coin_vault,
-    has_one = stable_coin_mint
+    seeds = [
+    _order_id.to_string().as_ref(),
+    constants::ORDER_PDA_SEED.as_ref(),
+    ],
+    bump = order.nonce
+    )]
+    pub order: Box<Account<'info, Order>>,
+
+    #[account(
+    mut,
+    seeds = [order.key().as_ref(), constants::BID_ESCROW_PDA_SEED.as_ref()],
+    bump = _escrow_nonce,
+    )]
+    pub bid_escrow: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+    mut,
+    constraint = bidder_stable_coin_vault.mint == bid_escrow.mint,
+    constraint = bidder_stable_coin_vault.owner == bidder.key(),
+    )]
+    pub bidder_stable_coin_vault: Box<Account<'info, TokenAccount>>,
+
+    // refunded when outbid, checked against the highest bidder in the instruction
+    #[account(
+    mut,
+    constraint = previous_bidder_stable_coin_vault.mint == bid_escrow.mint,
+    )]
+    pub previous_bidder_stable_coin_vault: Box<Account<'info, TokenAccount>>,
+
+    pub bidder: Signer<'info>,
+
+    // misc
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+#[instruction(_order_id: u64, _stable_nonce: u8, _nft_nonce: u8, _escrow_nonce: u8)]
+pub struct SettleAuction<'info> {
+    #[account(
+    seeds = [constants::CONFIG_PDA_SEED.as_ref()],
+    bump = config.nonce
     )]
     pub config: Box<Account<'info, Configuration>>,
 
+    #[account(
+    mut,
+    has_one = stable_coin_vault,
+    has_one = stable_coin_mint,
+    seeds = [stable_coin_mint.key().as_ref(), constants::MARKET_PDA_SEED.as_ref()],
+    bump = market.nonce
+    )]
+    pub market: Box<Account<'info, Market>>,
+
     // Order.
     #[account(
     mut,
+    has_one = lender,
+    constraint = order.market == market.key(),
     constraint = order.stable_coin_vault == stable_coin_vault.key(),
     constraint = order.borrower == borrower.key(),
     constraint = order.nft_vault == nft_vault.key(),
@@ -512,26 +5171,43 @@ pub struct CancelOrder<'info> {
     close = borrower,
     bump = order.nonce
     )]
-    pub order: Box<Account<'info, Order>>,
+    pub order: Box<Account<'info, Order>>,
+
+    pub stable_coin_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+    mut,
+    seeds = [stable_coin_mint.key().as_ref(), constants::STABLE_COIN_PDA_SEED.as_ref()],
+    bump = _stable_nonce,
+    )]
+    pub stable_coin_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+    mut,
+    seeds = [order.key().as_ref(), constants::BID_ESCROW_PDA_SEED.as_ref()],
+    bump = _escrow_nonce,
+    )]
+    pub bid_escrow: Box<Account<'info, TokenAccount>>,
 
     #[account(
-    address = token_constants::USDC_MINT_PUBKEY.parse::< Pubkey > ().unwrap(),
+    mut,
+    address = market.treasury_vault,
     )]
-    pub stable_coin_mint: Box<Account<'info, Mint>>,
+    pub treasury_vault: Box<Account<'info, TokenAccount>>,
 
     #[account(
     mut,
-    seeds = [stable_coin_mint.key().as_ref(), constants::STABLE_COIN_PDA_SEED.as_ref()],
-    bump = _stable_nonce,
+    constraint = lender_stable_coin_vault.mint == stable_coin_mint.key(),
+    constraint = lender_stable_coin_vault.owner == lender.key(),
     )]
-    pub stable_coin_vault: Box<Account<'info, TokenAccount>>,
+    pub lender_stable_coin_vault: Box<Account<'info, TokenAccount>>,
 
     #[account(
     mut,
-    constraint = user_stable_coin_vault.mint == stable_coin_mint.key(),
-    constraint = user_stable_coin_vault.owner == borrower.key(),
+    constraint = borrower_stable_coin_vault.mint == stable_coin_mint.key(),
+    constraint = borrower_stable_coin_vault.owner == borrower.key(),
     )]
-    pub user_stable_coin_vault: Box<Account<'info, TokenAccount>>,
+    pub borrower_stable_coin_vault: Box<Account<'info, TokenAccount>>,
 
     #[account(
     constraint = nft_mint.supply == 1,
@@ -546,37 +5222,41 @@ pub struct CancelOrder<'info> {
     )]
     pub nft_vault: Box<Account<'info, TokenAccount>>,
 
+    // highest bidder, or the lender when nobody bid, checked in the instruction
     #[account(
     mut,
-    constraint = user_nft_vault.mint == nft_mint.key(),
-    constraint = user_nft_vault.owner == borrower.key(),
+    constraint = winner_nft_vault.mint == nft_mint.key(),
     )]
-    pub user_nft_vault: Box<Account<'info, TokenAccount>>,
+    pub winner_nft_vault: Box<Account<'info, TokenAccount>>,
 
     #[account(mut)]
-    pub borrower: Signer<'info>,
+    /// CHECK: back to borrower
+    pub borrower: AccountInfo<'info>,
+
+    #[account(mut)]
+    /// CHECK: receives the escrow rent back
+    pub lender: AccountInfo<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    /// CHECK: loan receipt of the order, created in the instruction when receipts are kept
+    #[account(mut)]
+    pub receipt: UncheckedAccount<'info>,
 
     // misc
     pub system_program: Program<'info, System>,
     pub token_program: Program<'info, Token>,
 }
 
-
 #[derive(Accounts)]
-#[instruction(_order_id: u64, _stable_nonce: u8)]
-pub struct GiveLoan<'info> {
-    #[account(
-    mut,
-    has_one = stable_coin_vault,
-    has_one = stable_coin_mint
-    )]
-    pub config: Box<Account<'info, Configuration>>,
-
+#[instruction(_order_id: u64)]
+pub struct SetRedemptionWindow<'info> {
     // Order.
     #[account(
     mut,
-    constraint = order.stable_coin_vault == stable_coin_vault.key(),
-    constraint = order.borrower != lender.key(),
+    constraint = order.borrower == borrower.key(),
+    constraint = !order.is_native @ ErrorCode::NativeNotSupported,
     seeds = [
     _order_id.to_string().as_ref(),
     constants::ORDER_PDA_SEED.as_ref(),
@@ -585,52 +5265,31 @@ pub struct GiveLoan<'info> {
     )]
     pub order: Box<Account<'info, Order>>,
 
-    #[account(
-    address = token_constants::USDC_MINT_PUBKEY.parse::< Pubkey > ().unwrap(),
-    )]
-    pub stable_coin_mint: Box<Account<'info, Mint>>,
-
-    #[account(
-    mut,
-    seeds = [stable_coin_mint.key().as_ref(), constants::STABLE_COIN_PDA_SEED.as_ref()],
-    bump = _stable_nonce,
-    )]
-    pub stable_coin_vault: Box<Account<'info, TokenAccount>>,
-
-    #[account(
-    mut,
-    constraint = lender_stable_coin_vault.mint == stable_coin_mint.key(),
-    constraint = lender_stable_coin_vault.owner == lender.key(),
-    )]
-    pub lender_stable_coin_vault: Box<Account<'info, TokenAccount>>,
-    #[account(
-    mut,
-    constraint = borrower_stable_coin_vault.mint == stable_coin_mint.key(),
-    constraint = borrower_stable_coin_vault.owner == order.borrower,
-    )]
-    pub borrower_stable_coin_vault: Box<Account<'info, TokenAccount>>,
-
-    #[account(mut)]
-    pub lender: Signer<'info>,
-
-    // misc
-    pub system_program: Program<'info, System>,
-    pub token_program: Program<'info, Token>,
+    pub borrower: Signer<'info>,
 }
 
 #[derive(Accounts)]
 #[instruction(_order_id: u64, _stable_nonce: u8, _nft_nonce: u8)]
-pub struct Payback<'info> {
+pub struct Redeem<'info> {
+    #[account(
+    seeds = [constants::CONFIG_PDA_SEED.as_ref()],
+    bump = config.nonce
+    )]
+    pub config: Box<Account<'info, Configuration>>,
+
     #[account(
     mut,
     has_one = stable_coin_vault,
-    has_one = stable_coin_mint
+    has_one = stable_coin_mint,
+    seeds = [stable_coin_mint.key().as_ref(), constants::MARKET_PDA_SEED.as_ref()],
+    bump = market.nonce
     )]
-    pub config: Box<Account<'info, Configuration>>,
+    pub market: Box<Account<'info, Market>>,
 
     // Order.
     #[account(
     mut,
+    constraint = order.market == market.key(),
     constraint = order.stable_coin_vault == stable_coin_vault.key(),
     constraint = order.borrower == borrower.key(),
     constraint = order.nft_vault == nft_vault.key(),
@@ -644,9 +5303,6 @@ pub struct Payback<'info> {
     )]
     pub order: Box<Account<'info, Order>>,
 
-    #[account(
-    address = token_constants::USDC_MINT_PUBKEY.parse::< Pubkey > ().unwrap(),
-    )]
     pub stable_coin_mint: Box<Account<'info, Mint>>,
 
     #[account(
@@ -656,6 +5312,12 @@ pub struct Payback<'info> {
     )]
     pub stable_coin_vault: Box<Account<'info, TokenAccount>>,
 
+    #[account(
+    mut,
+    address = market.treasury_vault,
+    )]
+    pub treasury_vault: Box<Account<'info, TokenAccount>>,
+
     #[account(
     mut,
     constraint = lender_stable_coin_vault.mint == stable_coin_mint.key(),
@@ -693,139 +5355,726 @@ pub struct Payback<'info> {
     #[account(mut)]
     pub borrower: Signer<'info>,
 
+    /// CHECK: loan receipt of the order, created in the instruction when receipts are kept
+    #[account(mut)]
+    pub receipt: UncheckedAccount<'info>,
+
     // misc
     pub system_program: Program<'info, System>,
     pub token_program: Program<'info, Token>,
 }
 
-#[derive(Accounts)]
-#[instruction(_order_id: u64, _stable_nonce: u8, _nft_nonce: u8)]
-pub struct Liquidate<'info> {
-    #[account(
-    mut,
-    has_one = stable_coin_vault,
-    has_one = stable_coin_mint
-    )]
-    pub config: Box<Account<'info, Configuration>>,
+#[account]
+#[derive(Default)]
+pub struct Configuration {
+    // authority allowed to update the protocol parameters
+    pub admin: Pubkey,
+    // latest order id
+    pub order_id: u64,
+    // latest offer id
+    pub offer_id: u64,
+    // minimum loan period in seconds
+    pub min_period: u64,
+    // maximum loan period in seconds
+    pub max_period: u64,
+    // maximum annual interest rate, in basis points
+    pub max_interest_rate: u64,
+    // minimum additional collateral, in basis points of the request amount
+    pub min_collateral_ratio: u64,
+    // only accept nfts that belong to a verified metaplex collection
+    pub require_verified_collection: bool,
+    // only accept nfts of collections whitelisted for the market
+    pub require_whitelist: bool,
+    // protocol fee, in basis points of the interest paid
+    pub protocol_fee_rate: u64,
+    // origination fee, in basis points of the loan amount
+    pub origination_fee_rate: u64,
+    // interest is charged for at least this many seconds, even on early repayment
+    pub min_interest_period: u64,
+    // seconds after the loan period during which the borrower can still pay back with a late fee
+    pub grace_period: u64,
+    // late fee per started day past the loan period, in basis points of the outstanding principal
+    pub late_fee_rate: u64,
+    // keep a loan receipt for every settled order
+    pub keep_receipts: bool,
+    // seconds an unfunded order stays open before it can be expired, 0 when orders don't expire
+    pub order_lifetime: u64,
+    // starting price of liquidation auctions, in basis points of the debt, 0 when auctions are disabled
+    pub auction_start_multiplier: u64,
+    // seconds for the auction price to fall from the starting price to the debt
+    pub auction_duration: u64,
+    // minimum raise over the highest bid of an english auction, in basis points of that bid
+    pub bid_increment_rate: u64,
+    // a bid placed this many seconds before the end of an english auction pushes the end back to this
+    pub anti_snipe_period: u64,
+    // program owning the collection floor price feeds, default pubkey when loans are not valued
+    pub oracle_program: Pubkey,
+    // maximum loan-to-value of new loans, in basis points, 0 for no limit
+    pub max_ltv: u64,
+    // floor prices older than this many seconds are rejected
+    pub max_price_age: u64,
+    // loans above this loan-to-value can be liquidated before the deadline, in basis points, 0 to disable
+    pub maintenance_ltv: u64,
+    // nonce
+    pub nonce: u8,
+}
 
-    // Order.
-    #[account(
-    mut,
-    constraint = order.stable_coin_vault == stable_coin_vault.key(),
-    has_one = lender,
-    constraint = order.nft_vault == nft_vault.key(),
-    constraint = order.nft_mint == nft_mint.key(),
-    constraint = order.borrower == borrower.key(),
-    seeds = [
-    _order_id.to_string().as_ref(),
-    constants::ORDER_PDA_SEED.as_ref(),
-    ],
-    close = borrower,
-    bump = order.nonce
-    )]
-    pub order: Box<Account<'info, Order>>,
+impl Configuration {
+    pub fn validate(&self) -> Result<()> {
+        if self.min_period > self.max_period
+            || self.protocol_fee_rate > constants::BPS_DENOMINATOR
+            || self.origination_fee_rate > constants::BPS_DENOMINATOR
+            || self.max_ltv > constants::BPS_DENOMINATOR
+            || (self.maintenance_ltv != 0 && self.maintenance_ltv < self.max_ltv)
+            || (self.auction_start_multiplier != 0
+                && (self.auction_start_multiplier < constants::BPS_DENOMINATOR || self.auction_duration == 0))
+        {
+            return Err(ErrorCode::InvalidConfiguration.into());
+        }
+        Ok(())
+    }
 
-    #[account(
-    address = token_constants::USDC_MINT_PUBKEY.parse::< Pubkey > ().unwrap(),
-    )]
-    pub stable_coin_mint: Box<Account<'info, Mint>>,
+    // Read the verified collection of the nft, enforcing it when the protocol requires one.
+    pub fn check_collection(&self, nft_metadata: &AccountInfo, nft_mint: &Pubkey) -> Result<Pubkey> {
+        match metadata::verified_collection(nft_metadata, nft_mint)? {
+            Some(collection) => Ok(collection),
+            None if self.require_verified_collection => Err(ErrorCode::UnverifiedCollection.into()),
+            None => Ok(Pubkey::default()),
+        }
+    }
+
+    // Check the requested terms against the caps of the collection's whitelist entry.
+    pub fn check_whitelist<'info>(
+        &self,
+        collection_whitelist: &AccountInfo<'info>,
+        collection: &Pubkey,
+        market: &Pubkey,
+        request_amount: u64,
+        period: u64,
+    ) -> Result<()> {
+        if collection_whitelist.key() != CollectionWhitelist::address(collection, market) {
+            return Err(ErrorCode::InvalidWhitelist.into());
+        }
+        if collection_whitelist.data_is_empty() {
+            if self.require_whitelist {
+                return Err(ErrorCode::CollectionNotWhitelisted.into());
+            }
+            return Ok(());
+        }
+
+        let entry = Account::<CollectionWhitelist>::try_from(collection_whitelist)?;
+        if request_amount > entry.max_request_amount || period > entry.max_period {
+            return Err(ErrorCode::CollectionCapExceeded.into());
+        }
+        Ok(())
+    }
+
+    // Expiry of an order created at `created_at`, 0 when orders don't expire.
+    pub fn expires_at(&self, created_at: u64) -> Result<u64> {
+        if self.order_lifetime == 0 {
+            return Ok(0);
+        }
+        math::add(created_at, self.order_lifetime)
+    }
+
+    // Minimum interest charged on a loan, accrued over the minimum interest period.
+    pub fn min_interest(&self, request_amount: u64, interest_rate: u64, period: u64) -> Result<u64> {
+        math::accrued_interest(request_amount, interest_rate, self.min_interest_period.min(period))
+    }
+
+    // Validate the terms, collection and whitelist entry of a new order, returning the nft's collection.
+    pub fn check_order(
+        &self,
+        market: &Account<Market>,
+        nft_metadata: &AccountInfo,
+        nft_mint: &Pubkey,
+        collection_whitelist: &AccountInfo,
+        request_amount: u64,
+        interest_rate: u64,
+        period: u64,
+        additional_collateral: u64,
+    ) -> Result<Pubkey> {
+        self.validate_terms(market, request_amount, interest_rate, period, additional_collateral)?;
+        let collection = self.check_collection(nft_metadata, nft_mint)?;
+        self.check_whitelist(collection_whitelist, &collection, &market.key(), request_amount, period)?;
+        Ok(collection)
+    }
+
+    // Validate the requested terms of an order against the protocol and market bounds.
+    pub fn validate_terms(
+        &self,
+        market: &Market,
+        request_amount: u64,
+        interest_rate: u64,
+        period: u64,
+        additional_collateral: u64,
+    ) -> Result<()> {
+        if request_amount == 0 || request_amount < market.min_request_amount || request_amount > market.max_request_amount {
+            return Err(ErrorCode::InvalidRequestAmount.into());
+        }
+        if period < self.min_period || period > self.max_period {
+            return Err(ErrorCode::InvalidPeriod.into());
+        }
+        if interest_rate > self.max_interest_rate {
+            return Err(ErrorCode::InterestRateTooHigh.into());
+        }
+        // additional collateral, in basis points of the request amount
+        let collateral_ratio = (additional_collateral as u128)
+            .checked_mul(constants::BPS_DENOMINATOR as u128)
+            .unwrap()
+            / request_amount as u128;
+        if collateral_ratio < self.min_collateral_ratio as u128 {
+            return Err(ErrorCode::InsufficientCollateral.into());
+        }
+        Ok(())
+    }
+}
+
+#[account]
+#[derive(Default)]
+pub struct Market {
+    // Mint of the token lent in this market
+    pub stable_coin_mint: Pubkey,
+    // Vault holding the stablecoins -- mostly for holding the collateral stablecoins
+    pub stable_coin_vault: Pubkey,
+    // Vault collecting the protocol fees
+    pub treasury_vault: Pubkey,
+    // minimum amount a borrower can request
+    pub min_request_amount: u64,
+    // maximum amount a borrower can request
+    pub max_request_amount: u64,
+    // total additional collateral
+    pub total_additional_collateral: u64,
+    // protocol fees of native SOL loans, held by the market account
+    pub sol_treasury: u64,
+    // nonce
+    pub nonce: u8,
+}
+
+impl Market {
+    pub fn validate(&self) -> Result<()> {
+        if self.min_request_amount > self.max_request_amount {
+            return Err(ErrorCode::InvalidConfiguration.into());
+        }
+        Ok(())
+    }
+}
+
+#[account]
+#[derive(Default)]
+pub struct Order {
+    // person requesting the loan
+    pub borrower: Pubkey,
+    // market the loan is denominated in
+    pub market: Pubkey,
+    /// vault to send the loan
+    pub stable_coin_vault: Pubkey,
+    // mint of the nft
+    pub nft_mint: Pubkey,
+    /// collateral vault holding the nft
+    pub nft_vault: Pubkey,
+    // verified metaplex collection of the nft, default if it has none
+    pub collection: Pubkey,
+    // request amount
+    pub request_amount: u64,
+    // annual interest rate, in basis points
+    pub interest_rate: u64,
+    // minimum interest charged, even on early repayment
+    pub min_interest: u64,
+    // payback amoumt at the end of the loan period
+    pub payback_amount: u64,
+    // the loan period
+    pub period: u64,
+    // additional collateral
+    pub additional_collateral: u64,
+    // lender
+    pub lender: Pubkey,
+    // order created at
+    pub created_at: u64,
+    // unfunded order can be expired after this time, 0 when it doesn't expire
+    pub expires_at: u64,
+    // loan start time
+    pub loan_start_time: u64,
+    // repayment timestamp
+    pub paid_back_at: u64,
+    // time the lender liquidated the loan & withdrew the collateral
+    pub withdrew_at: u64,
+    // lifecycle state of the order
+    pub state: OrderState,
+
+    pub order_id: u64,
+    // protocol fee on the interest, snapshotted when the loan starts
+    pub protocol_fee_rate: u64,
+    // total amount repaid, principal and interest
+    pub amount_repaid: u64,
+    // principal repaid by partial repayments
+    pub principal_repaid: u64,
+    // time of the latest partial repayment, interest accrues from here
+    pub last_repaid_at: u64,
+    // minimum cumulative repayment per installment interval, 0 when there is no schedule
+    pub installment_amount: u64,
+    // installment interval in seconds
+    pub installment_interval: u64,
+    // number of partial repayments made
+    pub repayment_count: u64,
+    // latest partial repayments, oldest entries are overwritten
+    pub repayments: [Repayment; constants::REPAYMENT_HISTORY_LEN],
+    // loan period proposed by the borrower, 0 when there is no proposal
+    pub proposed_period: u64,
+    // extra interest the borrower offers for the extension
+    pub proposed_extra_interest: u64,
+    // longest loan period the lender agreed to
+    pub approved_max_period: u64,
+    // minimum extra interest the lender asks for an extension
+    pub approved_min_extra_interest: u64,
+    // number of times the loan was extended
+    pub extension_count: u64,
+    // bumped every time the borrower edits the terms of the open order
+    pub terms_version: u64,
+    // time the liquidation auction started, the debt is frozen at this time
+    pub auction_start_time: u64,
+    // time the auction price reaches the debt
+    pub auction_end_time: u64,
+    // auction price when it started
+    pub auction_start_price: u64,
+    // dutch or english auction
+    pub auction_kind: AuctionKind,
+    // floor price feed registered for the collection, snapshotted when the loan is valued
+    pub price_feed: Pubkey,
+    // collection floor price when the loan started, 0 when the oracle is disabled
+    pub floor_price: u64,
+    // publish time of that floor price
+    pub floor_price_time: u64,
+    // loan-to-value allowing liquidation before the deadline, snapshotted when the loan is valued
+    pub maintenance_ltv: u64,
+    // seconds the borrower can still redeem the nft after the grace period, 0 when there is no window
+    pub redemption_period: u64,
+    // paid to the lender on redemption, in basis points of the debt
+    pub redemption_penalty_rate: u64,
+    // highest english auction bid, held in the bid escrow
+    pub highest_bid: u64,
+    pub highest_bidder: Pubkey,
+    // minimum raise and anti-sniping period, snapshotted when the english auction starts
+    pub bid_increment_rate: u64,
+    pub anti_snipe_period: u64,
+    // grace period after the loan period, snapshotted when the loan starts
+    pub grace_period: u64,
+    // late fee per started day, snapshotted when the loan starts
+    pub late_fee_rate: u64,
+    // loan is in native SOL, the additional collateral is held by the order account. Native loans can't take
+    // an installment schedule, extensions, refinancing, auctions or a redemption window, offers on the native
+    // market lend wrapped SOL instead
+    pub is_native: bool,
+    // nonce
+    pub nonce: u8,
+}
+
+impl Order {
+    // Fails with the error matching the current state unless the order is in `expected`.
+    pub fn check_state(&self, expected: OrderState) -> Result<()> {
+        if self.state == expected {
+            return Ok(());
+        }
+        Err(match self.state {
+            OrderState::Open => ErrorCode::LoanNotProvided,
+            OrderState::Active => ErrorCode::LoanAlreadyStarted,
+            OrderState::Repaid => ErrorCode::AlreadyRepaid,
+            OrderState::Liquidated => ErrorCode::AlreadyLiquidated,
+            OrderState::Cancelled => ErrorCode::AlreadyCancelled,
+            OrderState::Expired => ErrorCode::OrderExpired,
+            OrderState::Auction => ErrorCode::AuctionInProgress,
+        }.into())
+    }
+
+    // Replace the terms of an open order.
+    pub fn set_terms(
+        &mut self,
+        config: &Configuration,
+        request_amount: u64,
+        interest_rate: u64,
+        period: u64,
+        additional_collateral: u64,
+    ) -> Result<()> {
+        self.request_amount = request_amount;
+        self.interest_rate = interest_rate;
+        self.period = period;
+        self.additional_collateral = additional_collateral;
+        self.min_interest = config.min_interest(request_amount, interest_rate, period)?;
+        self.payback_amount = math::add(request_amount, math::accrued_interest(request_amount, interest_rate, period)?)?;
+        self.terms_version = math::add(self.terms_version, 1)?;
+        Ok(())
+    }
+
+    // Fails unless the open order can be funded at `now` with the terms the lender signed for.
+    pub fn check_fundable(
+        &self,
+        terms_version: u64,
+        request_amount: u64,
+        interest_rate: u64,
+        period: u64,
+        nft_mint: &Pubkey,
+        now: u64,
+    ) -> Result<()> {
+        self.check_state(OrderState::Open)?;
+        if self.terms_version != terms_version {
+            return Err(ErrorCode::TermsVersionMismatch.into());
+        }
+        self.check_terms(request_amount, interest_rate, period, nft_mint)?;
+        if self.is_expired(now) {
+            return Err(ErrorCode::OrderExpired.into());
+        }
+        Ok(())
+    }
+
+    // Make sure the order still has the terms the lender signed for.
+    pub fn check_terms(&self, request_amount: u64, interest_rate: u64, period: u64, nft_mint: &Pubkey) -> Result<()> {
+        if self.request_amount != request_amount
+            || self.interest_rate != interest_rate
+            || self.period != period
+            || self.nft_mint != *nft_mint
+        {
+            return Err(ErrorCode::UnexpectedTerms.into());
+        }
+        Ok(())
+    }
+
+    // `debt` over the collateral value, in basis points.
+    pub fn loan_to_value(&self, debt: u64, floor_price: u64) -> Result<u64> {
+        let value = math::add(floor_price, self.additional_collateral)?;
+        if value == 0 {
+            return Ok(u64::MAX);
+        }
+        math::mul_div(debt, constants::BPS_DENOMINATOR, value)
+    }
+
+    // Repayment period and grace period are over, or an installment was missed.
+    pub fn is_defaulted(&self, now: u64) -> Result<bool> {
+        Ok(self.default_time()? <= now)
+    }
+
+    // Fails unless the lender can take or auction the collateral at `now`.
+    pub fn check_liquidatable(&self, now: u64) -> Result<()> {
+        self.check_state(OrderState::Active)?;
+        if !self.is_defaulted(now)? {
+            return Err(ErrorCode::RepaymentPeriodNotExceeded.into());
+        }
+        if self.redemption_period > 0 && self.redemption_deadline()? >= now {
+            return Err(ErrorCode::RedemptionWindowOpen.into());
+        }
+        Ok(())
+    }
+
+    // End of the redemption window, counted from the time the loan defaulted.
+    pub fn redemption_deadline(&self) -> Result<u64> {
+        math::add(self.default_time()?, self.redemption_period)
+    }
+
+    // Owed to the lender when the auction started: outstanding principal, interest and late fee.
+    pub fn auction_debt(&self) -> Result<u64> {
+        let amount = math::add(self.outstanding_principal()?, self.interest_due(self.auction_start_time)?)?;
+        math::add(amount, self.late_fee(self.auction_start_time)?)
+    }
+
+    // Lowest acceptable english auction bid, the first bid has to cover the debt.
+    pub fn min_bid(&self) -> Result<u64> {
+        if self.highest_bid == 0 {
+            return self.auction_debt();
+        }
+        let increment = math::bps_of(self.highest_bid, self.bid_increment_rate)?.max(1);
+        math::add(self.highest_bid, increment)
+    }
+
+    // Auction price at `now`, falling linearly from the start price to the debt.
+    pub fn auction_price(&self, now: u64) -> Result<u64> {
+        let debt = self.auction_debt()?;
+        if now >= self.auction_end_time {
+            return Ok(debt);
+        }
+        let remaining = self.auction_end_time - now.max(self.auction_start_time);
+        let duration = self.auction_end_time - self.auction_start_time;
+        math::add(debt, math::mul_div(math::sub(self.auction_start_price, debt)?, remaining, duration)?)
+    }
+
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.expires_at != 0 && now >= self.expires_at
+    }
+
+    // End of the loan period.
+    pub fn deadline(&self) -> Result<u64> {
+        math::add(self.loan_start_time, self.period)
+    }
+
+    // End of the grace period, the lender can liquidate afterwards.
+    pub fn liquidation_time(&self) -> Result<u64> {
+        math::add(self.deadline()?, self.grace_period)
+    }
+
+    // Time the loan defaults: the end of the grace period, or of the grace period after a missed installment.
+    pub fn default_time(&self) -> Result<u64> {
+        let liquidation_time = self.liquidation_time()?;
+        match self.missed_installment_time()? {
+            Some(missed_at) => Ok(math::add(missed_at, self.grace_period)?.min(liquidation_time)),
+            None => Ok(liquidation_time),
+        }
+    }
+
+    // Late fee owed when repaying at `now`, charged per started day past the deadline.
+    pub fn late_fee(&self, now: u64) -> Result<u64> {
+        let deadline = self.deadline()?;
+        if now <= deadline {
+            return Ok(0);
+        }
+        let days_late = (now - deadline + math::SECONDS_PER_DAY - 1) / math::SECONDS_PER_DAY;
+        let rate = days_late
+            .checked_mul(self.late_fee_rate)
+            .ok_or(ErrorCode::MathOverflow)?;
+        math::bps_of(self.outstanding_principal()?, rate)
+    }
+
+    pub fn outstanding_principal(&self) -> Result<u64> {
+        math::sub(self.request_amount, self.principal_repaid)
+    }
+
+    // Interest accrued on the outstanding principal since the loan started or the latest repayment.
+    pub fn accrued_interest(&self, now: u64) -> Result<u64> {
+        let elapsed = now.saturating_sub(self.loan_start_time.max(self.last_repaid_at));
+        math::accrued_interest(self.outstanding_principal()?, self.interest_rate, elapsed)
+    }
 
-    #[account(
-    mut,
-    seeds = [stable_coin_mint.key().as_ref(), constants::STABLE_COIN_PDA_SEED.as_ref()],
-    bump = _stable_nonce,
-    )]
-    pub stable_coin_vault: Box<Account<'info, TokenAccount>>,
+    // Interest owed when repaying the whole balance at `now`, topped up to the minimum interest.
+    pub fn interest_due(&self, now: u64) -> Result<u64> {
+        let interest_paid = math::sub(self.amount_repaid, self.principal_repaid)?;
+        let accrued = self.accrued_interest(now)?;
+        Ok(accrued.max(self.min_interest.saturating_sub(interest_paid)))
+    }
 
-    #[account(
-    mut,
-    constraint = lender_stable_coin_vault.mint == stable_coin_mint.key(),
-    constraint = lender_stable_coin_vault.owner == lender.key(),
-    )]
-    pub lender_stable_coin_vault: Box<Account<'info, TokenAccount>>,
+    // Time the borrower falls behind the installment schedule: the end of the first interval whose
+    // cumulative installments the repayments don't cover. None without a schedule.
+    pub fn missed_installment_time(&self) -> Result<Option<u64>> {
+        if self.installment_interval == 0 || self.loan_start_time == 0 {
+            return Ok(None);
+        }
+        let intervals = math::add(self.amount_repaid / self.installment_amount, 1)?;
+        let elapsed = intervals
+            .checked_mul(self.installment_interval)
+            .ok_or(ErrorCode::MathOverflow)?;
+        Ok(Some(math::add(self.loan_start_time, elapsed)?))
+    }
 
-    #[account(
-    constraint = nft_mint.supply == 1,
-    constraint = nft_mint.decimals == 0,
-    )]
-    pub nft_mint: Box<Account<'info, Mint>>,
+    pub fn record_repayment(&mut self, principal: u64, interest: u64, now: u64) -> Result<()> {
+        let index = (self.repayment_count % constants::REPAYMENT_HISTORY_LEN as u64) as usize;
+        self.repayments[index] = Repayment {
+            principal,
+            interest,
+            paid_at: now,
+        };
+        self.repayment_count = math::add(self.repayment_count, 1)?;
+        self.amount_repaid = math::add(self.amount_repaid, math::add(principal, interest)?)?;
+        self.principal_repaid = math::add(self.principal_repaid, principal)?;
+        self.last_repaid_at = now;
+        Ok(())
+    }
+}
 
-    #[account(
-    mut,
-    seeds = [nft_mint.key().as_ref(), constants::NFT_PDA_SEED.as_ref()],
-    bump = _nft_nonce,
-    )]
-    pub nft_vault: Box<Account<'info, TokenAccount>>,
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum OrderState {
+    // waiting for a lender
+    #[default]
+    Open,
+    // loan funded, waiting for repayment
+    Active,
+    // loan paid back, nft returned to the borrower
+    Repaid,
+    // collateral claimed by the lender or sold at auction
+    Liquidated,
+    // withdrawn by the borrower before funding
+    Cancelled,
+    // not funded in time, collateral returned to the borrower
+    Expired,
+    // defaulted, nft for sale in an auction
+    Auction,
+}
 
-    #[account(
-    mut,
-    constraint = lender_nft_vault.mint == nft_mint.key(),
-    constraint = lender_nft_vault.owner == lender.key(),
-    )]
-    pub lender_nft_vault: Box<Account<'info, TokenAccount>>,
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LiquidationTrigger {
+    // loan not paid back in time, installment missed, or auction and redemption window ended
+    MissedRepayment,
+    // collateral floor price fell below the maintenance loan-to-value
+    LoanToValue,
+}
 
-    #[account(mut)]
-    /// CHECK: back to borrower
-    pub borrower: AccountInfo<'info>,
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum AuctionKind {
+    // price falls over time, the first buyer takes the nft
+    #[default]
+    Dutch,
+    // bids rise over time, the highest bidder takes the nft when it ends
+    English,
+}
 
-    #[account(mut)]
-    pub lender: Signer<'info>,
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct Repayment {
+    // principal repaid
+    pub principal: u64,
+    // interest repaid
+    pub interest: u64,
+    // time of the repayment
+    pub paid_at: u64,
+}
 
-    // misc
-    pub system_program: Program<'info, System>,
-    pub token_program: Program<'info, Token>,
+#[account]
+#[derive(Default)]
+pub struct Offer {
+    // lender funding the loan
+    pub lender: Pubkey,
+    // market the loan is denominated in
+    pub market: Pubkey,
+    /// vault holding the escrowed principal
+    pub stable_coin_vault: Pubkey,
+    // mint of the nft the offer is made on
+    pub nft_mint: Pubkey,
+    // offered amount
+    pub request_amount: u64,
+    // annual interest rate, in basis points
+    pub interest_rate: u64,
+    // the loan period
+    pub period: u64,
+    // additional collateral required from the borrower
+    pub additional_collateral: u64,
+    // offer created at
+    pub created_at: u64,
+
+    pub offer_id: u64,
+    // nonce
+    pub nonce: u8,
 }
 
 #[account]
 #[derive(Default)]
-pub struct Configuration {
-    // Mint of the token
-    pub stable_coin_mint: Pubkey,
-    // Vault holding the stablecoins -- mostly for holding the collateral stablecoins
+pub struct CollectionOffer {
+    // lender funding the loans
+    pub lender: Pubkey,
+    // market the loans are denominated in
+    pub market: Pubkey,
+    /// vault holding the escrowed principal
     pub stable_coin_vault: Pubkey,
-    // latest order id
-    pub order_id: u64,
-    // total additional collateral
-    pub total_additional_collateral: u64,
+    // verified metaplex collection accepted as collateral
+    pub collection: Pubkey,
+    // offered amount per loan
+    pub request_amount: u64,
+    // annual interest rate, in basis points
+    pub interest_rate: u64,
+    // the loan period
+    pub period: u64,
+    // additional collateral required from the borrower
+    pub additional_collateral: u64,
+    // number of loans the offer was funded for
+    pub capacity: u64,
+    // number of loans that can still be taken
+    pub remaining: u64,
+    // offer created at
+    pub created_at: u64,
+
+    pub offer_id: u64,
     // nonce
     pub nonce: u8,
 }
 
 #[account]
 #[derive(Default)]
-pub struct Order {
-    // person requesting the loan
+pub struct CollectionWhitelist {
+    // verified metaplex collection
+    pub collection: Pubkey,
+    // market the caps apply to
+    pub market: Pubkey,
+    // maximum amount a borrower can request against this collection
+    pub max_request_amount: u64,
+    // maximum loan period against this collection
+    pub max_period: u64,
+    // floor price feed of the collection, default pubkey when loans against it can't be valued
+    pub price_feed: Pubkey,
+    // nonce
+    pub nonce: u8,
+}
+
+impl CollectionWhitelist {
+    // Address of the whitelist entry of `collection` in `market`.
+    pub fn address(collection: &Pubkey, market: &Pubkey) -> Pubkey {
+        let (address, _) = Pubkey::find_program_address(
+            &[collection.as_ref(), market.as_ref(), constants::WHITELIST_PDA_SEED.as_ref()],
+            &crate::ID,
+        );
+        address
+    }
+
+    // Floor price feed the admin registered for `collection` in `market`.
+    pub fn registered_price_feed(collection_whitelist: &AccountInfo, collection: &Pubkey, market: &Pubkey) -> Result<Pubkey> {
+        if collection_whitelist.key() != Self::address(collection, market) {
+            return Err(ErrorCode::InvalidWhitelist.into());
+        }
+        if collection_whitelist.data_is_empty() {
+            return Err(ErrorCode::PriceFeedNotRegistered.into());
+        }
+        let entry = Account::<CollectionWhitelist>::try_from(collection_whitelist)?;
+        if entry.price_feed == Pubkey::default() {
+            return Err(ErrorCode::PriceFeedNotRegistered.into());
+        }
+        Ok(entry.price_feed)
+    }
+
+    // Zero caps would block every loan against the collection, remove the entry instead.
+    pub fn validate(&self) -> Result<()> {
+        if self.max_request_amount == 0 || self.max_period == 0 {
+            return Err(ErrorCode::InvalidConfiguration.into());
+        }
+        Ok(())
+    }
+}
+
+#[account]
+#[derive(Default)]
+pub struct LoanReceipt {
+    // settled order
+    pub order: Pubkey,
+    pub order_id: u64,
+    // borrower of the loan
     pub borrower: Pubkey,
-    /// vault to send the loan
-    pub stable_coin_vault: Pubkey,
+    // lender of the loan
+    pub lender: Pubkey,
+    // market the loan was denominated in
+    pub market: Pubkey,
     // mint of the nft
     pub nft_mint: Pubkey,
-    /// collateral vault holding the nft
-    pub nft_vault: Pubkey,
-    // request amount
+    // verified metaplex collection of the nft, default if it had none
+    pub collection: Pubkey,
+    // final state of the order
+    pub state: OrderState,
+    // loan amount
     pub request_amount: u64,
-    // interest amount
-    pub interest: u64,
-    // payback amoumt
-    pub payback_amount: u64,
+    // annual interest rate, in basis points
+    pub interest_rate: u64,
     // the loan period
     pub period: u64,
     // additional collateral
     pub additional_collateral: u64,
-    // lender
-    pub lender: Pubkey,
+    // total amount repaid, principal and interest
+    pub amount_repaid: u64,
+    // principal repaid
+    pub principal_repaid: u64,
+    // late fee paid
+    pub late_fee: u64,
     // order created at
     pub created_at: u64,
     // loan start time
     pub loan_start_time: u64,
-    // repayment timestamp
+    // repayment timestamp, 0 unless the loan was paid back
     pub paid_back_at: u64,
-    // time the lender liquidated the loan & withdrew the collateral
+    // time the lender took the collateral, 0 unless the loan was liquidated
     pub withdrew_at: u64,
-    // status of the order
-    pub order_status: bool,
-
-    pub order_id: u64,
+    // time the order was settled
+    pub closed_at: u64,
+    // account that paid the rent, can close the receipt
+    pub rent_payer: Pubkey,
     // nonce
     pub nonce: u8,
 }
@@ -842,12 +6091,107 @@ pub enum ErrorCode {
     RepaymentPeriodNotExceeded,
     #[msg("Already liquidated")]
     AlreadyLiquidated,
+    #[msg("Invalid configuration")]
+    InvalidConfiguration,
+    #[msg("Request amount is out of the allowed range")]
+    InvalidRequestAmount,
+    #[msg("Loan period is out of the allowed range")]
+    InvalidPeriod,
+    #[msg("Interest rate is too high")]
+    InterestRateTooHigh,
+    #[msg("Additional collateral is below the minimum ratio")]
+    InsufficientCollateral,
+    #[msg("Math overflow")]
+    MathOverflow,
+    #[msg("Market does not lend native SOL")]
+    NotNativeMarket,
+    #[msg("Not supported for native SOL loans")]
+    NativeNotSupported,
+    #[msg("Capacity must be greater than zero")]
+    InvalidCapacity,
+    #[msg("Offer has no loans left")]
+    OfferExhausted,
+    #[msg("Invalid nft metadata")]
+    InvalidMetadata,
+    #[msg("Nft is not part of the verified collection")]
+    CollectionMismatch,
+    #[msg("Nft does not belong to a verified collection")]
+    UnverifiedCollection,
+    #[msg("Invalid collection whitelist account")]
+    InvalidWhitelist,
+    #[msg("Collection is not whitelisted")]
+    CollectionNotWhitelisted,
+    #[msg("Terms exceed the collection caps")]
+    CollectionCapExceeded,
+    #[msg("Not enough funds in the treasury")]
+    InsufficientTreasury,
+    #[msg("Invalid installment schedule")]
+    InvalidInstallmentSchedule,
+    #[msg("Repayment does not cover the accrued interest")]
+    RepaymentTooSmall,
+    #[msg("Repayment clears the loan, use payback instead")]
+    RepaymentExceedsBalance,
+    #[msg("No extension has been proposed")]
+    NoExtensionProposed,
+    #[msg("Extension is not approved by the lender")]
+    ExtensionNotApproved,
+    #[msg("Loan has already been repaid")]
+    AlreadyRepaid,
+    #[msg("Order has been cancelled")]
+    AlreadyCancelled,
+    #[msg("Order has expired")]
+    OrderExpired,
+    #[msg("Invalid loan receipt account")]
+    InvalidReceipt,
+    #[msg("Order has not expired yet")]
+    OrderNotExpired,
+    #[msg("Order terms changed since they were read")]
+    TermsVersionMismatch,
+    #[msg("Order terms do not match the expected terms")]
+    UnexpectedTerms,
+    #[msg("Liquidation auctions are disabled")]
+    AuctionsDisabled,
+    #[msg("Order is not being auctioned")]
+    NoAuction,
+    #[msg("Auction is still running")]
+    AuctionInProgress,
+    #[msg("Auction price is above the maximum price")]
+    AuctionPriceTooHigh,
+    #[msg("Not supported by this kind of auction")]
+    WrongAuctionKind,
+    #[msg("Auction has ended")]
+    AuctionEnded,
+    #[msg("Bid is below the minimum bid")]
+    BidTooLow,
+    #[msg("Refund account does not belong to the highest bidder")]
+    InvalidBidRefund,
+    #[msg("Nft account does not belong to the auction winner")]
+    InvalidAuctionWinner,
+    #[msg("Redemption window requires a period")]
+    InvalidRedemptionWindow,
+    #[msg("Order has no redemption window")]
+    NoRedemptionWindow,
+    #[msg("Redemption window is still open")]
+    RedemptionWindowOpen,
+    #[msg("Redemption window is closed")]
+    RedemptionWindowClosed,
+    #[msg("Invalid price feed")]
+    InvalidPriceFeed,
+    #[msg("Collection has no registered price feed")]
+    PriceFeedNotRegistered,
+    #[msg("Price feed is stale")]
+    StalePrice,
+    #[msg("Loan to value is above the maximum")]
+    LoanToValueTooHigh,
+    #[msg("Loan to value is below the maintenance threshold")]
+    LoanToValueHealthy,
 }
 
 #[event]
 pub struct CreatedOrderEvent {
     pub order_key: Pubkey,
     pub borrower: Pubkey,
+    pub collection: Pubkey,
 }
 
 #[event]
@@ -861,12 +6205,16 @@ pub struct LoanOrderEvent {
     pub order_key: Pubkey,
     pub borrower: Pubkey,
     pub lender: Pubkey,
+    pub origination_fee: u64,
 }
 
 #[event]
 pub struct PayBackOrderEvent {
     pub order_key: Pubkey,
     pub borrower: Pubkey,
+    pub interest: u64,
+    pub protocol_fee: u64,
+    pub late_fee: u64,
 }
 
 #[event]
@@ -874,4 +6222,359 @@ pub struct LiquidityOrderEvent {
     pub order_key: Pubkey,
     pub borrower: Pubkey,
     pub lender: Pubkey,
+    pub trigger: LiquidationTrigger,
+    // loan-to-value that allowed the liquidation, 0 unless triggered by the oracle
+    pub loan_to_value: u64,
+}
+
+#[event]
+pub struct UpdatedConfigEvent {
+    pub config_key: Pubkey,
+    pub admin: Pubkey,
+    pub min_period: u64,
+    pub max_period: u64,
+    pub max_interest_rate: u64,
+    pub min_collateral_ratio: u64,
+    pub require_verified_collection: bool,
+    pub require_whitelist: bool,
+    pub protocol_fee_rate: u64,
+    pub origination_fee_rate: u64,
+    pub min_interest_period: u64,
+    pub grace_period: u64,
+    pub late_fee_rate: u64,
+    pub keep_receipts: bool,
+    pub order_lifetime: u64,
+    pub auction_start_multiplier: u64,
+    pub auction_duration: u64,
+    pub bid_increment_rate: u64,
+    pub anti_snipe_period: u64,
+    pub oracle_program: Pubkey,
+    pub max_ltv: u64,
+    pub max_price_age: u64,
+    pub maintenance_ltv: u64,
+}
+
+#[event]
+pub struct CreatedMarketEvent {
+    pub market_key: Pubkey,
+    pub stable_coin_mint: Pubkey,
+    pub min_request_amount: u64,
+    pub max_request_amount: u64,
+}
+
+#[event]
+pub struct UpdatedMarketEvent {
+    pub market_key: Pubkey,
+    pub stable_coin_mint: Pubkey,
+    pub min_request_amount: u64,
+    pub max_request_amount: u64,
+}
+
+#[event]
+pub struct CreatedOfferEvent {
+    pub offer_key: Pubkey,
+    pub lender: Pubkey,
+    pub nft_mint: Pubkey,
+}
+
+#[event]
+pub struct CanceledOfferEvent {
+    pub offer_key: Pubkey,
+    pub lender: Pubkey,
+}
+
+#[event]
+pub struct AcceptedOfferEvent {
+    pub offer_key: Pubkey,
+    pub order_key: Pubkey,
+    pub borrower: Pubkey,
+    pub lender: Pubkey,
+    pub origination_fee: u64,
+}
+
+#[event]
+pub struct CreatedCollectionOfferEvent {
+    pub offer_key: Pubkey,
+    pub lender: Pubkey,
+    pub collection: Pubkey,
+    pub capacity: u64,
+}
+
+#[event]
+pub struct CanceledCollectionOfferEvent {
+    pub offer_key: Pubkey,
+    pub lender: Pubkey,
+    pub remaining: u64,
+}
+
+#[event]
+pub struct AcceptedCollectionOfferEvent {
+    pub offer_key: Pubkey,
+    pub order_key: Pubkey,
+    pub borrower: Pubkey,
+    pub lender: Pubkey,
+    pub remaining: u64,
+    pub origination_fee: u64,
+}
+
+#[event]
+pub struct UpdatedCollectionWhitelistEvent {
+    pub whitelist_key: Pubkey,
+    pub collection: Pubkey,
+    pub market: Pubkey,
+    pub max_request_amount: u64,
+    pub max_period: u64,
+    pub price_feed: Pubkey,
+}
+
+#[event]
+pub struct RemovedCollectionWhitelistEvent {
+    pub whitelist_key: Pubkey,
+    pub collection: Pubkey,
+    pub market: Pubkey,
+}
+
+#[event]
+pub struct WithdrewTreasuryEvent {
+    pub market_key: Pubkey,
+    pub admin: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct UpdatedInstallmentScheduleEvent {
+    pub order_key: Pubkey,
+    pub borrower: Pubkey,
+    pub installment_amount: u64,
+    pub installment_interval: u64,
+    pub terms_version: u64,
+}
+
+#[event]
+pub struct RepaidOrderEvent {
+    pub order_key: Pubkey,
+    pub borrower: Pubkey,
+    pub principal: u64,
+    pub interest: u64,
+    pub protocol_fee: u64,
+    pub outstanding_principal: u64,
+}
+
+#[event]
+pub struct ProposedExtensionEvent {
+    pub order_key: Pubkey,
+    pub borrower: Pubkey,
+    pub new_period: u64,
+    pub extra_interest: u64,
+}
+
+#[event]
+pub struct ApprovedExtensionEvent {
+    pub order_key: Pubkey,
+    pub lender: Pubkey,
+    pub max_period: u64,
+    pub min_extra_interest: u64,
+}
+
+#[event]
+pub struct ExtendedLoanEvent {
+    pub order_key: Pubkey,
+    pub borrower: Pubkey,
+    pub lender: Pubkey,
+    pub old_period: u64,
+    pub new_period: u64,
+    pub interest: u64,
+    pub protocol_fee: u64,
+}
+
+#[event]
+pub struct RefinancedOrderEvent {
+    pub order_key: Pubkey,
+    pub borrower: Pubkey,
+    pub old_lender: Pubkey,
+    pub new_lender: Pubkey,
+    pub old_principal: u64,
+    pub old_interest_rate: u64,
+    pub old_period: u64,
+    pub new_principal: u64,
+    pub new_interest_rate: u64,
+    pub new_period: u64,
+    pub interest: u64,
+    pub protocol_fee: u64,
+}
+
+#[event]
+pub struct ExpiredOrderEvent {
+    pub order_key: Pubkey,
+    pub borrower: Pubkey,
+}
+
+#[event]
+pub struct UpdatedOrderEvent {
+    pub order_key: Pubkey,
+    pub borrower: Pubkey,
+    pub request_amount: u64,
+    pub interest_rate: u64,
+    pub period: u64,
+    pub additional_collateral: u64,
+    pub terms_version: u64,
+}
+
+#[event]
+pub struct StartedAuctionEvent {
+    pub order_key: Pubkey,
+    pub lender: Pubkey,
+    pub debt: u64,
+    pub start_price: u64,
+    pub end_time: u64,
+}
+
+#[event]
+pub struct SoldAuctionEvent {
+    pub order_key: Pubkey,
+    pub buyer: Pubkey,
+    pub price: u64,
+    pub debt: u64,
+    pub protocol_fee: u64,
+    pub surplus: u64,
+}
+
+#[event]
+pub struct PlacedBidEvent {
+    pub order_key: Pubkey,
+    pub bidder: Pubkey,
+    pub amount: u64,
+    pub end_time: u64,
+}
+
+#[event]
+pub struct SettledAuctionEvent {
+    pub order_key: Pubkey,
+    pub winner: Pubkey,
+    pub price: u64,
+    pub debt: u64,
+    pub protocol_fee: u64,
+    pub surplus: u64,
+}
+
+#[event]
+pub struct UpdatedRedemptionWindowEvent {
+    pub order_key: Pubkey,
+    pub borrower: Pubkey,
+    pub redemption_period: u64,
+    pub redemption_penalty_rate: u64,
+    pub terms_version: u64,
+}
+
+#[event]
+pub struct RedeemedOrderEvent {
+    pub order_key: Pubkey,
+    pub borrower: Pubkey,
+    pub interest: u64,
+    pub protocol_fee: u64,
+    pub late_fee: u64,
+    pub penalty: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 80 tokens at 15% for 10 minutes with a day of grace, auctioned when the grace period ends
+    fn defaulted_order() -> Order {
+        let mut order = Order {
+            request_amount: 80_000_000,
+            interest_rate: 1_500,
+            min_interest: 228,
+            period: 600,
+            loan_start_time: 1_000,
+            grace_period: 86_400,
+            late_fee_rate: 10,
+            state: OrderState::Auction,
+            ..Default::default()
+        };
+        order.auction_start_time = order.liquidation_time().unwrap();
+        order.auction_end_time = order.auction_start_time + 86_400;
+        order
+    }
+
+    #[test]
+    fn auction_debt_includes_interest_and_late_fee() {
+        let order = defaulted_order();
+        // 87_000 seconds of interest and a day of late fee
+        assert_eq!(order.auction_debt().unwrap(), 80_000_000 + 33_105 + 80_000);
+    }
+
+    #[test]
+    fn auction_debt_charges_min_interest() {
+        let mut order = defaulted_order();
+        order.auction_start_time = order.loan_start_time;
+        assert_eq!(order.auction_debt().unwrap(), 80_000_228);
+    }
+
+    #[test]
+    fn auction_debt_after_partial_repayment() {
+        let mut order = defaulted_order();
+        order.principal_repaid = 30_000_000;
+        order.amount_repaid = 30_000_100;
+        order.last_repaid_at = 10_000;
+        // interest since the repayment and the late fee on the outstanding principal only
+        assert_eq!(order.auction_debt().unwrap(), 50_000_000 + 18_550 + 50_000);
+    }
+
+    #[test]
+    fn auction_price_decays_to_debt() {
+        let mut order = defaulted_order();
+        let debt = order.auction_debt().unwrap();
+        order.auction_start_price = math::bps_of(debt, 15_000).unwrap();
+        let start = order.auction_start_time;
+
+        assert_eq!(order.auction_price(start - 1).unwrap(), 120_169_657);
+        assert_eq!(order.auction_price(start).unwrap(), 120_169_657);
+        assert_eq!(order.auction_price(start + 1).unwrap(), 120_169_657 - 464);
+        assert_eq!(order.auction_price(start + 43_200).unwrap(), 100_141_381);
+        assert_eq!(order.auction_price(order.auction_end_time).unwrap(), debt);
+        assert_eq!(order.auction_price(order.auction_end_time + 1).unwrap(), debt);
+    }
+
+    #[test]
+    fn redemption_window_delays_liquidation() {
+        let mut order = defaulted_order();
+        order.state = OrderState::Active;
+        let liquidation_time = order.liquidation_time().unwrap();
+        assert!(order.check_liquidatable(liquidation_time).is_ok());
+
+        order.redemption_period = 3_600;
+        assert_eq!(order.redemption_deadline().unwrap(), liquidation_time + 3_600);
+        assert!(order.check_liquidatable(liquidation_time - 1).is_err());
+        assert!(order.check_liquidatable(liquidation_time + 3_600).is_err());
+        assert!(order.check_liquidatable(liquidation_time + 3_601).is_ok());
+    }
+
+    #[test]
+    fn redemption_window_starts_at_missed_installment() {
+        let mut order = defaulted_order();
+        order.state = OrderState::Active;
+        order.period = 86_400;
+        order.grace_period = 60;
+        order.installment_amount = 40_000_000;
+        order.installment_interval = 300;
+        order.amount_repaid = 40_000_100;
+        order.redemption_period = 3_600;
+
+        // the second installment was due 600 seconds in, the loan defaults a grace period later, well
+        // before the end of the loan period
+        assert_eq!(order.missed_installment_time().unwrap(), Some(1_600));
+        assert_eq!(order.default_time().unwrap(), 1_660);
+        assert!(!order.is_defaulted(1_659).unwrap());
+        assert!(order.is_defaulted(1_660).unwrap());
+        assert_eq!(order.redemption_deadline().unwrap(), 1_660 + 3_600);
+        assert!(order.check_liquidatable(1_660 + 3_600).is_err());
+        assert!(order.check_liquidatable(1_660 + 3_601).is_ok());
+
+        // without any repayment the first installment is already missed
+        order.amount_repaid = 0;
+        assert_eq!(order.default_time().unwrap(), 1_360);
+        assert_eq!(order.redemption_deadline().unwrap(), 1_360 + 3_600);
+    }
 }
@@ -0,0 +1,87 @@
+use anchor_lang::prelude::*;
+use std::convert::TryFrom;
+
+use crate::constants;
+use crate::ErrorCode;
+
+pub const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+// Seconds in a 365 day year, annual rates accrue over this duration
+pub const SECONDS_PER_YEAR: u64 = 365 * SECONDS_PER_DAY;
+
+// `a * b / c` computed in u128, rounded down.
+pub fn mul_div(a: u64, b: u64, c: u64) -> Result<u64> {
+    let value = (a as u128)
+        .checked_mul(b as u128)
+        .ok_or(ErrorCode::MathOverflow)?
+        .checked_div(c as u128)
+        .ok_or(ErrorCode::MathOverflow)?;
+    u64::try_from(value).map_err(|_| ErrorCode::MathOverflow.into())
+}
+
+// `rate` basis points of `amount`, rounded down.
+pub fn bps_of(amount: u64, rate: u64) -> Result<u64> {
+    mul_div(amount, rate, constants::BPS_DENOMINATOR)
+}
+
+// Interest accrued on `principal` at an annual rate of `apr` basis points over `elapsed` seconds,
+// rounded down.
+pub fn accrued_interest(principal: u64, apr: u64, elapsed: u64) -> Result<u64> {
+    let value = (principal as u128)
+        .checked_mul(apr as u128)
+        .ok_or(ErrorCode::MathOverflow)?
+        .checked_mul(elapsed as u128)
+        .ok_or(ErrorCode::MathOverflow)?
+        / (constants::BPS_DENOMINATOR as u128 * SECONDS_PER_YEAR as u128);
+    u64::try_from(value).map_err(|_| ErrorCode::MathOverflow.into())
+}
+
+pub fn add(a: u64, b: u64) -> Result<u64> {
+    a.checked_add(b).ok_or(ErrorCode::MathOverflow.into())
+}
+
+pub fn sub(a: u64, b: u64) -> Result<u64> {
+    a.checked_sub(b).ok_or(ErrorCode::MathOverflow.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mul_div_rounds_down() {
+        assert_eq!(mul_div(10, 3, 4).unwrap(), 7);
+        assert_eq!(mul_div(u64::MAX, u64::MAX, u64::MAX).unwrap(), u64::MAX);
+    }
+
+    #[test]
+    fn mul_div_overflow() {
+        assert!(mul_div(u64::MAX, 2, 1).is_err());
+        assert!(mul_div(1, 1, 0).is_err());
+    }
+
+    #[test]
+    fn bps_of_amount() {
+        assert_eq!(bps_of(80_000_000, 50).unwrap(), 400_000);
+        assert_eq!(bps_of(19, 1_000).unwrap(), 1);
+    }
+
+    #[test]
+    fn accrued_interest_over_a_year() {
+        assert_eq!(accrued_interest(1_000_000, 1_500, SECONDS_PER_YEAR).unwrap(), 150_000);
+        assert_eq!(accrued_interest(80_000_000, 1_500, 600).unwrap(), 228);
+        assert_eq!(accrued_interest(1_000_000, 1_500, 0).unwrap(), 0);
+    }
+
+    #[test]
+    fn accrued_interest_overflow() {
+        assert!(accrued_interest(u64::MAX, u64::MAX, u64::MAX).is_err());
+    }
+
+    #[test]
+    fn checked_add_sub() {
+        assert_eq!(add(1, 2).unwrap(), 3);
+        assert!(add(u64::MAX, 1).is_err());
+        assert_eq!(sub(3, 2).unwrap(), 1);
+        assert!(sub(2, 3).is_err());
+    }
+}
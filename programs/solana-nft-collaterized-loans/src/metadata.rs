@@ -0,0 +1,181 @@
+use anchor_lang::prelude::*;
+
+use crate::ErrorCode;
+
+// Metaplex token metadata program
+pub const METADATA_PROGRAM_ID: &str = "metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s";
+pub const METADATA_PDA_SEED: &[u8] = b"metadata";
+
+#[derive(AnchorDeserialize)]
+struct Creator {
+    _address: Pubkey,
+    _verified: bool,
+    _share: u8,
+}
+
+#[derive(AnchorDeserialize)]
+struct Data {
+    _name: String,
+    _symbol: String,
+    _uri: String,
+    _seller_fee_basis_points: u16,
+    _creators: Option<Vec<Creator>>,
+}
+
+#[derive(AnchorDeserialize)]
+struct Collection {
+    verified: bool,
+    key: Pubkey,
+}
+
+pub fn metadata_program_id() -> Pubkey {
+    METADATA_PROGRAM_ID.parse::<Pubkey>().unwrap()
+}
+
+// Fields appended in later metadata versions are missing from older, shorter accounts.
+// Only a buffer that ends before the field reads as None, malformed data is still an error.
+fn appended_field<T: AnchorDeserialize>(buf: &mut &[u8]) -> Result<Option<T>> {
+    if buf.is_empty() {
+        return Ok(None);
+    }
+    Ok(Option::<T>::deserialize(buf).map_err(|_| ErrorCode::InvalidMetadata)?)
+}
+
+pub fn find_metadata_address(mint: &Pubkey) -> Pubkey {
+    let program_id = metadata_program_id();
+    Pubkey::find_program_address(
+        &[METADATA_PDA_SEED, program_id.as_ref(), mint.as_ref()],
+        &program_id,
+    ).0
+}
+
+// Returns the verified collection of `mint`, read from its metaplex metadata account.
+// Returns None when the metadata account does not exist or has no verified collection.
+pub fn verified_collection(metadata: &AccountInfo, mint: &Pubkey) -> Result<Option<Pubkey>> {
+    if metadata.key() != find_metadata_address(mint) {
+        return Err(ErrorCode::InvalidMetadata.into());
+    }
+    if metadata.data_is_empty() {
+        return Ok(None);
+    }
+    if *metadata.owner != metadata_program_id() {
+        return Err(ErrorCode::InvalidMetadata.into());
+    }
+
+    let data = metadata.try_borrow_data()?;
+    let buf = &mut &data[..];
+    let _key = u8::deserialize(buf).map_err(|_| ErrorCode::InvalidMetadata)?;
+    let _update_authority = Pubkey::deserialize(buf).map_err(|_| ErrorCode::InvalidMetadata)?;
+    let metadata_mint = Pubkey::deserialize(buf).map_err(|_| ErrorCode::InvalidMetadata)?;
+    if metadata_mint != *mint {
+        return Err(ErrorCode::InvalidMetadata.into());
+    }
+    Data::deserialize(buf).map_err(|_| ErrorCode::InvalidMetadata)?;
+    let _primary_sale_happened = bool::deserialize(buf).map_err(|_| ErrorCode::InvalidMetadata)?;
+    let _is_mutable = bool::deserialize(buf).map_err(|_| ErrorCode::InvalidMetadata)?;
+
+    let _edition_nonce = appended_field::<u8>(buf)?;
+    let _token_standard = appended_field::<u8>(buf)?;
+    let collection = appended_field::<Collection>(buf)?;
+
+    Ok(collection.filter(|c| c.verified).map(|c| c.key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COLLECTION: Pubkey = Pubkey::new_from_array([7; 32]);
+
+    // Metadata account data up to `is_mutable`, as written by the first metadata version.
+    fn base_metadata(mint: &Pubkey) -> Vec<u8> {
+        let mut data = vec![4u8];
+        data.extend(Pubkey::new_unique().to_bytes());
+        data.extend(mint.to_bytes());
+        data.extend("name".to_string().try_to_vec().unwrap());
+        data.extend("SYM".to_string().try_to_vec().unwrap());
+        data.extend("https://example.com".to_string().try_to_vec().unwrap());
+        data.extend(500u16.try_to_vec().unwrap());
+        data.extend(Some(vec![(Pubkey::new_unique(), true, 100u8)]).try_to_vec().unwrap());
+        data.extend([1u8, 1u8]);
+        data
+    }
+
+    fn metadata_with_collection(mint: &Pubkey, verified: bool) -> Vec<u8> {
+        let mut data = base_metadata(mint);
+        data.extend(Some(255u8).try_to_vec().unwrap());
+        data.extend(Some(0u8).try_to_vec().unwrap());
+        data.extend(Some((verified, COLLECTION)).try_to_vec().unwrap());
+        data
+    }
+
+    fn read(key: &Pubkey, owner: &Pubkey, mint: &Pubkey, mut data: Vec<u8>) -> Result<Option<Pubkey>> {
+        let mut lamports = 0;
+        let info = AccountInfo::new(key, false, false, &mut lamports, &mut data, owner, false, 0);
+        verified_collection(&info, mint)
+    }
+
+    #[test]
+    fn reads_verified_collection() {
+        let mint = Pubkey::new_unique();
+        let key = find_metadata_address(&mint);
+        let owner = metadata_program_id();
+        assert_eq!(read(&key, &owner, &mint, metadata_with_collection(&mint, true)).unwrap(), Some(COLLECTION));
+        assert_eq!(read(&key, &owner, &mint, metadata_with_collection(&mint, false)).unwrap(), None);
+    }
+
+    #[test]
+    fn rejects_wrong_address() {
+        let mint = Pubkey::new_unique();
+        let key = find_metadata_address(&Pubkey::new_unique());
+        let owner = metadata_program_id();
+        assert!(read(&key, &owner, &mint, metadata_with_collection(&mint, true)).is_err());
+    }
+
+    #[test]
+    fn rejects_wrong_owner() {
+        let mint = Pubkey::new_unique();
+        let key = find_metadata_address(&mint);
+        assert!(read(&key, &Pubkey::new_unique(), &mint, metadata_with_collection(&mint, true)).is_err());
+    }
+
+    #[test]
+    fn empty_account_has_no_collection() {
+        let mint = Pubkey::new_unique();
+        let key = find_metadata_address(&mint);
+        assert_eq!(read(&key, &Pubkey::default(), &mint, vec![]).unwrap(), None);
+    }
+
+    #[test]
+    fn ignores_trailing_fields() {
+        let mint = Pubkey::new_unique();
+        let key = find_metadata_address(&mint);
+        let mut data = metadata_with_collection(&mint, true);
+        // uses, collection details and the zero padding of the account
+        data.extend(Some((0u8, 3u64, 3u64)).try_to_vec().unwrap());
+        data.extend([0u8; 64]);
+        assert_eq!(read(&key, &metadata_program_id(), &mint, data).unwrap(), Some(COLLECTION));
+    }
+
+    #[test]
+    fn old_account_without_collection() {
+        let mint = Pubkey::new_unique();
+        let key = find_metadata_address(&mint);
+        assert_eq!(read(&key, &metadata_program_id(), &mint, base_metadata(&mint)).unwrap(), None);
+    }
+
+    #[test]
+    fn rejects_malformed_appended_field() {
+        let mint = Pubkey::new_unique();
+        let key = find_metadata_address(&mint);
+        let mut data = base_metadata(&mint);
+        // invalid option tag for the edition nonce
+        data.push(2);
+        assert!(read(&key, &metadata_program_id(), &mint, data).is_err());
+
+        let mut data = base_metadata(&mint);
+        data.extend([0u8, 0u8, 1u8, 1u8]);
+        // collection cut short
+        assert!(read(&key, &metadata_program_id(), &mint, data).is_err());
+    }
+}
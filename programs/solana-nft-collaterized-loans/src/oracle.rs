@@ -0,0 +1,149 @@
+use anchor_lang::prelude::*;
+use std::convert::TryFrom;
+
+use crate::ErrorCode;
+
+// Magic number and version at the start of a floor price feed account
+pub const FEED_MAGIC: u32 = 0x464c_4f52;
+pub const FEED_VERSION: u32 = 1;
+
+// Floor price feed layout, price is `price * 10^expo` units of the quote currency.
+#[derive(AnchorDeserialize)]
+struct Feed {
+    magic: u32,
+    version: u32,
+    collection: Pubkey,
+    price: i64,
+    conf: u64,
+    expo: i32,
+    publish_time: i64,
+}
+
+pub struct FloorPrice {
+    // floor price in the smallest unit of the loan token
+    pub price: u64,
+    // unix timestamp of the last feed update
+    pub publish_time: u64,
+}
+
+// Reads the floor price of `collection` from a feed account owned by `oracle_program`,
+// converted to a token with `decimals` decimals. The collateral is valued at the low end of
+// the confidence interval, a wide interval can't push the loan-to-value below the limits.
+pub fn floor_price(feed: &AccountInfo, oracle_program: &Pubkey, collection: &Pubkey, decimals: u8) -> Result<FloorPrice> {
+    // nfts without a verified collection share no floor price
+    if *collection == Pubkey::default() {
+        return Err(ErrorCode::UnverifiedCollection.into());
+    }
+    if feed.owner != oracle_program {
+        return Err(ErrorCode::InvalidPriceFeed.into());
+    }
+    let data = feed.try_borrow_data()?;
+    let feed = Feed::deserialize(&mut &data[..]).map_err(|_| ErrorCode::InvalidPriceFeed)?;
+    if feed.magic != FEED_MAGIC || feed.version != FEED_VERSION || feed.collection != *collection || feed.price < 0 {
+        return Err(ErrorCode::InvalidPriceFeed.into());
+    }
+
+    let low = (feed.price as i128 - feed.conf as i128).max(0) as u128;
+    let exponent = feed.expo + decimals as i32;
+    let scale = 10u128
+        .checked_pow(exponent.unsigned_abs())
+        .ok_or(ErrorCode::MathOverflow)?;
+    let price = if exponent >= 0 {
+        low.checked_mul(scale).ok_or(ErrorCode::MathOverflow)?
+    } else {
+        low / scale
+    };
+
+    Ok(FloorPrice {
+        price: u64::try_from(price).map_err(|_| ErrorCode::MathOverflow)?,
+        publish_time: u64::try_from(feed.publish_time).map_err(|_| ErrorCode::InvalidPriceFeed)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn feed_data(magic: u32, collection: Pubkey, price: i64, conf: u64, expo: i32) -> Vec<u8> {
+        let mut data = vec![];
+        data.extend_from_slice(&magic.to_le_bytes());
+        data.extend_from_slice(&FEED_VERSION.to_le_bytes());
+        data.extend_from_slice(collection.as_ref());
+        data.extend_from_slice(&price.to_le_bytes());
+        data.extend_from_slice(&conf.to_le_bytes());
+        data.extend_from_slice(&expo.to_le_bytes());
+        data.extend_from_slice(&1_000i64.to_le_bytes());
+        data
+    }
+
+    fn read(data: &mut [u8], collection: &Pubkey, decimals: u8) -> Result<FloorPrice> {
+        let key = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+        let mut lamports = 0;
+        let feed = AccountInfo::new(&key, false, false, &mut lamports, data, &owner, false, 0);
+        floor_price(&feed, &owner, collection, decimals)
+    }
+
+    #[test]
+    fn positive_expo() {
+        let collection = Pubkey::new_unique();
+        let mut data = feed_data(FEED_MAGIC, collection, 12, 0, 1);
+        let floor = read(&mut data, &collection, 6).unwrap();
+        assert_eq!(floor.price, 120_000_000);
+        assert_eq!(floor.publish_time, 1_000);
+    }
+
+    #[test]
+    fn negative_expo() {
+        let collection = Pubkey::new_unique();
+        // 12.345678912 tokens, truncated to 6 decimals
+        let mut data = feed_data(FEED_MAGIC, collection, 12_345_678_912, 0, -9);
+        assert_eq!(read(&mut data, &collection, 6).unwrap().price, 12_345_678);
+    }
+
+    #[test]
+    fn values_at_low_end_of_confidence() {
+        let collection = Pubkey::new_unique();
+        let mut data = feed_data(FEED_MAGIC, collection, 100, 2, 0);
+        assert_eq!(read(&mut data, &collection, 6).unwrap().price, 98_000_000);
+        let mut data = feed_data(FEED_MAGIC, collection, 100, 200, 0);
+        assert_eq!(read(&mut data, &collection, 6).unwrap().price, 0);
+    }
+
+    #[test]
+    fn rejects_negative_price() {
+        let collection = Pubkey::new_unique();
+        let mut data = feed_data(FEED_MAGIC, collection, -1, 0, 0);
+        assert!(read(&mut data, &collection, 6).is_err());
+    }
+
+    #[test]
+    fn rejects_wrong_magic() {
+        let collection = Pubkey::new_unique();
+        let mut data = feed_data(0, collection, 100, 0, 0);
+        assert!(read(&mut data, &collection, 6).is_err());
+    }
+
+    #[test]
+    fn rejects_wrong_collection() {
+        let collection = Pubkey::new_unique();
+        let mut data = feed_data(FEED_MAGIC, Pubkey::new_unique(), 100, 0, 0);
+        assert!(read(&mut data, &collection, 6).is_err());
+    }
+
+    #[test]
+    fn rejects_unverified_collection() {
+        let mut data = feed_data(FEED_MAGIC, Pubkey::default(), 100, 0, 0);
+        assert!(read(&mut data, &Pubkey::default(), 6).is_err());
+    }
+
+    #[test]
+    fn rejects_wrong_owner() {
+        let collection = Pubkey::new_unique();
+        let mut data = feed_data(FEED_MAGIC, collection, 100, 0, 0);
+        let key = Pubkey::new_unique();
+        let mut lamports = 0;
+        let feed = AccountInfo::new(&key, false, false, &mut lamports, &mut data, &key, false, 0);
+        assert!(floor_price(&feed, &Pubkey::new_unique(), &collection, 6).is_err());
+    }
+}
@@ -1,7 +1,8 @@
 import * as anchor from "@project-serum/anchor";
 import {Program} from "@project-serum/anchor";
 import {SolanaNftCollaterizedLoans} from "../target/types/solana_nft_collaterized_loans";
-import {Token, TOKEN_PROGRAM_ID} from "@solana/spl-token";
+import {MockOracle} from "../target/types/mock_oracle";
+import {NATIVE_MINT, Token, TOKEN_PROGRAM_ID} from "@solana/spl-token";
 
 const utils = require('./utils');
 import * as fs from "fs";
@@ -11,15 +12,92 @@ const provider = anchor.Provider.env();
 anchor.setProvider(provider);
 
 const program = anchor.workspace.SolanaNftCollaterizedLoans as Program<SolanaNftCollaterizedLoans>;
+const mockOracle = anchor.workspace.MockOracle as Program<MockOracle>;
 
 const CONFIG_PDA_SEED = "config";
 const STABLE_COIN_PDA_SEED = "stable";
 const NFT_PDA_SEED = "nft";
 const ORDER_PDA_SEED = "order";
+const MARKET_PDA_SEED = "market";
+const OFFER_PDA_SEED = "offer";
+const COLLECTION_OFFER_PDA_SEED = "collection_offer";
+const TREASURY_PDA_SEED = "treasury";
+const WHITELIST_PDA_SEED = "whitelist";
+const BID_ESCROW_PDA_SEED = "bid_escrow";
 
-describe("solana-nft-collaterized-loans", () => {
-    const USDC = "Gh9ZwEmdLJ8DscKNTkTqPbNwLNNBjuSzaG9Vp2KGtKJr";
+// Protocol bounds set on initialize
+const MIN_PERIOD = new anchor.BN(60);
+const MAX_PERIOD = new anchor.BN(60 * 60 * 24 * 30);
+const MAX_INTEREST_RATE = new anchor.BN(2_000);
+const MIN_COLLATERAL_RATIO = new anchor.BN(500);
+const PROTOCOL_FEE_RATE = new anchor.BN(1_000);
+const ORIGINATION_FEE_RATE = new anchor.BN(50);
+const MIN_INTEREST_PERIOD = new anchor.BN(60 * 10);
+const GRACE_PERIOD = new anchor.BN(60 * 60 * 24);
+const LATE_FEE_RATE = new anchor.BN(10);
+const ORDER_LIFETIME = new anchor.BN(60 * 60 * 24 * 7);
+const AUCTION_START_MULTIPLIER = new anchor.BN(15_000);
+const AUCTION_DURATION = new anchor.BN(60 * 60 * 24);
+const BID_INCREMENT_RATE = new anchor.BN(500);
+const ANTI_SNIPE_PERIOD = new anchor.BN(60 * 10);
+const MAX_LTV = new anchor.BN(8_000);
+const MAX_PRICE_AGE = new anchor.BN(60 * 5);
+const MAINTENANCE_LTV = new anchor.BN(9_000);
+const SECONDS_PER_YEAR = 365 * 24 * 60 * 60;
+
+// Market bounds set on create market
+const MIN_REQUEST_AMOUNT = new anchor.BN(1_000_000);
+const MAX_REQUEST_AMOUNT = new anchor.BN(1000_000_000);
+
+// Loan terms used by the orders in these tests
+const REQUEST_AMOUNT = new anchor.BN(80_000_000);
+const INTEREST_RATE = new anchor.BN(1_500);
+const PERIOD = new anchor.BN(60 * 10);
+const ADDITIONAL_COLLATERAL = new anchor.BN(8_000_000);
+// Repaid well within the minimum interest period, so the minimum interest is charged
+const INTEREST = 228;
+// Fees taken by the protocol on the loan terms above
+const ORIGINATION_FEE = 400_000;
+const PROTOCOL_FEE = 22;
+
+// Floor price of a collection in whole tokens, published now unless a time is given
+const setFloorPrice = (feed: anchor.web3.PublicKey, collection: anchor.web3.PublicKey, price: number, publishTime: number = 0, conf: number = 0) => mockOracle.rpc.setPrice(collection, new anchor.BN(price), new anchor.BN(conf), 0, new anchor.BN(publishTime), {
+    accounts: {
+        feed: feed,
+        authority: provider.wallet.publicKey,
+    },
+});
+
+// Admin update of the configuration, starting from the defaults above with the given settings overridden
+async function setConfig(settings: any = {}) {
+    const [config, _] = await anchor.web3.PublicKey.findProgramAddress(
+        [
+            Buffer.from(CONFIG_PDA_SEED)
+        ], program.programId);
+    const s = {
+        minPeriod: MIN_PERIOD,
+        requireVerifiedCollection: false,
+        requireWhitelist: false,
+        minInterestPeriod: MIN_INTEREST_PERIOD,
+        gracePeriod: GRACE_PERIOD,
+        orderLifetime: ORDER_LIFETIME,
+        auctionDuration: AUCTION_DURATION,
+        antiSnipePeriod: ANTI_SNIPE_PERIOD,
+        oracleProgram: anchor.web3.PublicKey.default,
+        maintenanceLtv: MAINTENANCE_LTV,
+        ...settings,
+    };
+    await program.rpc.updateConfig(provider.wallet.publicKey, s.minPeriod, MAX_PERIOD, MAX_INTEREST_RATE, MIN_COLLATERAL_RATIO, s.requireVerifiedCollection, s.requireWhitelist, PROTOCOL_FEE_RATE, ORIGINATION_FEE_RATE, s.minInterestPeriod, s.gracePeriod, LATE_FEE_RATE, true, s.orderLifetime, AUCTION_START_MULTIPLIER, s.auctionDuration, BID_INCREMENT_RATE, s.antiSnipePeriod, s.oracleProgram, MAX_LTV, MAX_PRICE_AGE, s.maintenanceLtv, {
+        accounts: {
+            config: config,
+            admin: provider.wallet.publicKey,
+        },
+    });
+}
 
+const sleep = (seconds: number) => new Promise(resolve => setTimeout(resolve, seconds * 1000));
+
+describe("solana-nft-collaterized-loans", () => {
     let isTestPayBack: boolean = true;
     let stableCoinMintKeyPair: anchor.web3.Keypair;
     let stableCoinMintObject: Token;
@@ -36,14 +114,138 @@ describe("solana-nft-collaterized-loans", () => {
     let bobStableCoinWallet: anchor.web3.PublicKey;
     let bobNftWallet: anchor.web3.PublicKey;
 
+    // Stablecoins of the provider wallet, a third party buying and bidding in auctions
+    let walletStableCoinWallet: anchor.web3.PublicKey;
+
     let configKeyPair: anchor.web3.Keypair;
+    let priceFeed: anchor.web3.PublicKey;
+    let oracleCollection: anchor.web3.PublicKey;
+
+    // Alice lists a fresh nft for a loan of `period` seconds on the stablecoin market
+    const createLoanOrder = async (period: anchor.BN) => {
+        const [config, configBump] = await anchor.web3.PublicKey.findProgramAddress(
+            [
+                Buffer.from(CONFIG_PDA_SEED)
+            ], program.programId);
+        const [market, marketBump] = await anchor.web3.PublicKey.findProgramAddress(
+            [
+                stableCoinMintPubKey.toBuffer(),
+                Buffer.from(MARKET_PDA_SEED)
+            ], program.programId);
+        const [stable, stableBump] = await anchor.web3.PublicKey.findProgramAddress(
+            [
+                stableCoinMintPubKey.toBuffer(),
+                Buffer.from(STABLE_COIN_PDA_SEED)
+            ], program.programId);
+        const [nftMint, aliceNft] = await utils.createMetadataNFT(provider, alice.publicKey);
+        // @ts-ignore
+        const bobNft = await new Token(provider.connection, nftMint, TOKEN_PROGRAM_ID, provider.wallet.payer).createAssociatedTokenAccount(bob.publicKey);
+        const [nft, nftBump] = await anchor.web3.PublicKey.findProgramAddress(
+            [
+                nftMint.toBuffer(),
+                Buffer.from(NFT_PDA_SEED)
+            ], program.programId);
+        const orderId = (await program.account.configuration.fetch(config)).orderId;
+        const [order, orderBump] = await anchor.web3.PublicKey.findProgramAddress(
+            [
+                Buffer.from(orderId.toString()),
+                Buffer.from(ORDER_PDA_SEED),
+            ], program.programId);
+        await program.rpc.createOrder(stableBump, nftBump, orderBump, REQUEST_AMOUNT, INTEREST_RATE, period, ADDITIONAL_COLLATERAL, {
+            accounts: {
+                config: config,
+                market: market,
+                stableCoinMint: stableCoinMintPubKey,
+                stableCoinVault: stable,
+                userStableCoinVault: aliceStableCoinWallet,
+                nftMint: nftMint,
+                nftMetadata: await utils.getMetadataAddress(nftMint),
+                collectionWhitelist: await utils.getWhitelistAddress(program.programId, anchor.web3.PublicKey.default, market),
+                nftVault: nft,
+                userNftVault: aliceNft,
+                order: order,
+                borrower: alice.publicKey,
+                systemProgram: anchor.web3.SystemProgram.programId,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                rent: anchor.web3.SYSVAR_RENT_PUBKEY
+            },
+            signers: [alice]
+        });
+        return {config, market, stable, stableBump, nftMint, aliceNft, bobNft, nft, nftBump, orderId, order, period};
+    };
+
+    // Bob funds an order listed by createLoanOrder, agreeing to the given version of its terms
+    const giveLoan = async (loan, termsVersion: number = 0) => {
+        await program.rpc.giveLoan(loan.orderId, loan.stableBump, new anchor.BN(termsVersion), REQUEST_AMOUNT, INTEREST_RATE, loan.period, loan.nftMint, {
+            accounts: {
+                config: loan.config,
+                market: loan.market,
+                order: loan.order,
+                stableCoinMint: stableCoinMintPubKey,
+                stableCoinVault: loan.stable,
+                treasuryVault: await utils.getTreasuryAddress(program.programId, stableCoinMintPubKey),
+                lenderStableCoinVault: bobStableCoinWallet,
+                borrowerStableCoinVault: aliceStableCoinWallet,
+                lender: bob.publicKey,
+                collectionWhitelist: await utils.getWhitelistAddress(program.programId, anchor.web3.PublicKey.default, loan.market),
+                priceFeed: anchor.web3.PublicKey.default,
+                systemProgram: anchor.web3.SystemProgram.programId,
+                tokenProgram: TOKEN_PROGRAM_ID,
+            },
+            signers: [bob],
+        });
+    };
+
+    const payback = async (loan) => {
+        await program.rpc.payback(loan.orderId, loan.stableBump, loan.nftBump, {
+            accounts: {
+                config: loan.config,
+                market: loan.market,
+                order: loan.order,
+                stableCoinMint: stableCoinMintPubKey,
+                stableCoinVault: loan.stable,
+                treasuryVault: await utils.getTreasuryAddress(program.programId, stableCoinMintPubKey),
+                lenderStableCoinVault: bobStableCoinWallet,
+                borrowerStableCoinVault: aliceStableCoinWallet,
+                nftMint: loan.nftMint,
+                nftVault: loan.nft,
+                borrowerNftVault: loan.aliceNft,
+                receipt: await utils.getReceiptAddress(program.programId, loan.order),
+                borrower: alice.publicKey,
+                systemProgram: anchor.web3.SystemProgram.programId,
+                tokenProgram: TOKEN_PROGRAM_ID,
+            },
+            signers: [alice],
+        });
+    };
+
+    const liquidate = async (loan) => {
+        await program.rpc.liquidate(loan.orderId, loan.stableBump, loan.nftBump, {
+            accounts: {
+                config: loan.config,
+                market: loan.market,
+                order: loan.order,
+                stableCoinMint: stableCoinMintPubKey,
+                stableCoinVault: loan.stable,
+                lenderStableCoinVault: bobStableCoinWallet,
+                nftMint: loan.nftMint,
+                nftVault: loan.nft,
+                lenderNftVault: loan.bobNft,
+                receipt: await utils.getReceiptAddress(program.programId, loan.order),
+                lender: bob.publicKey,
+                borrower: alice.publicKey,
+                priceFeed: anchor.web3.PublicKey.default,
+                systemProgram: anchor.web3.SystemProgram.programId,
+                tokenProgram: TOKEN_PROGRAM_ID,
+            },
+            signers: [bob]
+        });
+    };
 
     it('Prepare', async () => {
         configKeyPair = anchor.web3.Keypair.generate()
-        // Create StableCoin
-        let keyPairFile = fs.readFileSync('/home/alex/blockchain/solana-nft-collaterized-loans/tests/keys/stablecoin.json', "utf-8");
-        let keyPairData = JSON.parse(keyPairFile);
-        stableCoinMintKeyPair = anchor.web3.Keypair.fromSecretKey(new Uint8Array(keyPairData));
+        // Create StableCoin, any mint can be used as the configured stablecoin
+        stableCoinMintKeyPair = anchor.web3.Keypair.generate();
         stableCoinMintObject = await utils.createMint(stableCoinMintKeyPair, provider, provider.wallet.publicKey, null, 6, TOKEN_PROGRAM_ID);
         stableCoinMintPubKey = stableCoinMintObject.publicKey;
 
@@ -87,6 +289,9 @@ describe("solana-nft-collaterized-loans", () => {
         // Airdrop StableCoin To Bob
         await utils.mintToAccount(provider, stableCoinMintPubKey, bobStableCoinWallet, 1000_000_000);
 
+        walletStableCoinWallet = await stableCoinMintObject.createAssociatedTokenAccount(provider.wallet.publicKey);
+        await utils.mintToAccount(provider, stableCoinMintPubKey, walletStableCoinWallet, 1000_000_000);
+
         // Create Nft Token
         let mintKeyNft = anchor.web3.Keypair.generate();
         nftMintObject = await utils.createMint(mintKeyNft, provider, provider.wallet.publicKey, null, 0, TOKEN_PROGRAM_ID);
@@ -111,26 +316,84 @@ describe("solana-nft-collaterized-loans", () => {
             [
                 Buffer.from(CONFIG_PDA_SEED)
             ], program.programId);
+        await program.rpc.initialize(configBump, MIN_PERIOD, MAX_PERIOD, MAX_INTEREST_RATE, MIN_COLLATERAL_RATIO, false, false, PROTOCOL_FEE_RATE, ORIGINATION_FEE_RATE, MIN_INTEREST_PERIOD, GRACE_PERIOD, LATE_FEE_RATE, true, ORDER_LIFETIME, AUCTION_START_MULTIPLIER, AUCTION_DURATION, BID_INCREMENT_RATE, ANTI_SNIPE_PERIOD, anchor.web3.PublicKey.default, MAX_LTV, MAX_PRICE_AGE, MAINTENANCE_LTV, {
+            accounts: {
+                signer: provider.wallet.publicKey,
+                configuration: config,
+                systemProgram: anchor.web3.SystemProgram.programId,
+                rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+            },
+            // @ts-ignore
+            signers: [provider.wallet.payer],
+        });
+        const fetch = await program.account.configuration.fetch(config);
+        console.log(fetch);
+        assert.strictEqual(fetch.admin.toString(), provider.wallet.publicKey.toString());
+    });
+
+    it('Update Config', async () => {
+        const [config, configBump] = await anchor.web3.PublicKey.findProgramAddress(
+            [
+                Buffer.from(CONFIG_PDA_SEED)
+            ], program.programId);
+
+        // Only the admin can update the configuration
+        await assert.rejects(program.rpc.updateConfig(alice.publicKey, MIN_PERIOD, MAX_PERIOD, MAX_INTEREST_RATE, MIN_COLLATERAL_RATIO, false, false, PROTOCOL_FEE_RATE, ORIGINATION_FEE_RATE, MIN_INTEREST_PERIOD, GRACE_PERIOD, LATE_FEE_RATE, true, ORDER_LIFETIME, AUCTION_START_MULTIPLIER, AUCTION_DURATION, BID_INCREMENT_RATE, ANTI_SNIPE_PERIOD, anchor.web3.PublicKey.default, MAX_LTV, MAX_PRICE_AGE, MAINTENANCE_LTV, {
+            accounts: {
+                config: config,
+                admin: alice.publicKey,
+            },
+            signers: [alice]
+        }));
+
+        await program.rpc.updateConfig(provider.wallet.publicKey, MIN_PERIOD, MAX_PERIOD, MAX_INTEREST_RATE, MIN_COLLATERAL_RATIO, false, false, PROTOCOL_FEE_RATE, ORIGINATION_FEE_RATE, MIN_INTEREST_PERIOD, GRACE_PERIOD, LATE_FEE_RATE, true, ORDER_LIFETIME, AUCTION_START_MULTIPLIER, AUCTION_DURATION, BID_INCREMENT_RATE, ANTI_SNIPE_PERIOD, anchor.web3.PublicKey.default, MAX_LTV, MAX_PRICE_AGE, MAINTENANCE_LTV, {
+            accounts: {
+                config: config,
+                admin: provider.wallet.publicKey,
+            },
+        });
+        const fetch = await program.account.configuration.fetch(config);
+        assert.strictEqual(fetch.minCollateralRatio.toString(), MIN_COLLATERAL_RATIO.toString());
+        assert.strictEqual(fetch.gracePeriod.toString(), GRACE_PERIOD.toString());
+    });
+
+    it('Create Market', async () => {
+        const [config, configBump] = await anchor.web3.PublicKey.findProgramAddress(
+            [
+                Buffer.from(CONFIG_PDA_SEED)
+            ], program.programId);
+        const [market, marketBump] = await anchor.web3.PublicKey.findProgramAddress(
+            [
+                stableCoinMintPubKey.toBuffer(),
+                Buffer.from(MARKET_PDA_SEED)
+            ], program.programId);
         const [stable, stableBump] = await anchor.web3.PublicKey.findProgramAddress(
             [
                 stableCoinMintPubKey.toBuffer(),
                 Buffer.from(STABLE_COIN_PDA_SEED)
             ], program.programId);
-        await program.rpc.initialize(configBump, stableBump, {
+        const [treasury, treasuryBump] = await anchor.web3.PublicKey.findProgramAddress(
+            [
+                stableCoinMintPubKey.toBuffer(),
+                Buffer.from(TREASURY_PDA_SEED)
+            ], program.programId);
+        await program.rpc.createMarket(marketBump, stableBump, MIN_REQUEST_AMOUNT, MAX_REQUEST_AMOUNT, {
             accounts: {
-                signer: provider.wallet.publicKey,
-                configuration: config,
+                config: config,
+                market: market,
                 stableCoinMint: stableCoinMintPubKey,
                 stableCoinVault: stable,
+                treasuryVault: treasury,
+                admin: provider.wallet.publicKey,
                 systemProgram: anchor.web3.SystemProgram.programId,
                 tokenProgram: TOKEN_PROGRAM_ID,
                 rent: anchor.web3.SYSVAR_RENT_PUBKEY,
             },
-            // @ts-ignore
-            signers: [provider.wallet.payer],
         });
-        const fetch = await program.account.configuration.fetch(config);
-        console.log(fetch);
+        const fetch = await program.account.market.fetch(market);
+        assert.strictEqual(fetch.stableCoinMint.toString(), stableCoinMintPubKey.toString());
+        assert.strictEqual(fetch.stableCoinVault.toString(), stable.toString());
+        assert.strictEqual(fetch.treasuryVault.toString(), treasury.toString());
     });
 
     it('Create Order', async () => {
@@ -143,6 +406,11 @@ describe("solana-nft-collaterized-loans", () => {
             [
                 Buffer.from(CONFIG_PDA_SEED)
             ], program.programId);
+        const [market, marketBump] = await anchor.web3.PublicKey.findProgramAddress(
+            [
+                stableCoinMintPubKey.toBuffer(),
+                Buffer.from(MARKET_PDA_SEED)
+            ], program.programId);
         const [stable, stableBump] = await anchor.web3.PublicKey.findProgramAddress(
             [
                 stableCoinMintPubKey.toBuffer(),
@@ -158,13 +426,16 @@ describe("solana-nft-collaterized-loans", () => {
             Buffer.from(ORDER_PDA_SEED),
         ], program.programId);
 
-        await program.rpc.createOrder(stableBump, nftBump, orderBump, {
+        await program.rpc.createOrder(stableBump, nftBump, orderBump, REQUEST_AMOUNT, INTEREST_RATE, PERIOD, ADDITIONAL_COLLATERAL, {
             accounts: {
                 config: config,
+                market: market,
                 stableCoinMint: stableCoinMintPubKey,
                 stableCoinVault: stable,
                 userStableCoinVault: aliceStableCoinWallet,
                 nftMint: nftMintPubKey,
+                nftMetadata: await utils.getMetadataAddress(nftMintPubKey),
+                collectionWhitelist: await utils.getWhitelistAddress(program.programId, anchor.web3.PublicKey.default, market),
                 nftVault: nft,
                 userNftVault: aliceNftWallet,
                 order: order,
@@ -178,6 +449,8 @@ describe("solana-nft-collaterized-loans", () => {
 
         const fetch = await program.account.configuration.fetch(config);
         assert.strictEqual(fetch.orderId.toString(), "1");
+        const fetchOrder = await program.account.order.fetch(order);
+        assert.strictEqual(fetchOrder.expiresAt.toString(), fetchOrder.createdAt.add(ORDER_LIFETIME).toString());
 
         // Check alice wallet after create Order (ID: 0)
         assert.strictEqual(await utils.getTokenBalance(provider, aliceStableCoinWallet), 992_000_000);
@@ -198,6 +471,11 @@ describe("solana-nft-collaterized-loans", () => {
             [
                 Buffer.from(CONFIG_PDA_SEED)
             ], program.programId);
+        const [market, marketBump] = await anchor.web3.PublicKey.findProgramAddress(
+            [
+                stableCoinMintPubKey.toBuffer(),
+                Buffer.from(MARKET_PDA_SEED)
+            ], program.programId);
         const [order, orderBump] = await anchor.web3.PublicKey.findProgramAddress(
             [
                 Buffer.from(new anchor.BN(0).toString()),
@@ -218,6 +496,7 @@ describe("solana-nft-collaterized-loans", () => {
         await program.rpc.cancelOrder(new anchor.BN(0), stableBump, nftBump, {
             accounts: {
                 config: config,
+                market: market,
                 order: order,
                 stableCoinMint: stableCoinMintPubKey,
                 stableCoinVault: stable,
@@ -225,6 +504,7 @@ describe("solana-nft-collaterized-loans", () => {
                 nftMint: nftMintPubKey,
                 nftVault: nft,
                 userNftVault: aliceNftWallet,
+                receipt: await utils.getReceiptAddress(program.programId, order),
                 borrower: alice.publicKey,
                 systemProgram: anchor.web3.SystemProgram.programId,
                 tokenProgram: TOKEN_PROGRAM_ID,
@@ -249,6 +529,11 @@ describe("solana-nft-collaterized-loans", () => {
             [
                 Buffer.from(CONFIG_PDA_SEED)
             ], program.programId);
+        const [market, marketBump] = await anchor.web3.PublicKey.findProgramAddress(
+            [
+                stableCoinMintPubKey.toBuffer(),
+                Buffer.from(MARKET_PDA_SEED)
+            ], program.programId);
         const [stable, stableBump] = await anchor.web3.PublicKey.findProgramAddress(
             [
                 stableCoinMintPubKey.toBuffer(),
@@ -265,13 +550,16 @@ describe("solana-nft-collaterized-loans", () => {
                 Buffer.from(ORDER_PDA_SEED),
             ], program.programId
         );
-        await program.rpc.createOrder(stableBump, nftBump, orderBump, {
+        await program.rpc.createOrder(stableBump, nftBump, orderBump, REQUEST_AMOUNT, INTEREST_RATE, PERIOD, ADDITIONAL_COLLATERAL, {
             accounts: {
                 config: config,
+                market: market,
                 stableCoinMint: stableCoinMintPubKey,
                 stableCoinVault: stable,
                 userStableCoinVault: aliceStableCoinWallet,
                 nftMint: nftMintPubKey,
+                nftMetadata: await utils.getMetadataAddress(nftMintPubKey),
+                collectionWhitelist: await utils.getWhitelistAddress(program.programId, anchor.web3.PublicKey.default, market),
                 nftVault: nft,
                 userNftVault: aliceNftWallet,
                 order: order,
@@ -290,15 +578,19 @@ describe("solana-nft-collaterized-loans", () => {
         assert.strictEqual(await utils.getTokenBalance(provider, stable), 8_000_000);
         assert.strictEqual(await utils.getTokenBalance(provider, nft), 1);
 
-        await program.rpc.giveLoan(new anchor.BN(1), stableBump, {
+        await program.rpc.giveLoan(new anchor.BN(1), stableBump, new anchor.BN(0), REQUEST_AMOUNT, INTEREST_RATE, PERIOD, nftMintPubKey, {
             accounts: {
                 config: config,
+                market: market,
                 order: order,
                 stableCoinMint: stableCoinMintPubKey,
                 stableCoinVault: stable,
+                treasuryVault: await utils.getTreasuryAddress(program.programId, stableCoinMintPubKey),
                 lenderStableCoinVault: bobStableCoinWallet,
                 borrowerStableCoinVault: aliceStableCoinWallet,
                 lender: bob.publicKey,
+                collectionWhitelist: await utils.getWhitelistAddress(program.programId, anchor.web3.PublicKey.default, market),
+                priceFeed: anchor.web3.PublicKey.default,
                 systemProgram: anchor.web3.SystemProgram.programId,
                 tokenProgram: TOKEN_PROGRAM_ID,
             },
@@ -306,7 +598,7 @@ describe("solana-nft-collaterized-loans", () => {
         });
 
         // Check alice wallet after GiveLoan (ID: 1)
-        assert.strictEqual(await utils.getTokenBalance(provider, aliceStableCoinWallet), 1072_000_000);
+        assert.strictEqual(await utils.getTokenBalance(provider, aliceStableCoinWallet), 1072_000_000 - ORIGINATION_FEE);
         // Check bob wallet after GiveLoan (ID: 1)
         assert.strictEqual(await utils.getTokenBalance(provider, bobStableCoinWallet), 920_000_000);
 
@@ -314,7 +606,7 @@ describe("solana-nft-collaterized-loans", () => {
         const fetch = await program.account.order.fetch(order);
         assert.strictEqual(fetch.lender.toString(), bob.publicKey.toString());
         assert.strictEqual(fetch.loanStartTime == new anchor.BN(0), false);
-        assert.strictEqual(fetch.orderStatus, false);
+        assert.deepStrictEqual(fetch.state, { active: {} });
     });
 
     it("PayBack", async () => {
@@ -328,6 +620,11 @@ describe("solana-nft-collaterized-loans", () => {
                 [
                     Buffer.from(CONFIG_PDA_SEED)
                 ], program.programId);
+            const [market, marketBump] = await anchor.web3.PublicKey.findProgramAddress(
+                [
+                    stableCoinMintPubKey.toBuffer(),
+                    Buffer.from(MARKET_PDA_SEED)
+                ], program.programId);
             const [order, orderBump] = await anchor.web3.PublicKey.findProgramAddress(
                 [
                     Buffer.from(new anchor.BN(1).toString()),
@@ -348,14 +645,17 @@ describe("solana-nft-collaterized-loans", () => {
             await program.rpc.payback(new anchor.BN(1), stableBump, nftBump, {
                 accounts: {
                     config: config,
+                    market: market,
                     order: order,
                     stableCoinMint: stableCoinMintPubKey,
                     stableCoinVault: stable,
+                    treasuryVault: await utils.getTreasuryAddress(program.programId, stableCoinMintPubKey),
                     lenderStableCoinVault: bobStableCoinWallet,
                     borrowerStableCoinVault: aliceStableCoinWallet,
                     nftMint: nftMintPubKey,
                     nftVault: nft,
                     borrowerNftVault: aliceNftWallet,
+                    receipt: await utils.getReceiptAddress(program.programId, order),
                     borrower: alice.publicKey,
                     systemProgram: anchor.web3.SystemProgram.programId,
                     tokenProgram: TOKEN_PROGRAM_ID,
@@ -364,12 +664,28 @@ describe("solana-nft-collaterized-loans", () => {
             });
 
             // Check alice wallet after PayBack (ID: 1)
-            assert.strictEqual(await utils.getTokenBalance(provider, aliceStableCoinWallet), 995_200_000);
+            assert.strictEqual(await utils.getTokenBalance(provider, aliceStableCoinWallet), 1000_000_000 - INTEREST - ORIGINATION_FEE);
             assert.strictEqual(await utils.getTokenBalance(provider, aliceNftWallet), 1);
             // Check bob wallet after PayBack (ID: 1)
-            assert.strictEqual(await utils.getTokenBalance(provider, bobStableCoinWallet), 1004_800_000);
+            assert.strictEqual(await utils.getTokenBalance(provider, bobStableCoinWallet), 1000_000_000 + INTEREST - PROTOCOL_FEE);
             // Check vault after PayBack (ID: 1)
             assert.strictEqual(await utils.getTokenBalance(provider, stable), 0);
+
+            // The settled loan is kept as a receipt until alice reclaims the rent
+            const receipt = await utils.getReceiptAddress(program.programId, order);
+            const fetch = await program.account.loanReceipt.fetch(receipt);
+            assert.deepStrictEqual(fetch.state, { repaid: {} });
+            assert.strictEqual(fetch.amountRepaid.toNumber(), REQUEST_AMOUNT.toNumber() + INTEREST);
+            assert.strictEqual(fetch.paidBackAt.toString(), fetch.closedAt.toString());
+            assert.strictEqual(fetch.withdrewAt.toNumber(), 0);
+            await program.rpc.closeReceipt({
+                accounts: {
+                    receipt: receipt,
+                    rentPayer: alice.publicKey,
+                },
+                signers: [alice],
+            });
+            assert.strictEqual(await provider.connection.getAccountInfo(receipt), null);
         } else {
             console.log("Skip PayBack");
         }
@@ -386,6 +702,11 @@ describe("solana-nft-collaterized-loans", () => {
                 [
                     Buffer.from(CONFIG_PDA_SEED)
                 ], program.programId);
+            const [market, marketBump] = await anchor.web3.PublicKey.findProgramAddress(
+                [
+                    stableCoinMintPubKey.toBuffer(),
+                    Buffer.from(MARKET_PDA_SEED)
+                ], program.programId);
             const [stable, stableBump] = await anchor.web3.PublicKey.findProgramAddress(
                 [
                     stableCoinMintPubKey.toBuffer(),
@@ -405,6 +726,7 @@ describe("solana-nft-collaterized-loans", () => {
             await program.rpc.liquidate(new anchor.BN(1),stableBump, nftBump,  {
                 accounts: {
                     config: config,
+                    market: market,
                     order: order,
                     stableCoinMint: stableCoinMintPubKey,
                     stableCoinVault: stable,
@@ -412,8 +734,10 @@ describe("solana-nft-collaterized-loans", () => {
                     nftMint: nftMintPubKey,
                     nftVault: nft,
                     lenderNftVault: bobNftWallet,
+                    receipt: await utils.getReceiptAddress(program.programId, order),
                     lender: bob.publicKey,
                     borrower: alice.publicKey,
+                    priceFeed: anchor.web3.PublicKey.default,
                     systemProgram: anchor.web3.SystemProgram.programId,
                     tokenProgram: TOKEN_PROGRAM_ID,
                 },
@@ -421,7 +745,7 @@ describe("solana-nft-collaterized-loans", () => {
             });
 
             // Check alice wallet after Liquidity (ID: 1)
-            assert.strictEqual(await utils.getTokenBalance(provider, aliceStableCoinWallet), 1072_000_000);
+            assert.strictEqual(await utils.getTokenBalance(provider, aliceStableCoinWallet), 1072_000_000 - ORIGINATION_FEE);
             assert.strictEqual(await utils.getTokenBalance(provider, aliceNftWallet), 0);
 
             // Check bob wallet after Liquidity (ID: 1)
@@ -434,4 +758,2139 @@ describe("solana-nft-collaterized-loans", () => {
             console.log("Skip Liquidity");
         }
     })
+
+    it("Native SOL Loan", async () => {
+        const requestAmount = new anchor.BN(1_000_000_000);
+        const interestRate = new anchor.BN(1_000);
+        const additionalCollateral = new anchor.BN(100_000_000);
+
+        const [config, configBump] = await anchor.web3.PublicKey.findProgramAddress(
+            [
+                Buffer.from(CONFIG_PDA_SEED)
+            ], program.programId);
+        const [market, marketBump] = await anchor.web3.PublicKey.findProgramAddress(
+            [
+                NATIVE_MINT.toBuffer(),
+                Buffer.from(MARKET_PDA_SEED)
+            ], program.programId);
+        const [stable, stableBump] = await anchor.web3.PublicKey.findProgramAddress(
+            [
+                NATIVE_MINT.toBuffer(),
+                Buffer.from(STABLE_COIN_PDA_SEED)
+            ], program.programId);
+        const [treasury, treasuryBump] = await anchor.web3.PublicKey.findProgramAddress(
+            [
+                NATIVE_MINT.toBuffer(),
+                Buffer.from(TREASURY_PDA_SEED)
+            ], program.programId);
+        await program.rpc.createMarket(marketBump, stableBump, new anchor.BN(1_000_000), new anchor.BN(100_000_000_000), {
+            accounts: {
+                config: config,
+                market: market,
+                stableCoinMint: NATIVE_MINT,
+                stableCoinVault: stable,
+                treasuryVault: treasury,
+                admin: provider.wallet.publicKey,
+                systemProgram: anchor.web3.SystemProgram.programId,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+            },
+        });
+
+        // New nft for alice
+        let mintKeyNft = anchor.web3.Keypair.generate();
+        const solNftMintObject = await utils.createMint(mintKeyNft, provider, provider.wallet.publicKey, null, 0, TOKEN_PROGRAM_ID);
+        const solNftMintPubKey = solNftMintObject.publicKey;
+        const aliceSolNftWallet = await solNftMintObject.createAssociatedTokenAccount(alice.publicKey);
+        await utils.mintToAccount(provider, solNftMintPubKey, aliceSolNftWallet, 1);
+
+        const [nft, nftBump] = await anchor.web3.PublicKey.findProgramAddress(
+            [
+                solNftMintPubKey.toBuffer(),
+                Buffer.from(NFT_PDA_SEED)
+            ], program.programId);
+        const [order, orderBump] = await anchor.web3.PublicKey.findProgramAddress(
+            [
+                Buffer.from(new anchor.BN(2).toString()),
+                Buffer.from(ORDER_PDA_SEED),
+            ], program.programId
+        );
+
+        await program.rpc.createSolOrder(nftBump, orderBump, requestAmount, interestRate, PERIOD, additionalCollateral, {
+            accounts: {
+                config: config,
+                market: market,
+                nftMint: solNftMintPubKey,
+                nftMetadata: await utils.getMetadataAddress(solNftMintPubKey),
+                collectionWhitelist: await utils.getWhitelistAddress(program.programId, anchor.web3.PublicKey.default, market),
+                nftVault: nft,
+                userNftVault: aliceSolNftWallet,
+                order: order,
+                borrower: alice.publicKey,
+                systemProgram: anchor.web3.SystemProgram.programId,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                rent: anchor.web3.SYSVAR_RENT_PUBKEY
+            },
+            signers: [alice]
+        });
+        assert.strictEqual(await utils.getTokenBalance(provider, nft), 1);
+        let fetch = await program.account.order.fetch(order);
+        assert.strictEqual(fetch.isNative, true);
+
+        const bobBalanceBefore = await provider.connection.getBalance(bob.publicKey);
+        await program.rpc.giveSolLoan(new anchor.BN(2), new anchor.BN(0), requestAmount, interestRate, PERIOD, solNftMintPubKey, {
+            accounts: {
+                config: config,
+                market: market,
+                order: order,
+                borrower: alice.publicKey,
+                lender: bob.publicKey,
+                collectionWhitelist: await utils.getWhitelistAddress(program.programId, anchor.web3.PublicKey.default, market),
+                priceFeed: anchor.web3.PublicKey.default,
+                systemProgram: anchor.web3.SystemProgram.programId,
+            },
+            signers: [bob],
+        });
+        const bobBalanceAfterLoan = await provider.connection.getBalance(bob.publicKey);
+        // lender pays the loan and the transaction fee
+        assert.ok(bobBalanceBefore - bobBalanceAfterLoan >= requestAmount.toNumber());
+
+        await program.rpc.paybackSol(new anchor.BN(2), nftBump, {
+            accounts: {
+                config: config,
+                market: market,
+                order: order,
+                lender: bob.publicKey,
+                nftMint: solNftMintPubKey,
+                nftVault: nft,
+                borrowerNftVault: aliceSolNftWallet,
+                receipt: await utils.getReceiptAddress(program.programId, order),
+                borrower: alice.publicKey,
+                systemProgram: anchor.web3.SystemProgram.programId,
+                tokenProgram: TOKEN_PROGRAM_ID,
+            },
+            signers: [alice],
+        });
+        assert.strictEqual(await utils.getTokenBalance(provider, aliceSolNftWallet), 1);
+        // lender receives the minimum interest minus the protocol fee
+        const interest = requestAmount.mul(interestRate).mul(MIN_INTEREST_PERIOD).div(new anchor.BN(10_000 * SECONDS_PER_YEAR));
+        const protocolFee = interest.mul(PROTOCOL_FEE_RATE).divn(10_000);
+        const originationFee = requestAmount.mul(ORIGINATION_FEE_RATE).divn(10_000);
+        assert.strictEqual(await provider.connection.getBalance(bob.publicKey), bobBalanceAfterLoan + requestAmount.add(interest).sub(protocolFee).toNumber());
+        const fetchMarket = await program.account.market.fetch(market);
+        assert.strictEqual(fetchMarket.solTreasury.toString(), protocolFee.add(originationFee).toString());
+    });
+
+    it("Native SOL Partial Repayment", async () => {
+        const requestAmount = new anchor.BN(1_000_000_000);
+        const interestRate = new anchor.BN(1_000);
+        const additionalCollateral = new anchor.BN(100_000_000);
+
+        const [config, configBump] = await anchor.web3.PublicKey.findProgramAddress(
+            [
+                Buffer.from(CONFIG_PDA_SEED)
+            ], program.programId);
+        const [market, marketBump] = await anchor.web3.PublicKey.findProgramAddress(
+            [
+                NATIVE_MINT.toBuffer(),
+                Buffer.from(MARKET_PDA_SEED)
+            ], program.programId);
+
+        // New nft for alice
+        let mintKeyNft = anchor.web3.Keypair.generate();
+        const solNftMintObject = await utils.createMint(mintKeyNft, provider, provider.wallet.publicKey, null, 0, TOKEN_PROGRAM_ID);
+        const solNftMintPubKey = solNftMintObject.publicKey;
+        const aliceSolNftWallet = await solNftMintObject.createAssociatedTokenAccount(alice.publicKey);
+        await utils.mintToAccount(provider, solNftMintPubKey, aliceSolNftWallet, 1);
+
+        const [nft, nftBump] = await anchor.web3.PublicKey.findProgramAddress(
+            [
+                solNftMintPubKey.toBuffer(),
+                Buffer.from(NFT_PDA_SEED)
+            ], program.programId);
+        const orderId = (await program.account.configuration.fetch(config)).orderId;
+        const [order, orderBump] = await anchor.web3.PublicKey.findProgramAddress(
+            [
+                Buffer.from(orderId.toString()),
+                Buffer.from(ORDER_PDA_SEED),
+            ], program.programId);
+
+        await program.rpc.createSolOrder(nftBump, orderBump, requestAmount, interestRate, PERIOD, additionalCollateral, {
+            accounts: {
+                config: config,
+                market: market,
+                nftMint: solNftMintPubKey,
+                nftMetadata: await utils.getMetadataAddress(solNftMintPubKey),
+                collectionWhitelist: await utils.getWhitelistAddress(program.programId, anchor.web3.PublicKey.default, market),
+                nftVault: nft,
+                userNftVault: aliceSolNftWallet,
+                order: order,
+                borrower: alice.publicKey,
+                systemProgram: anchor.web3.SystemProgram.programId,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                rent: anchor.web3.SYSVAR_RENT_PUBKEY
+            },
+            signers: [alice]
+        });
+
+        // Installment schedules are only available on spl loans
+        try {
+            await program.rpc.setInstallmentSchedule(orderId, requestAmount.divn(2), PERIOD.divn(2), {
+                accounts: {
+                    order: order,
+                    borrower: alice.publicKey,
+                },
+                signers: [alice]
+            });
+            assert.fail("installment schedule set on a native order");
+        } catch (err) {
+            assert.strictEqual(err.msg, "Not supported for native SOL loans");
+        }
+
+        await program.rpc.giveSolLoan(orderId, new anchor.BN(0), requestAmount, interestRate, PERIOD, solNftMintPubKey, {
+            accounts: {
+                config: config,
+                market: market,
+                order: order,
+                borrower: alice.publicKey,
+                lender: bob.publicKey,
+                collectionWhitelist: await utils.getWhitelistAddress(program.programId, anchor.web3.PublicKey.default, market),
+                priceFeed: anchor.web3.PublicKey.default,
+                systemProgram: anchor.web3.SystemProgram.programId,
+            },
+            signers: [bob],
+        });
+
+        const repayPartialSol = (amount: anchor.BN) => program.rpc.repayPartialSol(orderId, amount, {
+            accounts: {
+                config: config,
+                market: market,
+                order: order,
+                lender: bob.publicKey,
+                borrower: alice.publicKey,
+                systemProgram: anchor.web3.SystemProgram.programId,
+            },
+            signers: [alice]
+        });
+
+        // Clearing the whole balance must go through payback_sol, which releases the nft
+        await assert.rejects(repayPartialSol(requestAmount.muln(2)));
+
+        const bobBalanceBefore = await provider.connection.getBalance(bob.publicKey);
+        const solTreasuryBefore = (await program.account.market.fetch(market)).solTreasury;
+        const amount = requestAmount.divn(2);
+        await repayPartialSol(amount);
+
+        const fetch = await program.account.order.fetch(order);
+        assert.strictEqual(fetch.repaymentCount.toString(), "1");
+        assert.strictEqual(fetch.principalRepaid.add(fetch.repayments[0].interest).toString(), amount.toString());
+        // the protocol fee on the interest is kept by the market
+        const protocolFee = fetch.repayments[0].interest.mul(PROTOCOL_FEE_RATE).divn(10_000);
+        assert.strictEqual(await provider.connection.getBalance(bob.publicKey), bobBalanceBefore + amount.sub(protocolFee).toNumber());
+        const fetchMarket = await program.account.market.fetch(market);
+        assert.strictEqual(fetchMarket.solTreasury.toString(), solTreasuryBefore.add(protocolFee).toString());
+        assert.strictEqual(await utils.getTokenBalance(provider, nft), 1);
+    });
+
+    it("Offer", async () => {
+        const [config, configBump] = await anchor.web3.PublicKey.findProgramAddress(
+            [
+                Buffer.from(CONFIG_PDA_SEED)
+            ], program.programId);
+        const [market, marketBump] = await anchor.web3.PublicKey.findProgramAddress(
+            [
+                stableCoinMintPubKey.toBuffer(),
+                Buffer.from(MARKET_PDA_SEED)
+            ], program.programId);
+        const [stable, stableBump] = await anchor.web3.PublicKey.findProgramAddress(
+            [
+                stableCoinMintPubKey.toBuffer(),
+                Buffer.from(STABLE_COIN_PDA_SEED)
+            ], program.programId);
+
+        // New nft for alice
+        let mintKeyNft = anchor.web3.Keypair.generate();
+        const offerNftMintObject = await utils.createMint(mintKeyNft, provider, provider.wallet.publicKey, null, 0, TOKEN_PROGRAM_ID);
+        const offerNftMintPubKey = offerNftMintObject.publicKey;
+        const aliceOfferNftWallet = await offerNftMintObject.createAssociatedTokenAccount(alice.publicKey);
+        await utils.mintToAccount(provider, offerNftMintPubKey, aliceOfferNftWallet, 1);
+
+        const [nft, nftBump] = await anchor.web3.PublicKey.findProgramAddress(
+            [
+                offerNftMintPubKey.toBuffer(),
+                Buffer.from(NFT_PDA_SEED)
+            ], program.programId);
+
+        const createOffer = async (offerId: number) => {
+            const [offer, offerBump] = await anchor.web3.PublicKey.findProgramAddress(
+                [
+                    Buffer.from(new anchor.BN(offerId).toString()),
+                    Buffer.from(OFFER_PDA_SEED),
+                ], program.programId);
+            await program.rpc.createOffer(stableBump, offerBump, REQUEST_AMOUNT, INTEREST_RATE, PERIOD, ADDITIONAL_COLLATERAL, {
+                accounts: {
+                    config: config,
+                    market: market,
+                    stableCoinMint: stableCoinMintPubKey,
+                    stableCoinVault: stable,
+                    lenderStableCoinVault: bobStableCoinWallet,
+                    nftMint: offerNftMintPubKey,
+                    offer: offer,
+                    lender: bob.publicKey,
+                    systemProgram: anchor.web3.SystemProgram.programId,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                    rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+                },
+                signers: [bob],
+            });
+            return offer;
+        };
+
+        const bobBalance = await utils.getTokenBalance(provider, bobStableCoinWallet);
+        const aliceBalance = await utils.getTokenBalance(provider, aliceStableCoinWallet);
+
+        // Offer ID: 0 is canceled by the lender
+        let offer = await createOffer(0);
+        assert.strictEqual(await utils.getTokenBalance(provider, bobStableCoinWallet), bobBalance - REQUEST_AMOUNT.toNumber());
+        await program.rpc.cancelOffer(new anchor.BN(0), stableBump, {
+            accounts: {
+                config: config,
+                market: market,
+                offer: offer,
+                stableCoinMint: stableCoinMintPubKey,
+                stableCoinVault: stable,
+                lenderStableCoinVault: bobStableCoinWallet,
+                lender: bob.publicKey,
+                systemProgram: anchor.web3.SystemProgram.programId,
+                tokenProgram: TOKEN_PROGRAM_ID,
+            },
+            signers: [bob],
+        });
+        assert.strictEqual(await utils.getTokenBalance(provider, bobStableCoinWallet), bobBalance);
+
+        // Offer ID: 1 is accepted by the nft holder
+        offer = await createOffer(1);
+        const orderId = (await program.account.configuration.fetch(config)).orderId;
+        const [order, orderBump] = await anchor.web3.PublicKey.findProgramAddress(
+            [
+                Buffer.from(orderId.toString()),
+                Buffer.from(ORDER_PDA_SEED),
+            ], program.programId);
+        await program.rpc.acceptOffer(new anchor.BN(1), stableBump, nftBump, orderBump, {
+            accounts: {
+                config: config,
+                market: market,
+                offer: offer,
+                stableCoinMint: stableCoinMintPubKey,
+                stableCoinVault: stable,
+                treasuryVault: await utils.getTreasuryAddress(program.programId, stableCoinMintPubKey),
+                borrowerStableCoinVault: aliceStableCoinWallet,
+                nftMint: offerNftMintPubKey,
+                nftMetadata: await utils.getMetadataAddress(offerNftMintPubKey),
+                collectionWhitelist: await utils.getWhitelistAddress(program.programId, anchor.web3.PublicKey.default, market),
+                nftVault: nft,
+                borrowerNftVault: aliceOfferNftWallet,
+                order: order,
+                lender: bob.publicKey,
+                borrower: alice.publicKey,
+                priceFeed: anchor.web3.PublicKey.default,
+                systemProgram: anchor.web3.SystemProgram.programId,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+            },
+            signers: [alice],
+        });
+
+        assert.strictEqual(await utils.getTokenBalance(provider, aliceStableCoinWallet), aliceBalance + REQUEST_AMOUNT.toNumber() - ORIGINATION_FEE - ADDITIONAL_COLLATERAL.toNumber());
+        assert.strictEqual(await utils.getTokenBalance(provider, aliceOfferNftWallet), 0);
+        assert.strictEqual(await utils.getTokenBalance(provider, nft), 1);
+        const fetch = await program.account.order.fetch(order);
+        assert.strictEqual(fetch.lender.toString(), bob.publicKey.toString());
+        assert.deepStrictEqual(fetch.state, { active: {} });
+    });
+
+    it("Withdraw Treasury", async () => {
+        const [config, configBump] = await anchor.web3.PublicKey.findProgramAddress(
+            [
+                Buffer.from(CONFIG_PDA_SEED)
+            ], program.programId);
+        const [market, marketBump] = await anchor.web3.PublicKey.findProgramAddress(
+            [
+                stableCoinMintPubKey.toBuffer(),
+                Buffer.from(MARKET_PDA_SEED)
+            ], program.programId);
+        const [treasury, treasuryBump] = await anchor.web3.PublicKey.findProgramAddress(
+            [
+                stableCoinMintPubKey.toBuffer(),
+                Buffer.from(TREASURY_PDA_SEED)
+            ], program.programId);
+        const adminStableCoinWallet = await stableCoinMintObject.createAccount(provider.wallet.publicKey);
+
+        // Origination fees of the loan and the offer, protocol fee of the repayment
+        const collected = isTestPayBack ? 2 * ORIGINATION_FEE + PROTOCOL_FEE : 2 * ORIGINATION_FEE;
+        assert.strictEqual(await utils.getTokenBalance(provider, treasury), collected);
+
+        // Only the admin can withdraw the fees
+        await assert.rejects(program.rpc.withdrawTreasury(treasuryBump, new anchor.BN(collected), {
+            accounts: {
+                config: config,
+                market: market,
+                stableCoinMint: stableCoinMintPubKey,
+                treasuryVault: treasury,
+                adminStableCoinVault: aliceStableCoinWallet,
+                admin: alice.publicKey,
+                tokenProgram: TOKEN_PROGRAM_ID,
+            },
+            signers: [alice],
+        }));
+
+        await program.rpc.withdrawTreasury(treasuryBump, new anchor.BN(collected), {
+            accounts: {
+                config: config,
+                market: market,
+                stableCoinMint: stableCoinMintPubKey,
+                treasuryVault: treasury,
+                adminStableCoinVault: adminStableCoinWallet,
+                admin: provider.wallet.publicKey,
+                tokenProgram: TOKEN_PROGRAM_ID,
+            },
+        });
+        assert.strictEqual(await utils.getTokenBalance(provider, treasury), 0);
+        assert.strictEqual(await utils.getTokenBalance(provider, adminStableCoinWallet), collected);
+    });
+
+    it("Partial Repayment", async () => {
+        const [config, configBump] = await anchor.web3.PublicKey.findProgramAddress(
+            [
+                Buffer.from(CONFIG_PDA_SEED)
+            ], program.programId);
+        const [market, marketBump] = await anchor.web3.PublicKey.findProgramAddress(
+            [
+                stableCoinMintPubKey.toBuffer(),
+                Buffer.from(MARKET_PDA_SEED)
+            ], program.programId);
+        const [stable, stableBump] = await anchor.web3.PublicKey.findProgramAddress(
+            [
+                stableCoinMintPubKey.toBuffer(),
+                Buffer.from(STABLE_COIN_PDA_SEED)
+            ], program.programId);
+        const treasury = await utils.getTreasuryAddress(program.programId, stableCoinMintPubKey);
+
+        // New nft for alice
+        let mintKeyNft = anchor.web3.Keypair.generate();
+        const partialNftMintObject = await utils.createMint(mintKeyNft, provider, provider.wallet.publicKey, null, 0, TOKEN_PROGRAM_ID);
+        const partialNftMintPubKey = partialNftMintObject.publicKey;
+        const alicePartialNftWallet = await partialNftMintObject.createAssociatedTokenAccount(alice.publicKey);
+        await utils.mintToAccount(provider, partialNftMintPubKey, alicePartialNftWallet, 1);
+
+        const [nft, nftBump] = await anchor.web3.PublicKey.findProgramAddress(
+            [
+                partialNftMintPubKey.toBuffer(),
+                Buffer.from(NFT_PDA_SEED)
+            ], program.programId);
+        const orderId = (await program.account.configuration.fetch(config)).orderId;
+        const [order, orderBump] = await anchor.web3.PublicKey.findProgramAddress(
+            [
+                Buffer.from(orderId.toString()),
+                Buffer.from(ORDER_PDA_SEED),
+            ], program.programId);
+
+        await program.rpc.createOrder(stableBump, nftBump, orderBump, REQUEST_AMOUNT, INTEREST_RATE, PERIOD, ADDITIONAL_COLLATERAL, {
+            accounts: {
+                config: config,
+                market: market,
+                stableCoinMint: stableCoinMintPubKey,
+                stableCoinVault: stable,
+                userStableCoinVault: aliceStableCoinWallet,
+                nftMint: partialNftMintPubKey,
+                nftMetadata: await utils.getMetadataAddress(partialNftMintPubKey),
+                collectionWhitelist: await utils.getWhitelistAddress(program.programId, anchor.web3.PublicKey.default, market),
+                nftVault: nft,
+                userNftVault: alicePartialNftWallet,
+                order: order,
+                borrower: alice.publicKey,
+                systemProgram: anchor.web3.SystemProgram.programId,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                rent: anchor.web3.SYSVAR_RENT_PUBKEY
+            },
+            signers: [alice]
+        });
+
+        // Half of the loan is due every half period
+        const installmentAmount = REQUEST_AMOUNT.divn(2);
+        await program.rpc.setInstallmentSchedule(orderId, installmentAmount, PERIOD.divn(2), {
+            accounts: {
+                order: order,
+                borrower: alice.publicKey,
+            },
+            signers: [alice]
+        });
+
+        // The schedule is part of the terms the lender agrees to
+        assert.strictEqual((await program.account.order.fetch(order)).termsVersion.toString(), "1");
+        await program.rpc.giveLoan(orderId, stableBump, new anchor.BN(1), REQUEST_AMOUNT, INTEREST_RATE, PERIOD, partialNftMintPubKey, {
+            accounts: {
+                config: config,
+                market: market,
+                order: order,
+                stableCoinMint: stableCoinMintPubKey,
+                stableCoinVault: stable,
+                treasuryVault: treasury,
+                lenderStableCoinVault: bobStableCoinWallet,
+                borrowerStableCoinVault: aliceStableCoinWallet,
+                lender: bob.publicKey,
+                collectionWhitelist: await utils.getWhitelistAddress(program.programId, anchor.web3.PublicKey.default, market),
+                priceFeed: anchor.web3.PublicKey.default,
+                systemProgram: anchor.web3.SystemProgram.programId,
+                tokenProgram: TOKEN_PROGRAM_ID,
+            },
+            signers: [bob],
+        });
+
+        const repayPartial = (amount: anchor.BN) => program.rpc.repayPartial(orderId, stableBump, amount, {
+            accounts: {
+                config: config,
+                market: market,
+                order: order,
+                stableCoinMint: stableCoinMintPubKey,
+                stableCoinVault: stable,
+                treasuryVault: treasury,
+                lenderStableCoinVault: bobStableCoinWallet,
+                borrowerStableCoinVault: aliceStableCoinWallet,
+                borrower: alice.publicKey,
+                tokenProgram: TOKEN_PROGRAM_ID,
+            },
+            signers: [alice]
+        });
+
+        // Clearing the whole balance must go through payback, which releases the nft
+        await assert.rejects(repayPartial(REQUEST_AMOUNT.muln(2)));
+
+        await repayPartial(installmentAmount);
+        const fetch = await program.account.order.fetch(order);
+        assert.strictEqual(fetch.repaymentCount.toString(), "1");
+        assert.strictEqual(fetch.amountRepaid.toString(), installmentAmount.toString());
+        assert.strictEqual(fetch.principalRepaid.add(fetch.repayments[0].interest).toString(), installmentAmount.toString());
+        assert.strictEqual(await utils.getTokenBalance(provider, nft), 1);
+    });
+
+    it("Loan Extension", async () => {
+        const [config, configBump] = await anchor.web3.PublicKey.findProgramAddress(
+            [
+                Buffer.from(CONFIG_PDA_SEED)
+            ], program.programId);
+        const [market, marketBump] = await anchor.web3.PublicKey.findProgramAddress(
+            [
+                stableCoinMintPubKey.toBuffer(),
+                Buffer.from(MARKET_PDA_SEED)
+            ], program.programId);
+        // Extend the loan left open by the partial repayment test
+        const orderId = (await program.account.configuration.fetch(config)).orderId.subn(1);
+        const [order, orderBump] = await anchor.web3.PublicKey.findProgramAddress(
+            [
+                Buffer.from(orderId.toString()),
+                Buffer.from(ORDER_PDA_SEED),
+            ], program.programId);
+        const newPeriod = PERIOD.muln(2);
+        const extraInterest = new anchor.BN(100_000);
+
+        await program.rpc.proposeExtension(orderId, newPeriod, extraInterest, {
+            accounts: {
+                config: config,
+                order: order,
+                borrower: alice.publicKey,
+            },
+            signers: [alice]
+        });
+
+        const extendLoan = () => program.rpc.extendLoan(orderId, {
+            accounts: {
+                config: config,
+                market: market,
+                order: order,
+                stableCoinMint: stableCoinMintPubKey,
+                treasuryVault: await utils.getTreasuryAddress(program.programId, stableCoinMintPubKey),
+                lenderStableCoinVault: bobStableCoinWallet,
+                borrowerStableCoinVault: aliceStableCoinWallet,
+                borrower: alice.publicKey,
+                tokenProgram: TOKEN_PROGRAM_ID,
+            },
+            signers: [alice]
+        });
+
+        // The lender has not agreed yet
+        await assert.rejects(extendLoan());
+
+        // The lender can't approve periods beyond the protocol bound
+        try {
+            await program.rpc.approveExtension(orderId, MAX_PERIOD.addn(1), extraInterest, {
+                accounts: {
+                    config: config,
+                    order: order,
+                    lender: bob.publicKey,
+                },
+                signers: [bob]
+            });
+            assert.fail("extension beyond the maximum period approved");
+        } catch (err) {
+            assert.strictEqual(err.msg, "Loan period is out of the allowed range");
+        }
+
+        await program.rpc.approveExtension(orderId, newPeriod, extraInterest, {
+            accounts: {
+                config: config,
+                order: order,
+                lender: bob.publicKey,
+            },
+            signers: [bob]
+        });
+        const bobBalance = await utils.getTokenBalance(provider, bobStableCoinWallet);
+        await extendLoan();
+
+        const fetch = await program.account.order.fetch(order);
+        assert.strictEqual(fetch.period.toString(), newPeriod.toString());
+        assert.strictEqual(fetch.extensionCount.toString(), "1");
+        // lender receives the accrued and the extra interest, minus the protocol fee
+        const extraFee = extraInterest.mul(PROTOCOL_FEE_RATE).divn(10_000);
+        assert.ok(await utils.getTokenBalance(provider, bobStableCoinWallet) >= bobBalance + extraInterest.sub(extraFee).toNumber());
+    });
+
+    it("Refinance", async () => {
+        const [config, configBump] = await anchor.web3.PublicKey.findProgramAddress(
+            [
+                Buffer.from(CONFIG_PDA_SEED)
+            ], program.programId);
+        const [market, marketBump] = await anchor.web3.PublicKey.findProgramAddress(
+            [
+                stableCoinMintPubKey.toBuffer(),
+                Buffer.from(MARKET_PDA_SEED)
+            ], program.programId);
+        // Refinance the loan left open by the partial repayment test
+        const orderId = (await program.account.configuration.fetch(config)).orderId.subn(1);
+        const [order, orderBump] = await anchor.web3.PublicKey.findProgramAddress(
+            [
+                Buffer.from(orderId.toString()),
+                Buffer.from(ORDER_PDA_SEED),
+            ], program.programId);
+
+        // The provider wallet takes over the loan from bob at a lower rate
+        const newLenderStableCoinWallet = await stableCoinMintObject.createAccount(provider.wallet.publicKey);
+        await utils.mintToAccount(provider, stableCoinMintPubKey, newLenderStableCoinWallet, 1000_000_000);
+        const newInterestRate = INTEREST_RATE.divn(2);
+        const bobBalance = await utils.getTokenBalance(provider, bobStableCoinWallet);
+        const before = await program.account.order.fetch(order);
+
+        await program.rpc.refinance(orderId, newInterestRate, PERIOD, {
+            accounts: {
+                config: config,
+                market: market,
+                order: order,
+                collectionWhitelist: await utils.getWhitelistAddress(program.programId, before.collection, market),
+                stableCoinMint: stableCoinMintPubKey,
+                treasuryVault: await utils.getTreasuryAddress(program.programId, stableCoinMintPubKey),
+                lenderStableCoinVault: bobStableCoinWallet,
+                newLenderStableCoinVault: newLenderStableCoinWallet,
+                newLender: provider.wallet.publicKey,
+                borrower: alice.publicKey,
+                priceFeed: anchor.web3.PublicKey.default,
+                tokenProgram: TOKEN_PROGRAM_ID,
+            },
+            signers: [alice]
+        });
+
+        const fetch = await program.account.order.fetch(order);
+        const outstanding = before.requestAmount.sub(before.principalRepaid);
+        assert.strictEqual(fetch.lender.toString(), provider.wallet.publicKey.toString());
+        assert.strictEqual(fetch.interestRate.toString(), newInterestRate.toString());
+        assert.strictEqual(fetch.principalRepaid.toString(), "0");
+        // the repayment history belongs to the previous loan
+        assert.strictEqual(before.repaymentCount.toString(), "1");
+        assert.strictEqual(fetch.repaymentCount.toString(), "0");
+        assert.strictEqual(fetch.repayments[0].paidAt.toString(), "0");
+        // the new principal covers the old principal and the interest owed to bob
+        assert.ok(fetch.requestAmount.gte(outstanding));
+        assert.ok(await utils.getTokenBalance(provider, bobStableCoinWallet) >= bobBalance + outstanding.toNumber());
+    });
+
+    it("Update Order", async () => {
+        const [config, configBump] = await anchor.web3.PublicKey.findProgramAddress(
+            [
+                Buffer.from(CONFIG_PDA_SEED)
+            ], program.programId);
+        const [market, marketBump] = await anchor.web3.PublicKey.findProgramAddress(
+            [
+                stableCoinMintPubKey.toBuffer(),
+                Buffer.from(MARKET_PDA_SEED)
+            ], program.programId);
+        const [stable, stableBump] = await anchor.web3.PublicKey.findProgramAddress(
+            [
+                stableCoinMintPubKey.toBuffer(),
+                Buffer.from(STABLE_COIN_PDA_SEED)
+            ], program.programId);
+
+        let mintKeyNft = anchor.web3.Keypair.generate();
+        const updateNftMintObject = await utils.createMint(mintKeyNft, provider, provider.wallet.publicKey, null, 0, TOKEN_PROGRAM_ID);
+        const updateNftMintPubKey = updateNftMintObject.publicKey;
+        const aliceUpdateNftWallet = await updateNftMintObject.createAssociatedTokenAccount(alice.publicKey);
+        await utils.mintToAccount(provider, updateNftMintPubKey, aliceUpdateNftWallet, 1);
+
+        const [nft, nftBump] = await anchor.web3.PublicKey.findProgramAddress(
+            [
+                updateNftMintPubKey.toBuffer(),
+                Buffer.from(NFT_PDA_SEED)
+            ], program.programId);
+        const orderId = (await program.account.configuration.fetch(config)).orderId;
+        const [order, orderBump] = await anchor.web3.PublicKey.findProgramAddress(
+            [
+                Buffer.from(orderId.toString()),
+                Buffer.from(ORDER_PDA_SEED),
+            ], program.programId);
+        const collectionWhitelist = await utils.getWhitelistAddress(program.programId, anchor.web3.PublicKey.default, market);
+
+        await program.rpc.createOrder(stableBump, nftBump, orderBump, REQUEST_AMOUNT, INTEREST_RATE, PERIOD, ADDITIONAL_COLLATERAL, {
+            accounts: {
+                config: config,
+                market: market,
+                stableCoinMint: stableCoinMintPubKey,
+                stableCoinVault: stable,
+                userStableCoinVault: aliceStableCoinWallet,
+                nftMint: updateNftMintPubKey,
+                nftMetadata: await utils.getMetadataAddress(updateNftMintPubKey),
+                collectionWhitelist: collectionWhitelist,
+                nftVault: nft,
+                userNftVault: aliceUpdateNftWallet,
+                order: order,
+                borrower: alice.publicKey,
+                systemProgram: anchor.web3.SystemProgram.programId,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                rent: anchor.web3.SYSVAR_RENT_PUBKEY
+            },
+            signers: [alice]
+        });
+
+        // Alice doubles the additional collateral
+        const aliceBalance = await utils.getTokenBalance(provider, aliceStableCoinWallet);
+        await program.rpc.updateOrder(orderId, stableBump, REQUEST_AMOUNT, INTEREST_RATE, PERIOD, ADDITIONAL_COLLATERAL.muln(2), {
+            accounts: {
+                config: config,
+                market: market,
+                order: order,
+                stableCoinMint: stableCoinMintPubKey,
+                stableCoinVault: stable,
+                userStableCoinVault: aliceStableCoinWallet,
+                collectionWhitelist: collectionWhitelist,
+                borrower: alice.publicKey,
+                tokenProgram: TOKEN_PROGRAM_ID,
+            },
+            signers: [alice]
+        });
+
+        const fetch = await program.account.order.fetch(order);
+        assert.strictEqual(fetch.termsVersion.toString(), "1");
+        assert.strictEqual(fetch.additionalCollateral.toString(), ADDITIONAL_COLLATERAL.muln(2).toString());
+        assert.strictEqual(await utils.getTokenBalance(provider, aliceStableCoinWallet), aliceBalance - ADDITIONAL_COLLATERAL.toNumber());
+
+        // A lender that read the original terms is rejected
+        try {
+            await program.rpc.giveLoan(orderId, stableBump, new anchor.BN(0), REQUEST_AMOUNT, INTEREST_RATE, PERIOD, updateNftMintPubKey, {
+                accounts: {
+                    config: config,
+                    market: market,
+                    order: order,
+                    stableCoinMint: stableCoinMintPubKey,
+                    stableCoinVault: stable,
+                    treasuryVault: await utils.getTreasuryAddress(program.programId, stableCoinMintPubKey),
+                    lenderStableCoinVault: bobStableCoinWallet,
+                    borrowerStableCoinVault: aliceStableCoinWallet,
+                    lender: bob.publicKey,
+                    collectionWhitelist: await utils.getWhitelistAddress(program.programId, anchor.web3.PublicKey.default, market),
+                    priceFeed: anchor.web3.PublicKey.default,
+                    systemProgram: anchor.web3.SystemProgram.programId,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                },
+                signers: [bob],
+            });
+            assert.fail("stale terms version accepted");
+        } catch (err) {
+            assert.strictEqual(err.msg, "Order terms changed since they were read");
+        }
+
+        // So is a lender expecting a different period
+        try {
+            await program.rpc.giveLoan(orderId, stableBump, new anchor.BN(1), REQUEST_AMOUNT, INTEREST_RATE, PERIOD.muln(2), updateNftMintPubKey, {
+                accounts: {
+                    config: config,
+                    market: market,
+                    order: order,
+                    stableCoinMint: stableCoinMintPubKey,
+                    stableCoinVault: stable,
+                    treasuryVault: await utils.getTreasuryAddress(program.programId, stableCoinMintPubKey),
+                    lenderStableCoinVault: bobStableCoinWallet,
+                    borrowerStableCoinVault: aliceStableCoinWallet,
+                    lender: bob.publicKey,
+                    collectionWhitelist: await utils.getWhitelistAddress(program.programId, anchor.web3.PublicKey.default, market),
+                    priceFeed: anchor.web3.PublicKey.default,
+                    systemProgram: anchor.web3.SystemProgram.programId,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                },
+                signers: [bob],
+            });
+            assert.fail("unexpected terms accepted");
+        } catch (err) {
+            assert.strictEqual(err.msg, "Order terms do not match the expected terms");
+        }
+    });
+
+    it("Auction", async () => {
+        const [config, configBump] = await anchor.web3.PublicKey.findProgramAddress(
+            [
+                Buffer.from(CONFIG_PDA_SEED)
+            ], program.programId);
+        const [market, marketBump] = await anchor.web3.PublicKey.findProgramAddress(
+            [
+                stableCoinMintPubKey.toBuffer(),
+                Buffer.from(MARKET_PDA_SEED)
+            ], program.programId);
+        // The refinanced loan is still running
+        const orderId = (await program.account.configuration.fetch(config)).orderId.subn(2);
+        const [order, orderBump] = await anchor.web3.PublicKey.findProgramAddress(
+            [
+                Buffer.from(orderId.toString()),
+                Buffer.from(ORDER_PDA_SEED),
+            ], program.programId);
+
+        await assert.rejects(program.rpc.startAuction(orderId, {
+            accounts: {
+                config: config,
+                order: order,
+                lender: provider.wallet.publicKey,
+            },
+        }));
+        await assert.rejects(program.rpc.startEnglishAuction(orderId, {
+            accounts: {
+                config: config,
+                market: market,
+                order: order,
+                stableCoinMint: stableCoinMintPubKey,
+                bidEscrow: (await anchor.web3.PublicKey.findProgramAddress(
+                    [
+                        order.toBuffer(),
+                        Buffer.from(BID_ESCROW_PDA_SEED),
+                    ], program.programId))[0],
+                lender: provider.wallet.publicKey,
+                systemProgram: anchor.web3.SystemProgram.programId,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                rent: anchor.web3.SYSVAR_RENT_PUBKEY
+            },
+        }));
+        const fetch = await program.account.order.fetch(order);
+        assert.deepStrictEqual(fetch.state, { active: {} });
+    });
+
+    it("Redemption Window", async () => {
+        const [config, configBump] = await anchor.web3.PublicKey.findProgramAddress(
+            [
+                Buffer.from(CONFIG_PDA_SEED)
+            ], program.programId);
+        // The order left open by the update order test
+        const orderId = (await program.account.configuration.fetch(config)).orderId.subn(1);
+        const [order, orderBump] = await anchor.web3.PublicKey.findProgramAddress(
+            [
+                Buffer.from(orderId.toString()),
+                Buffer.from(ORDER_PDA_SEED),
+            ], program.programId);
+        const before = await program.account.order.fetch(order);
+
+        // A penalty without a window is rejected
+        await assert.rejects(program.rpc.setRedemptionWindow(orderId, new anchor.BN(0), new anchor.BN(500), {
+            accounts: {
+                order: order,
+                borrower: alice.publicKey,
+            },
+            signers: [alice]
+        }));
+
+        await program.rpc.setRedemptionWindow(orderId, new anchor.BN(60 * 60 * 48), new anchor.BN(500), {
+            accounts: {
+                order: order,
+                borrower: alice.publicKey,
+            },
+            signers: [alice]
+        });
+
+        const fetch = await program.account.order.fetch(order);
+        assert.strictEqual(fetch.redemptionPeriod.toString(), (60 * 60 * 48).toString());
+        assert.strictEqual(fetch.redemptionPenaltyRate.toString(), "500");
+        // lenders have to sign for the new terms
+        assert.strictEqual(fetch.termsVersion.toString(), before.termsVersion.addn(1).toString());
+    });
+
+    it("Oracle", async () => {
+        const [config, configBump] = await anchor.web3.PublicKey.findProgramAddress(
+            [
+                Buffer.from(CONFIG_PDA_SEED)
+            ], program.programId);
+        const [market, marketBump] = await anchor.web3.PublicKey.findProgramAddress(
+            [
+                stableCoinMintPubKey.toBuffer(),
+                Buffer.from(MARKET_PDA_SEED)
+            ], program.programId);
+        const [stable, stableBump] = await anchor.web3.PublicKey.findProgramAddress(
+            [
+                stableCoinMintPubKey.toBuffer(),
+                Buffer.from(STABLE_COIN_PDA_SEED)
+            ], program.programId);
+
+        await setConfig({oracleProgram: mockOracle.programId});
+
+        // Nfts without a verified collection have no floor price
+        const unverified = await createLoanOrder(PERIOD);
+        try {
+            await giveLoan(unverified);
+            assert.fail("loan against an nft without a collection valued");
+        } catch (err) {
+            assert.strictEqual(err.msg, "Nft does not belong to a verified collection");
+        }
+        await program.rpc.cancelOrder(unverified.orderId, stableBump, unverified.nftBump, {
+            accounts: {
+                config: config,
+                market: market,
+                order: unverified.order,
+                stableCoinMint: stableCoinMintPubKey,
+                stableCoinVault: stable,
+                userStableCoinVault: aliceStableCoinWallet,
+                nftMint: unverified.nftMint,
+                nftVault: unverified.nft,
+                userNftVault: unverified.aliceNft,
+                receipt: await utils.getReceiptAddress(program.programId, unverified.order),
+                borrower: alice.publicKey,
+                systemProgram: anchor.web3.SystemProgram.programId,
+                tokenProgram: TOKEN_PROGRAM_ID,
+            },
+            signers: [alice]
+        });
+
+        // Floor price of the collection of alice's nft
+        [oracleCollection] = await utils.createMetadataNFT(provider, provider.wallet.publicKey);
+        priceFeed = await utils.createPriceFeed(provider, mockOracle.programId);
+        const [oracleNftMintPubKey, aliceOracleNftWallet] = await utils.createMetadataNFT(provider, alice.publicKey, oracleCollection);
+
+        const [nft, nftBump] = await anchor.web3.PublicKey.findProgramAddress(
+            [
+                oracleNftMintPubKey.toBuffer(),
+                Buffer.from(NFT_PDA_SEED)
+            ], program.programId);
+        const orderId = (await program.account.configuration.fetch(config)).orderId;
+        const [order, orderBump] = await anchor.web3.PublicKey.findProgramAddress(
+            [
+                Buffer.from(orderId.toString()),
+                Buffer.from(ORDER_PDA_SEED),
+            ], program.programId);
+
+        await program.rpc.createOrder(stableBump, nftBump, orderBump, REQUEST_AMOUNT, INTEREST_RATE, PERIOD, ADDITIONAL_COLLATERAL, {
+            accounts: {
+                config: config,
+                market: market,
+                stableCoinMint: stableCoinMintPubKey,
+                stableCoinVault: stable,
+                userStableCoinVault: aliceStableCoinWallet,
+                nftMint: oracleNftMintPubKey,
+                nftMetadata: await utils.getMetadataAddress(oracleNftMintPubKey),
+                collectionWhitelist: await utils.getWhitelistAddress(program.programId, oracleCollection, market),
+                nftVault: nft,
+                userNftVault: aliceOracleNftWallet,
+                order: order,
+                borrower: alice.publicKey,
+                systemProgram: anchor.web3.SystemProgram.programId,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                rent: anchor.web3.SYSVAR_RENT_PUBKEY
+            },
+            signers: [alice]
+        });
+
+        const treasury = await utils.getTreasuryAddress(program.programId, stableCoinMintPubKey);
+        const giveOracleLoan = (feed: anchor.web3.PublicKey = priceFeed) => program.rpc.giveLoan(orderId, stableBump, new anchor.BN(0), REQUEST_AMOUNT, INTEREST_RATE, PERIOD, oracleNftMintPubKey, {
+            accounts: {
+                config: config,
+                market: market,
+                order: order,
+                stableCoinMint: stableCoinMintPubKey,
+                stableCoinVault: stable,
+                treasuryVault: treasury,
+                lenderStableCoinVault: bobStableCoinWallet,
+                borrowerStableCoinVault: aliceStableCoinWallet,
+                lender: bob.publicKey,
+                collectionWhitelist: await utils.getWhitelistAddress(program.programId, oracleCollection, market),
+                priceFeed: feed,
+                systemProgram: anchor.web3.SystemProgram.programId,
+                tokenProgram: TOKEN_PROGRAM_ID,
+            },
+            signers: [bob],
+        });
+
+        // Feeds are only read once the admin registered them for the collection
+        await setFloorPrice(priceFeed, oracleCollection, 100, 0, 2);
+        try {
+            await giveOracleLoan();
+            assert.fail("loan valued by an unregistered feed");
+        } catch (err) {
+            assert.strictEqual(err.msg, "Collection has no registered price feed");
+        }
+        const [solMarket, solMarketBump] = await anchor.web3.PublicKey.findProgramAddress(
+            [
+                NATIVE_MINT.toBuffer(),
+                Buffer.from(MARKET_PDA_SEED)
+            ], program.programId);
+        const registerPriceFeed = async (registryMarket: anchor.web3.PublicKey, maxRequestAmount: anchor.BN) => {
+            const [whitelist, whitelistBump] = await anchor.web3.PublicKey.findProgramAddress(
+                [
+                    oracleCollection.toBuffer(),
+                    registryMarket.toBuffer(),
+                    Buffer.from(WHITELIST_PDA_SEED),
+                ], program.programId);
+            await program.rpc.addCollection(whitelistBump, oracleCollection, maxRequestAmount, MAX_PERIOD, priceFeed, {
+                accounts: {
+                    config: config,
+                    market: registryMarket,
+                    collectionWhitelist: whitelist,
+                    admin: provider.wallet.publicKey,
+                    systemProgram: anchor.web3.SystemProgram.programId,
+                    rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+                },
+            });
+        };
+        await registerPriceFeed(market, REQUEST_AMOUNT);
+        await registerPriceFeed(solMarket, new anchor.BN(1_000_000_000));
+
+        // Anyone can publish a feed for the collection under the oracle program, only the registered one is read
+        const forgedFeed = await utils.createPriceFeed(provider, mockOracle.programId);
+        await mockOracle.rpc.setPrice(oracleCollection, new anchor.BN(1_000), new anchor.BN(0), 0, new anchor.BN(0), {
+            accounts: {
+                feed: forgedFeed,
+                authority: bob.publicKey,
+            },
+            signers: [bob],
+        });
+        try {
+            await giveOracleLoan(forgedFeed);
+            assert.fail("loan valued by a forged feed");
+        } catch (err) {
+            assert.strictEqual(err.msg, "Invalid price feed");
+        }
+
+        // The registered feed only takes prices from its authority
+        try {
+            await mockOracle.rpc.setPrice(oracleCollection, new anchor.BN(1_000), new anchor.BN(0), 0, new anchor.BN(0), {
+                accounts: {
+                    feed: priceFeed,
+                    authority: bob.publicKey,
+                },
+                signers: [bob],
+            });
+            assert.fail("feed updated by another authority");
+        } catch (err) {
+            assert.strictEqual(err.msg, "Feed belongs to another authority");
+        }
+
+        // 80 tokens against a 10 token floor and 8 tokens of additional collateral
+        await setFloorPrice(priceFeed, oracleCollection, 10);
+        try {
+            await giveOracleLoan();
+            assert.fail("loan above the maximum loan-to-value");
+        } catch (err) {
+            assert.strictEqual(err.msg, "Loan to value is above the maximum");
+        }
+
+        // A 100 token floor known within 95 tokens is valued at 5 tokens
+        await setFloorPrice(priceFeed, oracleCollection, 100, 0, 95);
+        try {
+            await giveOracleLoan();
+            assert.fail("loan valued above the confidence interval");
+        } catch (err) {
+            assert.strictEqual(err.msg, "Loan to value is above the maximum");
+        }
+
+        // 80 tokens against a 98 token floor and 8 tokens of additional collateral
+        await setFloorPrice(priceFeed, oracleCollection, 100, 0, 2);
+        await giveOracleLoan();
+
+        const fetch = await program.account.order.fetch(order);
+        assert.deepStrictEqual(fetch.state, { active: {} });
+        assert.strictEqual(fetch.collection.toString(), oracleCollection.toString());
+        assert.strictEqual(fetch.floorPrice.toString(), "98000000");
+        assert.strictEqual(fetch.priceFeed.toString(), priceFeed.toString());
+    });
+
+    it("LTV Liquidation", async () => {
+        const [config, configBump] = await anchor.web3.PublicKey.findProgramAddress(
+            [
+                Buffer.from(CONFIG_PDA_SEED)
+            ], program.programId);
+        const [market, marketBump] = await anchor.web3.PublicKey.findProgramAddress(
+            [
+                stableCoinMintPubKey.toBuffer(),
+                Buffer.from(MARKET_PDA_SEED)
+            ], program.programId);
+        const [stable, stableBump] = await anchor.web3.PublicKey.findProgramAddress(
+            [
+                stableCoinMintPubKey.toBuffer(),
+                Buffer.from(STABLE_COIN_PDA_SEED)
+            ], program.programId);
+        // The loan valued by the oracle test
+        const orderId = (await program.account.configuration.fetch(config)).orderId.subn(1);
+        const [order, orderBump] = await anchor.web3.PublicKey.findProgramAddress(
+            [
+                Buffer.from(orderId.toString()),
+                Buffer.from(ORDER_PDA_SEED),
+            ], program.programId);
+        const fetchOrder = await program.account.order.fetch(order);
+        assert.strictEqual(fetchOrder.maintenanceLtv.toString(), MAINTENANCE_LTV.toString());
+        const nftMint = new Token(provider.connection, fetchOrder.nftMint, TOKEN_PROGRAM_ID, provider.wallet.payer);
+        const bobOracleNftWallet = await nftMint.createAssociatedTokenAccount(bob.publicKey);
+        const [nft, nftBump] = await anchor.web3.PublicKey.findProgramAddress(
+            [
+                fetchOrder.nftMint.toBuffer(),
+                Buffer.from(NFT_PDA_SEED)
+            ], program.programId);
+
+        const receipt = await utils.getReceiptAddress(program.programId, order);
+        const liquidate = (feed: anchor.web3.PublicKey = priceFeed) => program.rpc.liquidate(orderId, stableBump, nftBump, {
+            accounts: {
+                config: config,
+                market: market,
+                order: order,
+                stableCoinMint: stableCoinMintPubKey,
+                stableCoinVault: stable,
+                lenderStableCoinVault: bobStableCoinWallet,
+                nftMint: fetchOrder.nftMint,
+                nftVault: nft,
+                lenderNftVault: bobOracleNftWallet,
+                receipt: receipt,
+                lender: bob.publicKey,
+                borrower: alice.publicKey,
+                priceFeed: feed,
+                systemProgram: anchor.web3.SystemProgram.programId,
+                tokenProgram: TOKEN_PROGRAM_ID,
+            },
+            signers: [bob]
+        });
+
+        // Healthy loan: 80 tokens against 106 tokens of collateral
+        try {
+            await liquidate();
+            assert.fail("healthy loan liquidated");
+        } catch (err) {
+            assert.strictEqual(err.msg, "Loan to value is below the maintenance threshold");
+        }
+
+        // A feed for the same collection under the oracle program is not the one the loan was valued with
+        const forgedFeed = await utils.createPriceFeed(provider, mockOracle.programId);
+        await mockOracle.rpc.setPrice(oracleCollection, new anchor.BN(1), new anchor.BN(0), 0, new anchor.BN(0), {
+            accounts: {
+                feed: forgedFeed,
+                authority: bob.publicKey,
+            },
+            signers: [bob],
+        });
+        try {
+            await liquidate(forgedFeed);
+            assert.fail("liquidated on a forged feed");
+        } catch (err) {
+            assert.strictEqual(err.msg, "Invalid price feed");
+        }
+
+        // Stale price
+        await setFloorPrice(priceFeed, oracleCollection, 10, 1);
+        try {
+            await liquidate();
+            assert.fail("liquidated on a stale price");
+        } catch (err) {
+            assert.strictEqual(err.msg, "Price feed is stale");
+        }
+
+        // 88.889 tokens of collateral keep the principal alone below the threshold, the interest owed
+        // since the loan started pushes it over
+        await mockOracle.rpc.setPrice(oracleCollection, new anchor.BN(80_889_000), new anchor.BN(0), -6, new anchor.BN(0), {
+            accounts: {
+                feed: priceFeed,
+                authority: provider.wallet.publicKey,
+            },
+        });
+        assert.ok(REQUEST_AMOUNT.toNumber() * 10_000 / 88_889_000 < MAINTENANCE_LTV.toNumber());
+        await liquidate();
+
+        assert.strictEqual(await utils.getTokenBalance(provider, bobOracleNftWallet), 1);
+        const fetchReceipt = await program.account.loanReceipt.fetch(receipt);
+        assert.deepStrictEqual(fetchReceipt.state, { liquidated: {} });
+        assert.strictEqual(fetchReceipt.withdrewAt.toString(), fetchReceipt.closedAt.toString());
+        assert.strictEqual(fetchReceipt.paidBackAt.toNumber(), 0);
+
+        // Native SOL loans are liquidated on the same trigger
+        const [solMarket, solMarketBump] = await anchor.web3.PublicKey.findProgramAddress(
+            [
+                NATIVE_MINT.toBuffer(),
+                Buffer.from(MARKET_PDA_SEED)
+            ], program.programId);
+        const [solNftMintPubKey, aliceSolNftWallet] = await utils.createMetadataNFT(provider, alice.publicKey, oracleCollection);
+        const [solNft, solNftBump] = await anchor.web3.PublicKey.findProgramAddress(
+            [
+                solNftMintPubKey.toBuffer(),
+                Buffer.from(NFT_PDA_SEED)
+            ], program.programId);
+        const solOrderId = (await program.account.configuration.fetch(config)).orderId;
+        const [solOrder, solOrderBump] = await anchor.web3.PublicKey.findProgramAddress(
+            [
+                Buffer.from(solOrderId.toString()),
+                Buffer.from(ORDER_PDA_SEED),
+            ], program.programId);
+        const requestAmount = new anchor.BN(1_000_000_000);
+        const additionalCollateral = new anchor.BN(100_000_000);
+        await program.rpc.createSolOrder(solNftBump, solOrderBump, requestAmount, INTEREST_RATE, PERIOD, additionalCollateral, {
+            accounts: {
+                config: config,
+                market: solMarket,
+                nftMint: solNftMintPubKey,
+                nftMetadata: await utils.getMetadataAddress(solNftMintPubKey),
+                collectionWhitelist: await utils.getWhitelistAddress(program.programId, oracleCollection, solMarket),
+                nftVault: solNft,
+                userNftVault: aliceSolNftWallet,
+                order: solOrder,
+                borrower: alice.publicKey,
+                systemProgram: anchor.web3.SystemProgram.programId,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                rent: anchor.web3.SYSVAR_RENT_PUBKEY
+            },
+            signers: [alice]
+        });
+
+        // 1 SOL against a 2 SOL floor and 0.1 SOL of additional collateral
+        await setFloorPrice(priceFeed, oracleCollection, 2);
+        await program.rpc.giveSolLoan(solOrderId, new anchor.BN(0), requestAmount, INTEREST_RATE, PERIOD, solNftMintPubKey, {
+            accounts: {
+                config: config,
+                market: solMarket,
+                order: solOrder,
+                borrower: alice.publicKey,
+                lender: bob.publicKey,
+                collectionWhitelist: await utils.getWhitelistAddress(program.programId, oracleCollection, solMarket),
+                priceFeed: priceFeed,
+                systemProgram: anchor.web3.SystemProgram.programId,
+            },
+            signers: [bob],
+        });
+
+        // @ts-ignore
+        const bobSolNftWallet = await new Token(provider.connection, solNftMintPubKey, TOKEN_PROGRAM_ID, provider.wallet.payer).createAssociatedTokenAccount(bob.publicKey);
+        const solReceipt = await utils.getReceiptAddress(program.programId, solOrder);
+        const liquidateSol = () => program.rpc.liquidateSol(solOrderId, solNftBump, {
+            accounts: {
+                config: config,
+                market: solMarket,
+                order: solOrder,
+                nftMint: solNftMintPubKey,
+                nftVault: solNft,
+                lenderNftVault: bobSolNftWallet,
+                borrower: alice.publicKey,
+                lender: bob.publicKey,
+                receipt: solReceipt,
+                priceFeed: priceFeed,
+                systemProgram: anchor.web3.SystemProgram.programId,
+                tokenProgram: TOKEN_PROGRAM_ID,
+            },
+            signers: [bob]
+        });
+        try {
+            await liquidateSol();
+            assert.fail("healthy sol loan liquidated");
+        } catch (err) {
+            assert.strictEqual(err.msg, "Loan to value is below the maintenance threshold");
+        }
+
+        // 1 SOL against 1.1 SOL of collateral
+        await setFloorPrice(priceFeed, oracleCollection, 1);
+        const bobBalance = await provider.connection.getBalance(bob.publicKey);
+        await liquidateSol();
+        assert.strictEqual(await utils.getTokenBalance(provider, bobSolNftWallet), 1);
+        // the additional collateral goes to the lender, who also pays the receipt rent and the fee
+        assert.ok(await provider.connection.getBalance(bob.publicKey) > bobBalance);
+        const fetchSolReceipt = await program.account.loanReceipt.fetch(solReceipt);
+        assert.deepStrictEqual(fetchSolReceipt.state, { liquidated: {} });
+    });
+
+    it("Collection Whitelist", async () => {
+        const [config, configBump] = await anchor.web3.PublicKey.findProgramAddress(
+            [
+                Buffer.from(CONFIG_PDA_SEED)
+            ], program.programId);
+        const [market, marketBump] = await anchor.web3.PublicKey.findProgramAddress(
+            [
+                stableCoinMintPubKey.toBuffer(),
+                Buffer.from(MARKET_PDA_SEED)
+            ], program.programId);
+        const [stable, stableBump] = await anchor.web3.PublicKey.findProgramAddress(
+            [
+                stableCoinMintPubKey.toBuffer(),
+                Buffer.from(STABLE_COIN_PDA_SEED)
+            ], program.programId);
+        // Caps for nfts without a verified collection
+        const collection = anchor.web3.PublicKey.default;
+        const [whitelist, whitelistBump] = await anchor.web3.PublicKey.findProgramAddress(
+            [
+                collection.toBuffer(),
+                market.toBuffer(),
+                Buffer.from(WHITELIST_PDA_SEED),
+            ], program.programId);
+
+        // Zero caps are rejected
+        await assert.rejects(program.rpc.addCollection(whitelistBump, collection, new anchor.BN(0), MAX_PERIOD, anchor.web3.PublicKey.default, {
+            accounts: {
+                config: config,
+                market: market,
+                collectionWhitelist: whitelist,
+                admin: provider.wallet.publicKey,
+                systemProgram: anchor.web3.SystemProgram.programId,
+                rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+            },
+        }));
+
+        await program.rpc.addCollection(whitelistBump, collection, REQUEST_AMOUNT, MAX_PERIOD, anchor.web3.PublicKey.default, {
+            accounts: {
+                config: config,
+                market: market,
+                collectionWhitelist: whitelist,
+                admin: provider.wallet.publicKey,
+                systemProgram: anchor.web3.SystemProgram.programId,
+                rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+            },
+        });
+        let fetch = await program.account.collectionWhitelist.fetch(whitelist);
+        assert.strictEqual(fetch.collection.toString(), collection.toString());
+        assert.strictEqual(fetch.market.toString(), market.toString());
+        assert.strictEqual(fetch.maxRequestAmount.toString(), REQUEST_AMOUNT.toString());
+
+        const updateCollection = (maxRequestAmount: anchor.BN) => program.rpc.updateCollection(maxRequestAmount, MAX_PERIOD, anchor.web3.PublicKey.default, {
+            accounts: {
+                config: config,
+                collectionWhitelist: whitelist,
+                admin: provider.wallet.publicKey,
+            },
+        });
+        try {
+            await updateCollection(new anchor.BN(0));
+            assert.fail("zero cap accepted");
+        } catch (err) {
+            assert.strictEqual(err.msg, "Invalid configuration");
+        }
+        await updateCollection(REQUEST_AMOUNT.subn(1));
+        fetch = await program.account.collectionWhitelist.fetch(whitelist);
+        assert.strictEqual(fetch.maxRequestAmount.toString(), REQUEST_AMOUNT.subn(1).toString());
+
+        // New nft for alice, requesting more than the cap
+        let mintKeyNft = anchor.web3.Keypair.generate();
+        const capNftMintObject = await utils.createMint(mintKeyNft, provider, provider.wallet.publicKey, null, 0, TOKEN_PROGRAM_ID);
+        const capNftMintPubKey = capNftMintObject.publicKey;
+        const aliceCapNftWallet = await capNftMintObject.createAssociatedTokenAccount(alice.publicKey);
+        await utils.mintToAccount(provider, capNftMintPubKey, aliceCapNftWallet, 1);
+
+        const [nft, nftBump] = await anchor.web3.PublicKey.findProgramAddress(
+            [
+                capNftMintPubKey.toBuffer(),
+                Buffer.from(NFT_PDA_SEED)
+            ], program.programId);
+        const orderId = (await program.account.configuration.fetch(config)).orderId;
+        const [order, orderBump] = await anchor.web3.PublicKey.findProgramAddress(
+            [
+                Buffer.from(orderId.toString()),
+                Buffer.from(ORDER_PDA_SEED),
+            ], program.programId);
+        try {
+            await program.rpc.createOrder(stableBump, nftBump, orderBump, REQUEST_AMOUNT, INTEREST_RATE, PERIOD, ADDITIONAL_COLLATERAL, {
+                accounts: {
+                    config: config,
+                    market: market,
+                    stableCoinMint: stableCoinMintPubKey,
+                    stableCoinVault: stable,
+                    userStableCoinVault: aliceStableCoinWallet,
+                    nftMint: capNftMintPubKey,
+                    nftMetadata: await utils.getMetadataAddress(capNftMintPubKey),
+                    collectionWhitelist: whitelist,
+                    nftVault: nft,
+                    userNftVault: aliceCapNftWallet,
+                    order: order,
+                    borrower: alice.publicKey,
+                    systemProgram: anchor.web3.SystemProgram.programId,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                    rent: anchor.web3.SYSVAR_RENT_PUBKEY
+                },
+                signers: [alice]
+            });
+            assert.fail("order above the collection cap accepted");
+        } catch (err) {
+            assert.strictEqual(err.msg, "Terms exceed the collection caps");
+        }
+        assert.strictEqual(await utils.getTokenBalance(provider, aliceCapNftWallet), 1);
+
+        await program.rpc.removeCollection({
+            accounts: {
+                config: config,
+                collectionWhitelist: whitelist,
+                admin: provider.wallet.publicKey,
+            },
+        });
+        assert.strictEqual(await provider.connection.getAccountInfo(whitelist), null);
+    });
+
+    it("Collection Offer", async () => {
+        const [config, configBump] = await anchor.web3.PublicKey.findProgramAddress(
+            [
+                Buffer.from(CONFIG_PDA_SEED)
+            ], program.programId);
+        const [market, marketBump] = await anchor.web3.PublicKey.findProgramAddress(
+            [
+                stableCoinMintPubKey.toBuffer(),
+                Buffer.from(MARKET_PDA_SEED)
+            ], program.programId);
+        const [stable, stableBump] = await anchor.web3.PublicKey.findProgramAddress(
+            [
+                stableCoinMintPubKey.toBuffer(),
+                Buffer.from(STABLE_COIN_PDA_SEED)
+            ], program.programId);
+        const treasury = await utils.getTreasuryAddress(program.programId, stableCoinMintPubKey);
+        const [collection, _] = await utils.createMetadataNFT(provider, provider.wallet.publicKey);
+        // Loans below are taken without a price feed
+        await setConfig();
+
+        const createCollectionOffer = async (capacity: number) => {
+            const offerId = (await program.account.configuration.fetch(config)).offerId;
+            const [offer, offerBump] = await anchor.web3.PublicKey.findProgramAddress(
+                [
+                    Buffer.from(offerId.toString()),
+                    Buffer.from(COLLECTION_OFFER_PDA_SEED),
+                ], program.programId);
+            await program.rpc.createCollectionOffer(stableBump, offerBump, collection, REQUEST_AMOUNT, INTEREST_RATE, PERIOD, ADDITIONAL_COLLATERAL, new anchor.BN(capacity), {
+                accounts: {
+                    config: config,
+                    market: market,
+                    stableCoinMint: stableCoinMintPubKey,
+                    stableCoinVault: stable,
+                    lenderStableCoinVault: bobStableCoinWallet,
+                    offer: offer,
+                    lender: bob.publicKey,
+                    systemProgram: anchor.web3.SystemProgram.programId,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                    rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+                },
+                signers: [bob],
+            });
+            return [offerId, offer];
+        };
+
+        const acceptCollectionOffer = async (offerId: anchor.BN, offer: anchor.web3.PublicKey, nftMint: anchor.web3.PublicKey, aliceNft: anchor.web3.PublicKey) => {
+            const [nft, nftBump] = await anchor.web3.PublicKey.findProgramAddress(
+                [
+                    nftMint.toBuffer(),
+                    Buffer.from(NFT_PDA_SEED)
+                ], program.programId);
+            const orderId = (await program.account.configuration.fetch(config)).orderId;
+            const [order, orderBump] = await anchor.web3.PublicKey.findProgramAddress(
+                [
+                    Buffer.from(orderId.toString()),
+                    Buffer.from(ORDER_PDA_SEED),
+                ], program.programId);
+            await program.rpc.acceptCollectionOffer(offerId, stableBump, nftBump, orderBump, {
+                accounts: {
+                    config: config,
+                    market: market,
+                    offer: offer,
+                    stableCoinMint: stableCoinMintPubKey,
+                    stableCoinVault: stable,
+                    treasuryVault: treasury,
+                    borrowerStableCoinVault: aliceStableCoinWallet,
+                    nftMint: nftMint,
+                    nftMetadata: await utils.getMetadataAddress(nftMint),
+                    collectionWhitelist: await utils.getWhitelistAddress(program.programId, collection, market),
+                    nftVault: nft,
+                    borrowerNftVault: aliceNft,
+                    order: order,
+                    borrower: alice.publicKey,
+                    priceFeed: anchor.web3.PublicKey.default,
+                    systemProgram: anchor.web3.SystemProgram.programId,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                    rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+                },
+                signers: [alice],
+            });
+            return order;
+        };
+
+        // An offer needs room for at least one loan
+        try {
+            await createCollectionOffer(0);
+            assert.fail("empty offer accepted");
+        } catch (err) {
+            assert.strictEqual(err.msg, "Capacity must be greater than zero");
+        }
+
+        // The principal of every loan is escrowed up front
+        const bobBalance = await utils.getTokenBalance(provider, bobStableCoinWallet);
+        let [offerId, offer] = await createCollectionOffer(2);
+        assert.strictEqual(await utils.getTokenBalance(provider, bobStableCoinWallet), bobBalance - REQUEST_AMOUNT.muln(2).toNumber());
+        let fetchOffer = await program.account.collectionOffer.fetch(offer);
+        assert.strictEqual(fetchOffer.capacity.toString(), "2");
+        assert.strictEqual(fetchOffer.remaining.toString(), "2");
+
+        // Any verified member of the collection takes one of the loans
+        const aliceBalance = await utils.getTokenBalance(provider, aliceStableCoinWallet);
+        const [memberMint, aliceMemberNft] = await utils.createMetadataNFT(provider, alice.publicKey, collection);
+        const order = await acceptCollectionOffer(offerId, offer, memberMint, aliceMemberNft);
+        fetchOffer = await program.account.collectionOffer.fetch(offer);
+        assert.strictEqual(fetchOffer.remaining.toString(), "1");
+        const fetchOrder = await program.account.order.fetch(order);
+        assert.deepStrictEqual(fetchOrder.state, { active: {} });
+        assert.strictEqual(fetchOrder.collection.toString(), collection.toString());
+        assert.strictEqual(fetchOrder.lender.toString(), bob.publicKey.toString());
+        assert.strictEqual(await utils.getTokenBalance(provider, aliceStableCoinWallet), aliceBalance + REQUEST_AMOUNT.toNumber() - ORIGINATION_FEE - ADDITIONAL_COLLATERAL.toNumber());
+
+        // Nfts outside the collection are turned away
+        const [otherMint, aliceOtherNft] = await utils.createMetadataNFT(provider, alice.publicKey);
+        try {
+            await acceptCollectionOffer(offerId, offer, otherMint, aliceOtherNft);
+            assert.fail("nft outside the collection accepted");
+        } catch (err) {
+            assert.strictEqual(err.msg, "Nft is not part of the verified collection");
+        }
+
+        // Cancelling returns the principal of the loan not taken
+        await program.rpc.cancelCollectionOffer(offerId, stableBump, {
+            accounts: {
+                config: config,
+                market: market,
+                offer: offer,
+                stableCoinMint: stableCoinMintPubKey,
+                stableCoinVault: stable,
+                lenderStableCoinVault: bobStableCoinWallet,
+                lender: bob.publicKey,
+                systemProgram: anchor.web3.SystemProgram.programId,
+                tokenProgram: TOKEN_PROGRAM_ID,
+            },
+            signers: [bob],
+        });
+        assert.strictEqual(await utils.getTokenBalance(provider, bobStableCoinWallet), bobBalance - REQUEST_AMOUNT.toNumber());
+        assert.strictEqual(await provider.connection.getAccountInfo(offer), null);
+
+        // An offer with no loans left can't be accepted
+        [offerId, offer] = await createCollectionOffer(1);
+        const [secondMint, aliceSecondNft] = await utils.createMetadataNFT(provider, alice.publicKey, collection);
+        await acceptCollectionOffer(offerId, offer, secondMint, aliceSecondNft);
+        fetchOffer = await program.account.collectionOffer.fetch(offer);
+        assert.strictEqual(fetchOffer.remaining.toString(), "0");
+        try {
+            await acceptCollectionOffer(offerId, offer, otherMint, aliceOtherNft);
+            assert.fail("exhausted offer accepted");
+        } catch (err) {
+            assert.strictEqual(err.msg, "Offer has no loans left");
+        }
+    });
+
+    it("Verified Collection", async () => {
+        const [config, configBump] = await anchor.web3.PublicKey.findProgramAddress(
+            [
+                Buffer.from(CONFIG_PDA_SEED)
+            ], program.programId);
+        const [market, marketBump] = await anchor.web3.PublicKey.findProgramAddress(
+            [
+                stableCoinMintPubKey.toBuffer(),
+                Buffer.from(MARKET_PDA_SEED)
+            ], program.programId);
+        const [stable, stableBump] = await anchor.web3.PublicKey.findProgramAddress(
+            [
+                stableCoinMintPubKey.toBuffer(),
+                Buffer.from(STABLE_COIN_PDA_SEED)
+            ], program.programId);
+        const [collection, _] = await utils.createMetadataNFT(provider, provider.wallet.publicKey);
+        await setConfig({requireVerifiedCollection: true});
+
+        const createOrder = async (nftMint: anchor.web3.PublicKey, aliceNft: anchor.web3.PublicKey, collection: anchor.web3.PublicKey) => {
+            const [nft, nftBump] = await anchor.web3.PublicKey.findProgramAddress(
+                [
+                    nftMint.toBuffer(),
+                    Buffer.from(NFT_PDA_SEED)
+                ], program.programId);
+            const orderId = (await program.account.configuration.fetch(config)).orderId;
+            const [order, orderBump] = await anchor.web3.PublicKey.findProgramAddress(
+                [
+                    Buffer.from(orderId.toString()),
+                    Buffer.from(ORDER_PDA_SEED),
+                ], program.programId);
+            await program.rpc.createOrder(stableBump, nftBump, orderBump, REQUEST_AMOUNT, INTEREST_RATE, PERIOD, ADDITIONAL_COLLATERAL, {
+                accounts: {
+                    config: config,
+                    market: market,
+                    stableCoinMint: stableCoinMintPubKey,
+                    stableCoinVault: stable,
+                    userStableCoinVault: aliceStableCoinWallet,
+                    nftMint: nftMint,
+                    nftMetadata: await utils.getMetadataAddress(nftMint),
+                    collectionWhitelist: await utils.getWhitelistAddress(program.programId, collection, market),
+                    nftVault: nft,
+                    userNftVault: aliceNft,
+                    order: order,
+                    borrower: alice.publicKey,
+                    systemProgram: anchor.web3.SystemProgram.programId,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                    rent: anchor.web3.SYSVAR_RENT_PUBKEY
+                },
+                signers: [alice]
+            });
+            return [orderId, order, nft, nftBump];
+        };
+
+        // Nfts outside a verified collection are turned away
+        const [plainMint, alicePlainNft] = await utils.createMetadataNFT(provider, alice.publicKey);
+        try {
+            await createOrder(plainMint, alicePlainNft, anchor.web3.PublicKey.default);
+            assert.fail("nft without a verified collection accepted");
+        } catch (err) {
+            assert.strictEqual(err.msg, "Nft does not belong to a verified collection");
+        }
+        assert.strictEqual(await utils.getTokenBalance(provider, alicePlainNft), 1);
+
+        // The verified collection is recorded on the order
+        const [memberMint, aliceMemberNft] = await utils.createMetadataNFT(provider, alice.publicKey, collection);
+        const [orderId, order, nft, nftBump] = await createOrder(memberMint, aliceMemberNft, collection);
+        const fetchOrder = await program.account.order.fetch(order);
+        assert.strictEqual(fetchOrder.collection.toString(), collection.toString());
+        assert.strictEqual(await utils.getTokenBalance(provider, nft), 1);
+
+        await program.rpc.cancelOrder(orderId, stableBump, nftBump, {
+            accounts: {
+                config: config,
+                market: market,
+                order: order,
+                stableCoinMint: stableCoinMintPubKey,
+                stableCoinVault: stable,
+                userStableCoinVault: aliceStableCoinWallet,
+                nftMint: memberMint,
+                nftVault: nft,
+                userNftVault: aliceMemberNft,
+                receipt: await utils.getReceiptAddress(program.programId, order),
+                borrower: alice.publicKey,
+                systemProgram: anchor.web3.SystemProgram.programId,
+                tokenProgram: TOKEN_PROGRAM_ID,
+            },
+            signers: [alice]
+        });
+        assert.strictEqual(await utils.getTokenBalance(provider, aliceMemberNft), 1);
+        await setConfig();
+    });
+
+    it("Late Repayment", async () => {
+        const period = new anchor.BN(2);
+        await setConfig({minPeriod: period});
+        const loan = await createLoanOrder(period);
+        await giveLoan(loan);
+        await sleep(4);
+
+        // The period is over but the loan is still in its grace period
+        const fetchOrder = await program.account.order.fetch(loan.order);
+        assert.deepStrictEqual(fetchOrder.state, { active: {} });
+        assert.strictEqual(fetchOrder.gracePeriod.toString(), GRACE_PERIOD.toString());
+        assert.ok(Date.now() / 1000 > fetchOrder.loanStartTime.add(period).toNumber());
+        try {
+            await liquidate(loan);
+            assert.fail("liquidated during the grace period");
+        } catch (err) {
+            assert.strictEqual(err.msg, "Repayment Period has not been exceeded");
+        }
+
+        // Repaying late adds a day of late fee on the principal, paid to the lender
+        const bobBalance = await utils.getTokenBalance(provider, bobStableCoinWallet);
+        await payback(loan);
+        assert.strictEqual(await utils.getTokenBalance(provider, loan.aliceNft), 1);
+        const receipt = await utils.getReceiptAddress(program.programId, loan.order);
+        const fetchReceipt = await program.account.loanReceipt.fetch(receipt);
+        assert.deepStrictEqual(fetchReceipt.state, { repaid: {} });
+        const interest = fetchReceipt.amountRepaid.toNumber() - REQUEST_AMOUNT.toNumber();
+        const protocolFee = Math.floor(interest * PROTOCOL_FEE_RATE.toNumber() / 10_000);
+        const lateFee = REQUEST_AMOUNT.toNumber() * LATE_FEE_RATE.toNumber() / 10_000;
+        assert.strictEqual(await utils.getTokenBalance(provider, bobStableCoinWallet), bobBalance + REQUEST_AMOUNT.toNumber() + interest - protocolFee + lateFee);
+
+        await program.rpc.closeReceipt({
+            accounts: {
+                receipt: receipt,
+                rentPayer: alice.publicKey,
+            },
+            signers: [alice],
+        });
+        await setConfig();
+    });
+
+    it("Order Expiry", async () => {
+        const orderLifetime = new anchor.BN(3);
+        await setConfig({orderLifetime: orderLifetime});
+        const loan = await createLoanOrder(PERIOD);
+        const fetchOrder = await program.account.order.fetch(loan.order);
+        assert.strictEqual(fetchOrder.expiresAt.toString(), fetchOrder.createdAt.add(orderLifetime).toString());
+
+        const receipt = await utils.getReceiptAddress(program.programId, loan.order);
+        const expireOrder = () => program.rpc.expireOrder(loan.orderId, loan.stableBump, loan.nftBump, {
+            accounts: {
+                config: loan.config,
+                market: loan.market,
+                order: loan.order,
+                stableCoinMint: stableCoinMintPubKey,
+                stableCoinVault: loan.stable,
+                userStableCoinVault: aliceStableCoinWallet,
+                nftMint: loan.nftMint,
+                nftVault: loan.nft,
+                userNftVault: loan.aliceNft,
+                borrower: alice.publicKey,
+                payer: provider.wallet.publicKey,
+                receipt: receipt,
+                systemProgram: anchor.web3.SystemProgram.programId,
+                tokenProgram: TOKEN_PROGRAM_ID,
+            },
+        });
+
+        // A listing can't be expired while it may still be funded
+        try {
+            await expireOrder();
+            assert.fail("live order expired");
+        } catch (err) {
+            assert.strictEqual(err.msg, "Order has not expired yet");
+        }
+
+        await sleep(orderLifetime.toNumber() + 2);
+        try {
+            await giveLoan(loan);
+            assert.fail("expired order funded");
+        } catch (err) {
+            assert.strictEqual(err.msg, "Order has expired");
+        }
+
+        // Nor can alice revive it by editing the terms
+        try {
+            await program.rpc.updateOrder(loan.orderId, loan.stableBump, REQUEST_AMOUNT, INTEREST_RATE, PERIOD, ADDITIONAL_COLLATERAL, {
+                accounts: {
+                    config: loan.config,
+                    market: loan.market,
+                    order: loan.order,
+                    stableCoinMint: stableCoinMintPubKey,
+                    stableCoinVault: loan.stable,
+                    userStableCoinVault: aliceStableCoinWallet,
+                    collectionWhitelist: await utils.getWhitelistAddress(program.programId, anchor.web3.PublicKey.default, loan.market),
+                    borrower: alice.publicKey,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                },
+                signers: [alice]
+            });
+            assert.fail("expired order updated");
+        } catch (err) {
+            assert.strictEqual(err.msg, "Order has expired");
+        }
+
+        // Anyone can crank the expired order, the collateral and the rent go back to alice
+        const aliceBalance = await utils.getTokenBalance(provider, aliceStableCoinWallet);
+        const aliceLamports = await provider.connection.getBalance(alice.publicKey);
+        await expireOrder();
+        assert.strictEqual(await utils.getTokenBalance(provider, loan.aliceNft), 1);
+        assert.strictEqual(await utils.getTokenBalance(provider, loan.nft), 0);
+        assert.strictEqual(await utils.getTokenBalance(provider, aliceStableCoinWallet), aliceBalance + ADDITIONAL_COLLATERAL.toNumber());
+        assert.ok(await provider.connection.getBalance(alice.publicKey) > aliceLamports);
+        assert.strictEqual(await provider.connection.getAccountInfo(loan.order), null);
+
+        const fetchReceipt = await program.account.loanReceipt.fetch(receipt);
+        assert.deepStrictEqual(fetchReceipt.state, { expired: {} });
+        await program.rpc.closeReceipt({
+            accounts: {
+                receipt: receipt,
+                rentPayer: provider.wallet.publicKey,
+            },
+        });
+        await setConfig();
+    });
+
+    it("Dutch Auction", async () => {
+        const period = new anchor.BN(2);
+        const gracePeriod = new anchor.BN(2);
+        const auctionDuration = new anchor.BN(8);
+        await setConfig({minPeriod: period, gracePeriod: gracePeriod, auctionDuration: auctionDuration});
+
+        const startAuction = (loan) => program.rpc.startAuction(loan.orderId, {
+            accounts: {
+                config: loan.config,
+                order: loan.order,
+                lender: bob.publicKey,
+            },
+            signers: [bob],
+        });
+
+        // A defaulted loan is sold to the provider wallet while the price decays
+        const sold = await createLoanOrder(period);
+        await giveLoan(sold);
+        await sleep(period.add(gracePeriod).toNumber() + 2);
+        await startAuction(sold);
+        let fetchOrder = await program.account.order.fetch(sold.order);
+        assert.deepStrictEqual(fetchOrder.state, { auction: {} });
+        assert.strictEqual(fetchOrder.auctionEndTime.toString(), fetchOrder.auctionStartTime.add(auctionDuration).toString());
+        const startPrice = fetchOrder.auctionStartPrice.toNumber();
+
+        // @ts-ignore
+        const walletNft = await new Token(provider.connection, sold.nftMint, TOKEN_PROGRAM_ID, provider.wallet.payer).createAssociatedTokenAccount(provider.wallet.publicKey);
+        const receipt = await utils.getReceiptAddress(program.programId, sold.order);
+        const buyAuction = async (loan, buyerNft: anchor.web3.PublicKey, maxPrice: anchor.BN) => program.rpc.buyAuction(loan.orderId, loan.stableBump, loan.nftBump, maxPrice, {
+            accounts: {
+                config: loan.config,
+                market: loan.market,
+                order: loan.order,
+                stableCoinMint: stableCoinMintPubKey,
+                stableCoinVault: loan.stable,
+                treasuryVault: await utils.getTreasuryAddress(program.programId, stableCoinMintPubKey),
+                lenderStableCoinVault: bobStableCoinWallet,
+                borrowerStableCoinVault: aliceStableCoinWallet,
+                buyerStableCoinVault: walletStableCoinWallet,
+                nftMint: loan.nftMint,
+                nftVault: loan.nft,
+                buyerNftVault: buyerNft,
+                borrower: alice.publicKey,
+                buyer: provider.wallet.publicKey,
+                receipt: await utils.getReceiptAddress(program.programId, loan.order),
+                systemProgram: anchor.web3.SystemProgram.programId,
+                tokenProgram: TOKEN_PROGRAM_ID,
+            },
+        });
+        try {
+            await buyAuction(sold, walletNft, REQUEST_AMOUNT);
+            assert.fail("bought below the auction price");
+        } catch (err) {
+            assert.strictEqual(err.msg, "Auction price is above the maximum price");
+        }
+
+        await sleep(2);
+        const aliceBalance = await utils.getTokenBalance(provider, aliceStableCoinWallet);
+        const bobBalance = await utils.getTokenBalance(provider, bobStableCoinWallet);
+        const walletBalance = await utils.getTokenBalance(provider, walletStableCoinWallet);
+        await buyAuction(sold, walletNft, new anchor.BN(startPrice));
+        assert.strictEqual(await utils.getTokenBalance(provider, walletNft), 1);
+
+        // The price has decayed, the lender is paid the debt and the borrower the surplus and collateral
+        const price = walletBalance - await utils.getTokenBalance(provider, walletStableCoinWallet);
+        const fetchReceipt = await program.account.loanReceipt.fetch(receipt);
+        assert.deepStrictEqual(fetchReceipt.state, { liquidated: {} });
+        const interest = fetchReceipt.amountRepaid.toNumber() - REQUEST_AMOUNT.toNumber();
+        const debt = fetchReceipt.amountRepaid.toNumber() + fetchReceipt.lateFee.toNumber();
+        const protocolFee = Math.floor(interest * PROTOCOL_FEE_RATE.toNumber() / 10_000);
+        assert.ok(price < startPrice);
+        assert.ok(price > debt);
+        assert.strictEqual(await utils.getTokenBalance(provider, bobStableCoinWallet), bobBalance + debt - protocolFee);
+        assert.strictEqual(await utils.getTokenBalance(provider, aliceStableCoinWallet), aliceBalance + price - debt + ADDITIONAL_COLLATERAL.toNumber());
+        await program.rpc.closeReceipt({
+            accounts: {
+                receipt: receipt,
+                rentPayer: provider.wallet.publicKey,
+            },
+        });
+
+        // Nobody buys the second loan, the lender falls back to liquidate once the auction ends
+        const unsold = await createLoanOrder(period);
+        await giveLoan(unsold);
+        await sleep(period.add(gracePeriod).toNumber() + 2);
+        await startAuction(unsold);
+        try {
+            await liquidate(unsold);
+            assert.fail("liquidated during the auction");
+        } catch (err) {
+            assert.strictEqual(err.msg, "Auction is still running");
+        }
+
+        await sleep(auctionDuration.toNumber() + 2);
+        // @ts-ignore
+        const walletUnsoldNft = await new Token(provider.connection, unsold.nftMint, TOKEN_PROGRAM_ID, provider.wallet.payer).createAssociatedTokenAccount(provider.wallet.publicKey);
+        try {
+            await buyAuction(unsold, walletUnsoldNft, REQUEST_AMOUNT.muln(2));
+            assert.fail("bought after the auction ended");
+        } catch (err) {
+            assert.strictEqual(err.msg, "Auction has ended");
+        }
+        const bobAfterAuction = await utils.getTokenBalance(provider, bobStableCoinWallet);
+        await liquidate(unsold);
+        assert.strictEqual(await utils.getTokenBalance(provider, unsold.bobNft), 1);
+        assert.strictEqual(await utils.getTokenBalance(provider, bobStableCoinWallet), bobAfterAuction + ADDITIONAL_COLLATERAL.toNumber());
+        const unsoldReceipt = await program.account.loanReceipt.fetch(await utils.getReceiptAddress(program.programId, unsold.order));
+        assert.deepStrictEqual(unsoldReceipt.state, { liquidated: {} });
+        await setConfig();
+    });
+
+    it("English Auction", async () => {
+        const period = new anchor.BN(2);
+        const gracePeriod = new anchor.BN(2);
+        const auctionDuration = new anchor.BN(8);
+        const antiSnipePeriod = new anchor.BN(12);
+        await setConfig({minPeriod: period, gracePeriod: gracePeriod, auctionDuration: auctionDuration, antiSnipePeriod: antiSnipePeriod});
+
+        // Carol bids against the provider wallet
+        const carol = anchor.web3.Keypair.generate();
+        await provider.connection.confirmTransaction(
+            await provider.connection.requestAirdrop(
+                carol.publicKey,
+                1_000_000_000
+            ),
+            "confirmed"
+        );
+        const carolStableCoinWallet = await stableCoinMintObject.createAssociatedTokenAccount(carol.publicKey);
+        await utils.mintToAccount(provider, stableCoinMintPubKey, carolStableCoinWallet, 1000_000_000);
+
+        const treasury = await utils.getTreasuryAddress(program.programId, stableCoinMintPubKey);
+        const startAuction = async (loan) => {
+            const [escrow, escrowBump] = await anchor.web3.PublicKey.findProgramAddress(
+                [
+                    loan.order.toBuffer(),
+                    Buffer.from(BID_ESCROW_PDA_SEED),
+                ], program.programId);
+            await program.rpc.startEnglishAuction(loan.orderId, {
+                accounts: {
+                    config: loan.config,
+                    market: loan.market,
+                    order: loan.order,
+                    stableCoinMint: stableCoinMintPubKey,
+                    bidEscrow: escrow,
+                    lender: bob.publicKey,
+                    systemProgram: anchor.web3.SystemProgram.programId,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                    rent: anchor.web3.SYSVAR_RENT_PUBKEY
+                },
+                signers: [bob],
+            });
+            return [escrow, escrowBump];
+        };
+        const settleAuction = (loan, escrow, escrowBump, winnerNft) => program.rpc.settleAuction(loan.orderId, loan.stableBump, loan.nftBump, escrowBump, {
+            accounts: {
+                config: loan.config,
+                market: loan.market,
+                order: loan.order,
+                stableCoinMint: stableCoinMintPubKey,
+                stableCoinVault: loan.stable,
+                bidEscrow: escrow,
+                treasuryVault: treasury,
+                lenderStableCoinVault: bobStableCoinWallet,
+                borrowerStableCoinVault: aliceStableCoinWallet,
+                nftMint: loan.nftMint,
+                nftVault: loan.nft,
+                winnerNftVault: winnerNft,
+                borrower: alice.publicKey,
+                lender: bob.publicKey,
+                payer: provider.wallet.publicKey,
+                receipt: await utils.getReceiptAddress(program.programId, loan.order),
+                systemProgram: anchor.web3.SystemProgram.programId,
+                tokenProgram: TOKEN_PROGRAM_ID,
+            },
+        });
+        const waitForEnd = async (loan) => {
+            const endTime = (await program.account.order.fetch(loan.order)).auctionEndTime.toNumber();
+            await sleep(Math.max(endTime - Date.now() / 1000, 0) + 2);
+        };
+
+        const sold = await createLoanOrder(period);
+        await giveLoan(sold);
+        await sleep(period.add(gracePeriod).toNumber() + 2);
+        const [escrow, escrowBump] = await startAuction(sold);
+        let fetchOrder = await program.account.order.fetch(sold.order);
+        assert.deepStrictEqual(fetchOrder.state, { auction: {} });
+        const endTime = fetchOrder.auctionEndTime.toNumber();
+
+        // The first bid has to cover principal, interest and the late fee at the start of the auction
+        const elapsed = fetchOrder.auctionStartTime.sub(fetchOrder.loanStartTime).toNumber();
+        const accrued = Math.floor(REQUEST_AMOUNT.toNumber() * INTEREST_RATE.toNumber() * elapsed / (10_000 * SECONDS_PER_YEAR));
+        const lateFee = REQUEST_AMOUNT.toNumber() * LATE_FEE_RATE.toNumber() / 10_000;
+        const interest = Math.max(accrued, fetchOrder.minInterest.toNumber());
+        const debt = REQUEST_AMOUNT.toNumber() + interest + lateFee;
+
+        const placeBid = (bidder: anchor.web3.Keypair, bidderVault: anchor.web3.PublicKey, previousVault: anchor.web3.PublicKey, amount: number) => program.rpc.placeBid(sold.orderId, escrowBump, new anchor.BN(amount), {
+            accounts: {
+                order: sold.order,
+                bidEscrow: escrow,
+                bidderStableCoinVault: bidderVault,
+                previousBidderStableCoinVault: previousVault,
+                bidder: bidder.publicKey,
+                tokenProgram: TOKEN_PROGRAM_ID,
+            },
+            signers: [bidder],
+        });
+        // @ts-ignore
+        const walletBid = (previousVault: anchor.web3.PublicKey, amount: number) => placeBid(provider.wallet.payer, walletStableCoinWallet, previousVault, amount);
+        const carolBid = (previousVault: anchor.web3.PublicKey, amount: number) => placeBid(carol, carolStableCoinWallet, previousVault, amount);
+
+        try {
+            await walletBid(walletStableCoinWallet, debt - 1);
+            assert.fail("bid below the debt accepted");
+        } catch (err) {
+            assert.strictEqual(err.msg, "Bid is below the minimum bid");
+        }
+        const walletBalance = await utils.getTokenBalance(provider, walletStableCoinWallet);
+        await walletBid(walletStableCoinWallet, debt);
+        assert.strictEqual(await utils.getTokenBalance(provider, escrow), debt);
+
+        // Outbidding takes the minimum increment and a refund to the highest bidder
+        const minBid = debt + Math.floor(debt * BID_INCREMENT_RATE.toNumber() / 10_000);
+        try {
+            await carolBid(walletStableCoinWallet, minBid - 1);
+            assert.fail("bid below the increment accepted");
+        } catch (err) {
+            assert.strictEqual(err.msg, "Bid is below the minimum bid");
+        }
+        try {
+            await carolBid(carolStableCoinWallet, minBid);
+            assert.fail("refund to the wrong bidder accepted");
+        } catch (err) {
+            assert.strictEqual(err.msg, "Refund account does not belong to the highest bidder");
+        }
+        const price = minBid + 1_000_000;
+        await carolBid(walletStableCoinWallet, price);
+        assert.strictEqual(await utils.getTokenBalance(provider, walletStableCoinWallet), walletBalance);
+        assert.strictEqual(await utils.getTokenBalance(provider, escrow), price);
+        fetchOrder = await program.account.order.fetch(sold.order);
+        assert.strictEqual(fetchOrder.highestBidder.toString(), carol.publicKey.toString());
+        assert.strictEqual(fetchOrder.highestBid.toNumber(), price);
+        // The bid came in the anti-snipe period and pushed the end back
+        assert.ok(fetchOrder.auctionEndTime.toNumber() > endTime);
+
+        // @ts-ignore
+        const carolNft = await new Token(provider.connection, sold.nftMint, TOKEN_PROGRAM_ID, provider.wallet.payer).createAssociatedTokenAccount(carol.publicKey);
+        try {
+            await settleAuction(sold, escrow, escrowBump, carolNft);
+            assert.fail("settled a running auction");
+        } catch (err) {
+            assert.strictEqual(err.msg, "Auction is still running");
+        }
+
+        // Tokens sent straight to the escrow don't keep it from closing, they go to the borrower
+        const stray = 1_000;
+        // @ts-ignore
+        await stableCoinMintObject.transfer(walletStableCoinWallet, escrow, provider.wallet.payer, [], stray);
+
+        await waitForEnd(sold);
+        const aliceBalance = await utils.getTokenBalance(provider, aliceStableCoinWallet);
+        const bobBalance = await utils.getTokenBalance(provider, bobStableCoinWallet);
+        const treasuryBalance = await utils.getTokenBalance(provider, treasury);
+        await settleAuction(sold, escrow, escrowBump, carolNft);
+
+        // The lender is paid the debt less the protocol fee, the borrower the surplus and the collateral
+        const protocolFee = Math.floor(interest * PROTOCOL_FEE_RATE.toNumber() / 10_000);
+        assert.strictEqual(await utils.getTokenBalance(provider, carolNft), 1);
+        assert.strictEqual(await utils.getTokenBalance(provider, bobStableCoinWallet), bobBalance + debt - protocolFee);
+        assert.strictEqual(await utils.getTokenBalance(provider, treasury), treasuryBalance + protocolFee);
+        assert.strictEqual(await utils.getTokenBalance(provider, aliceStableCoinWallet), aliceBalance + price - debt + stray + ADDITIONAL_COLLATERAL.toNumber());
+        assert.strictEqual(await provider.connection.getAccountInfo(escrow), null);
+
+        // Without bids the lender takes the nft and the collateral
+        const unsold = await createLoanOrder(period);
+        await giveLoan(unsold);
+        await sleep(period.add(gracePeriod).toNumber() + 2);
+        const [unsoldEscrow, unsoldEscrowBump] = await startAuction(unsold);
+        await waitForEnd(unsold);
+        const bobAfterAuction = await utils.getTokenBalance(provider, bobStableCoinWallet);
+        await settleAuction(unsold, unsoldEscrow, unsoldEscrowBump, unsold.bobNft);
+        assert.strictEqual(await utils.getTokenBalance(provider, unsold.bobNft), 1);
+        assert.strictEqual(await utils.getTokenBalance(provider, bobStableCoinWallet), bobAfterAuction + ADDITIONAL_COLLATERAL.toNumber());
+        assert.strictEqual(await provider.connection.getAccountInfo(unsoldEscrow), null);
+        const fetchReceipt = await program.account.loanReceipt.fetch(await utils.getReceiptAddress(program.programId, unsold.order));
+        assert.deepStrictEqual(fetchReceipt.state, { liquidated: {} });
+        await setConfig();
+    });
+
+    it("Redemption", async () => {
+        const period = new anchor.BN(2);
+        const gracePeriod = new anchor.BN(2);
+        const redemptionPeriod = new anchor.BN(10);
+        const penaltyRate = new anchor.BN(500);
+        await setConfig({minPeriod: period, gracePeriod: gracePeriod});
+
+        const openLoan = async () => {
+            const loan = await createLoanOrder(period);
+            await program.rpc.setRedemptionWindow(loan.orderId, redemptionPeriod, penaltyRate, {
+                accounts: {
+                    order: loan.order,
+                    borrower: alice.publicKey,
+                },
+                signers: [alice]
+            });
+            await giveLoan(loan, 1);
+            return loan;
+        };
+        const redeem = async (loan) => program.rpc.redeem(loan.orderId, loan.stableBump, loan.nftBump, {
+            accounts: {
+                config: loan.config,
+                market: loan.market,
+                order: loan.order,
+                stableCoinMint: stableCoinMintPubKey,
+                stableCoinVault: loan.stable,
+                treasuryVault: await utils.getTreasuryAddress(program.programId, stableCoinMintPubKey),
+                lenderStableCoinVault: bobStableCoinWallet,
+                borrowerStableCoinVault: aliceStableCoinWallet,
+                nftMint: loan.nftMint,
+                nftVault: loan.nft,
+                borrowerNftVault: loan.aliceNft,
+                borrower: alice.publicKey,
+                receipt: await utils.getReceiptAddress(program.programId, loan.order),
+                systemProgram: anchor.web3.SystemProgram.programId,
+                tokenProgram: TOKEN_PROGRAM_ID,
+            },
+            signers: [alice],
+        });
+
+        // Running loans are repaid with payback, not redeemed
+        const redeemed = await openLoan();
+        try {
+            await redeem(redeemed);
+            assert.fail("redeemed a running loan");
+        } catch (err) {
+            assert.strictEqual(err.msg, "Repayment Period has not been exceeded");
+        }
+
+        // After the grace period the lender has to wait for the window to close
+        await sleep(period.add(gracePeriod).toNumber() + 2);
+        try {
+            await liquidate(redeemed);
+            assert.fail("liquidated during the redemption window");
+        } catch (err) {
+            assert.strictEqual(err.msg, "Redemption window is still open");
+        }
+
+        // The borrower buys the nft back with the debt, the late fee and the penalty
+        const bobBalance = await utils.getTokenBalance(provider, bobStableCoinWallet);
+        const aliceBalance = await utils.getTokenBalance(provider, aliceStableCoinWallet);
+        await redeem(redeemed);
+        assert.strictEqual(await utils.getTokenBalance(provider, redeemed.aliceNft), 1);
+        const receipt = await utils.getReceiptAddress(program.programId, redeemed.order);
+        const fetchReceipt = await program.account.loanReceipt.fetch(receipt);
+        assert.deepStrictEqual(fetchReceipt.state, { repaid: {} });
+        const debt = fetchReceipt.amountRepaid.toNumber();
+        const interest = debt - REQUEST_AMOUNT.toNumber();
+        const lateFee = fetchReceipt.lateFee.toNumber();
+        const protocolFee = Math.floor(interest * PROTOCOL_FEE_RATE.toNumber() / 10_000);
+        const penalty = Math.floor(debt * penaltyRate.toNumber() / 10_000);
+        assert.ok(lateFee > 0);
+        assert.strictEqual(await utils.getTokenBalance(provider, bobStableCoinWallet), bobBalance + debt - protocolFee + lateFee + penalty);
+        assert.strictEqual(await utils.getTokenBalance(provider, aliceStableCoinWallet), aliceBalance - debt - lateFee - penalty + ADDITIONAL_COLLATERAL.toNumber());
+        await program.rpc.closeReceipt({
+            accounts: {
+                receipt: receipt,
+                rentPayer: alice.publicKey,
+            },
+            signers: [alice],
+        });
+
+        // Once the window closes the nft can't be redeemed and the lender liquidates
+        const claimed = await openLoan();
+        await sleep(period.add(gracePeriod).add(redemptionPeriod).toNumber() + 2);
+        try {
+            await redeem(claimed);
+            assert.fail("redeemed after the window");
+        } catch (err) {
+            assert.strictEqual(err.msg, "Redemption window is closed");
+        }
+        await liquidate(claimed);
+        assert.strictEqual(await utils.getTokenBalance(provider, claimed.bobNft), 1);
+        await setConfig();
+    });
 });
@@ -2,6 +2,11 @@ import * as anchor from '@project-serum/anchor';
 import {Provider} from "@project-serum/anchor";
 
 const {TOKEN_PROGRAM_ID, Token, MintLayout} = require("@solana/spl-token");
+const {
+    createCreateMetadataAccountV2Instruction,
+    createCreateMasterEditionV3Instruction,
+    createVerifyCollectionInstruction,
+} = require("@metaplex-foundation/mpl-token-metadata");
 
 async function createRandomMint(provider, decimals) {
     const mint = await Token.createMint(
@@ -115,6 +120,140 @@ async function createNFT(provider, payer, receiver){
     return [nftAccount, nftMintAddress];
 }
 
+const METADATA_PROGRAM_ID = new anchor.web3.PublicKey("metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s");
+
+async function getMetadataAddress(mint: anchor.web3.PublicKey) {
+    const [metadata, _] = await anchor.web3.PublicKey.findProgramAddress(
+        [
+            Buffer.from("metadata"),
+            METADATA_PROGRAM_ID.toBuffer(),
+            mint.toBuffer(),
+        ], METADATA_PROGRAM_ID);
+    return metadata;
+}
+
+async function getEditionAddress(mint: anchor.web3.PublicKey) {
+    const [edition, _] = await anchor.web3.PublicKey.findProgramAddress(
+        [
+            Buffer.from("metadata"),
+            METADATA_PROGRAM_ID.toBuffer(),
+            mint.toBuffer(),
+            Buffer.from("edition"),
+        ], METADATA_PROGRAM_ID);
+    return edition;
+}
+
+// Mint a master edition nft with metaplex metadata to `owner`. When a collection mint is given the nft
+// is verified as a member of it, the provider wallet being the collection's update authority.
+async function createMetadataNFT(provider: Provider, owner: anchor.web3.PublicKey, collection: anchor.web3.PublicKey = null) {
+    const mint = await createRandomMint(provider, 0);
+    const account = await mint.createAssociatedTokenAccount(owner);
+    await mintToAccount(provider, mint.publicKey, account, 1);
+
+    const authority = provider.wallet.publicKey;
+    const metadata = await getMetadataAddress(mint.publicKey);
+    const tx = new anchor.web3.Transaction();
+    tx.add(
+        createCreateMetadataAccountV2Instruction(
+            {
+                metadata: metadata,
+                mint: mint.publicKey,
+                mintAuthority: authority,
+                payer: authority,
+                updateAuthority: authority,
+            },
+            {
+                createMetadataAccountArgsV2: {
+                    data: {
+                        name: "Collateral",
+                        symbol: "NFT",
+                        uri: "",
+                        sellerFeeBasisPoints: 0,
+                        creators: null,
+                        collection: collection ? {verified: false, key: collection} : null,
+                        uses: null,
+                    },
+                    isMutable: true,
+                },
+            }
+        ),
+        createCreateMasterEditionV3Instruction(
+            {
+                edition: await getEditionAddress(mint.publicKey),
+                mint: mint.publicKey,
+                updateAuthority: authority,
+                mintAuthority: authority,
+                payer: authority,
+                metadata: metadata,
+            },
+            {
+                createMasterEditionArgs: {maxSupply: 0},
+            }
+        )
+    );
+    if (collection) {
+        tx.add(
+            createVerifyCollectionInstruction({
+                metadata: metadata,
+                collectionAuthority: authority,
+                payer: authority,
+                collectionMint: collection,
+                collection: await getMetadataAddress(collection),
+                collectionMasterEditionAccount: await getEditionAddress(collection),
+            })
+        );
+    }
+    await provider.send(tx);
+    return [mint.publicKey, account];
+}
+
+async function getWhitelistAddress(programId: anchor.web3.PublicKey, collection: anchor.web3.PublicKey, market: anchor.web3.PublicKey) {
+    const [whitelist, _] = await anchor.web3.PublicKey.findProgramAddress(
+        [
+            collection.toBuffer(),
+            market.toBuffer(),
+            Buffer.from("whitelist"),
+        ], programId);
+    return whitelist;
+}
+
+async function getTreasuryAddress(programId: anchor.web3.PublicKey, mint: anchor.web3.PublicKey) {
+    const [treasury, _] = await anchor.web3.PublicKey.findProgramAddress(
+        [
+            mint.toBuffer(),
+            Buffer.from("treasury"),
+        ], programId);
+    return treasury;
+}
+
+async function getReceiptAddress(programId: anchor.web3.PublicKey, order: anchor.web3.PublicKey) {
+    const [receipt, _] = await anchor.web3.PublicKey.findProgramAddress(
+        [
+            order.toBuffer(),
+            Buffer.from("receipt"),
+        ], programId);
+    return receipt;
+}
+
+// Raw floor price feed account owned by the oracle program, filled in with setPrice
+async function createPriceFeed(provider: Provider, oracleProgramId: anchor.web3.PublicKey) {
+    const feed = anchor.web3.Keypair.generate();
+    // magic, version, collection, price, conf, expo, publish time, authority
+    const space = 4 + 4 + 32 + 8 + 8 + 4 + 8 + 32;
+    const tx = new anchor.web3.Transaction();
+    tx.add(
+        anchor.web3.SystemProgram.createAccount({
+            fromPubkey: provider.wallet.publicKey,
+            newAccountPubkey: feed.publicKey,
+            space,
+            lamports: await provider.connection.getMinimumBalanceForRentExemption(space),
+            programId: oracleProgramId,
+        })
+    );
+    await provider.send(tx, [feed]);
+    return feed.publicKey;
+}
+
 async function getTokenBalance(provider: Provider, pubKey: anchor.web3.PublicKey) {
     return parseInt((await provider.connection.getTokenAccountBalance(pubKey)).value.amount);
 }
@@ -125,5 +264,11 @@ module.exports = {
     mintToAccount,
     sendLamports,
     getTokenBalance,
+    getMetadataAddress,
+    getWhitelistAddress,
+    getTreasuryAddress,
+    getReceiptAddress,
+    createPriceFeed,
     createNFT,
+    createMetadataNFT,
 };
\ No newline at end of file